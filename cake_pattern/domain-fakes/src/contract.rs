@@ -0,0 +1,398 @@
+//! The behavior every implementation of the repository traits must share,
+//! as checks to run against each of them.
+//!
+//! Each check takes a repository holding no animals with the IDs 1 to 4 it
+//! writes, but an animal of each kind with every ID of [`PREDATORS`], for
+//! those to be eaten by, each eaten by the next in the food chain with the
+//! same ID; [`repository_contract!`](crate::repository_contract) makes a test
+//! of each check against a fresh repository.
+
+use std::{future::Future, ops::RangeInclusive, pin::Pin, sync::Barrier, thread};
+
+use domain::{
+    EntityID, Error, Frog, FrogID, FrogRepository, Result, Slug, SlugID, SlugRepository, Snake,
+    SnakeID, SnakeRepository,
+};
+use tokio::{runtime::Handle, task};
+
+/// The IDs of the animals the ones the checks write are eaten by, which a
+/// repository that refuses animals eaten by missing ones must hold.
+pub const PREDATORS: RangeInclusive<u64> = 11..=14;
+
+/// The checks, by module, that [`repository_contract!`](crate::repository_contract)
+/// makes tests of.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __contract_checks {
+    (immediate, $callback:ident!($($args:tt)*)) => {
+        $crate::__contract_checks!(
+            deferred,
+            $callback!($($args)*),
+            insert_missing_predator,
+            update_missing_predator,
+            delete_eaten
+        );
+    };
+    (deferred, $callback:ident!($($args:tt)*) $(, $extra:ident)*) => {
+        $crate::$callback!(
+            $($args)*
+            [
+                get_missing,
+                insert,
+                insert_taken,
+                update,
+                update_missing,
+                upsert,
+                delete,
+                delete_missing,
+                concurrent_inserts,
+                concurrent_writes
+                $(, $extra)*
+            ]
+        );
+    };
+}
+
+/// Makes a module of tests, one per check of [`contract`](crate::contract),
+/// for each repository given. The `setup` statements run at the start of
+/// every test, so each gets fresh repositories, which may borrow what the
+/// statements bind.
+///
+/// The checks that writes breaking foreign keys fail are left out for
+/// repositories given after `foreign_keys: deferred,`, which check them only
+/// on commit.
+///
+/// ```ignore
+/// domain_fakes::repository_contract! {
+///     setup {
+///         let repository = FakeRepositoryProvider::new();
+///     }
+///     snake: &repository.snake_repository(),
+///     slug: &repository.slug_repository(),
+///     frog: &repository.frog_repository(),
+/// }
+/// ```
+#[macro_export]
+macro_rules! repository_contract {
+    (
+        setup { $($setup:tt)* }
+        snake: $snake:expr,
+        slug: $slug:expr,
+        frog: $frog:expr $(,)?
+    ) => {
+        $crate::repository_contract! {
+            setup { $($setup)* }
+            foreign_keys: immediate,
+            snake: $snake,
+            slug: $slug,
+            frog: $frog,
+        }
+    };
+    (
+        setup { $($setup:tt)* }
+        foreign_keys: $foreign_keys:ident,
+        snake: $snake:expr,
+        slug: $slug:expr,
+        frog: $frog:expr $(,)?
+    ) => {
+        $crate::__contract_checks!(
+            $foreign_keys,
+            __contract_tests!(snake, { $($setup)* }, $snake,)
+        );
+        $crate::__contract_checks!(
+            $foreign_keys,
+            __contract_tests!(slug, { $($setup)* }, $slug,)
+        );
+        $crate::__contract_checks!(
+            $foreign_keys,
+            __contract_tests!(frog, { $($setup)* }, $frog,)
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __contract_tests {
+    ($module:ident, $setup:tt, $repository:expr, [$($check:ident),*]) => {
+        mod $module {
+            use super::*;
+
+            $(
+                #[tokio::test(flavor = "multi_thread")]
+                async fn $check() {
+                    $crate::__contract_test!($setup, $crate::contract::$module::$check, $repository);
+                }
+            )*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __contract_test {
+    ({ $($setup:tt)* }, $check:path, $repository:expr) => {
+        $($setup)*
+        $check($repository).await;
+    };
+}
+
+/// Fails unless `err` is, at its root, [`Error::NotFound`] of `id`.
+fn assert_not_found(err: Error, id: impl Into<EntityID>) {
+    let id = id.into();
+    assert!(
+        matches!(err.root(), Error::NotFound(found) if *found == id),
+        "expected {id} not found, got {err}"
+    );
+}
+
+/// Fails unless `err` is, at its root, [`Error::Conflict`] on `id`.
+fn assert_conflict(err: Error, id: impl Into<EntityID>) {
+    let id = id.into();
+    assert!(
+        matches!(err.root(), Error::Conflict(found) if *found == id),
+        "expected {id} to conflict, got {err}"
+    );
+}
+
+/// Fails unless `err` is, at its root, [`Error::Invalid`].
+fn assert_invalid(err: Error) {
+    assert!(
+        matches!(err.root(), Error::Invalid(_)),
+        "expected invalid input, got {err}"
+    );
+}
+
+/// A call to a repository, as its methods return it.
+type Call<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Runs each call on a thread of its own, all released at once, and returns
+/// their results in order, so the calls overlap as they would on the workers
+/// of a multi-threaded runtime, which the calling one must be.
+fn at_once<const N: usize>(calls: [Call<'_>; N]) -> [Result<()>; N] {
+    let runtime = Handle::current();
+    let start = Barrier::new(N);
+    task::block_in_place(|| {
+        thread::scope(|scope| {
+            let threads = calls.map(|call| {
+                scope.spawn(|| {
+                    start.wait();
+                    runtime.block_on(call)
+                })
+            });
+            threads.map(|thread| thread.join().unwrap())
+        })
+    })
+}
+
+macro_rules! checks {
+    (
+        $module:ident,
+        $Repository:ident,
+        $Animal:ident($ID:ident, eaten_by: $Predator:ident),
+        $get:ident,
+        $insert:ident,
+        $update:ident,
+        $upsert:ident,
+        $delete:ident
+    ) => {
+        /// The contract of
+        #[doc = concat!("[`", stringify!($Repository), "`].")]
+        pub mod $module {
+            use super::*;
+
+            fn animal(id: u64, eaten_by: u64) -> $Animal {
+                $Animal {
+                    id: $ID::new(id).unwrap(),
+                    eaten_by: $Predator::new(eaten_by).unwrap(),
+                }
+            }
+
+            pub async fn get_missing<R: $Repository>(repository: &R) {
+                let id = animal(1, 11).id;
+                assert_not_found(repository.$get(id).await.unwrap_err(), id);
+            }
+
+            pub async fn insert<R: $Repository>(repository: &R) {
+                let animal = animal(1, 12);
+                repository.$insert(animal.clone()).await.unwrap();
+                assert_eq!(repository.$get(animal.id).await.unwrap(), animal);
+                // Only the animal inserted is there.
+                let other = $ID::new(2).unwrap();
+                assert_not_found(repository.$get(other).await.unwrap_err(), other);
+            }
+
+            /// Inserting over a stored animal fails and leaves it as it was.
+            pub async fn insert_taken<R: $Repository>(repository: &R) {
+                let stored = animal(1, 12);
+                repository.$insert(stored.clone()).await.unwrap();
+                let err = repository.$insert(animal(1, 13)).await.unwrap_err();
+                assert_conflict(err, stored.id);
+                assert_eq!(repository.$get(stored.id).await.unwrap(), stored);
+            }
+
+            pub async fn update<R: $Repository>(repository: &R) {
+                repository.$insert(animal(1, 12)).await.unwrap();
+                let updated = animal(1, 13);
+                repository.$update(updated.clone()).await.unwrap();
+                assert_eq!(repository.$get(updated.id).await.unwrap(), updated);
+            }
+
+            /// Updating fails rather than inserting.
+            pub async fn update_missing<R: $Repository>(repository: &R) {
+                let animal = animal(1, 12);
+                let err = repository.$update(animal.clone()).await.unwrap_err();
+                assert_not_found(err, animal.id);
+                assert_not_found(repository.$get(animal.id).await.unwrap_err(), animal.id);
+            }
+
+            /// Upserting inserts and then updates.
+            pub async fn upsert<R: $Repository>(repository: &R) {
+                let inserted = animal(1, 12);
+                repository.$upsert(inserted.clone()).await.unwrap();
+                assert_eq!(repository.$get(inserted.id).await.unwrap(), inserted);
+                let updated = animal(1, 13);
+                repository.$upsert(updated.clone()).await.unwrap();
+                assert_eq!(repository.$get(updated.id).await.unwrap(), updated);
+            }
+
+            /// Deleting removes the animal, and only it, freeing its ID.
+            pub async fn delete<R: $Repository>(repository: &R) {
+                let deleted = animal(1, 12);
+                let kept = animal(2, 12);
+                repository.$insert(deleted.clone()).await.unwrap();
+                repository.$insert(kept.clone()).await.unwrap();
+                repository.$delete(deleted.id).await.unwrap();
+                assert_not_found(repository.$get(deleted.id).await.unwrap_err(), deleted.id);
+                assert_eq!(repository.$get(kept.id).await.unwrap(), kept);
+                // The ID is free again.
+                repository.$insert(deleted.clone()).await.unwrap();
+                assert_eq!(repository.$get(deleted.id).await.unwrap(), deleted);
+            }
+
+            pub async fn delete_missing<R: $Repository>(repository: &R) {
+                let id = animal(1, 12).id;
+                assert_not_found(repository.$delete(id).await.unwrap_err(), id);
+            }
+
+            /// Inserting an animal eaten by a missing one fails and stores
+            /// nothing.
+            pub async fn insert_missing_predator<R: $Repository>(repository: &R) {
+                let animal = animal(1, 5);
+                assert_invalid(repository.$insert(animal.clone()).await.unwrap_err());
+                assert_not_found(repository.$get(animal.id).await.unwrap_err(), animal.id);
+            }
+
+            /// Updating an animal to be eaten by a missing one fails and
+            /// leaves it as it was.
+            pub async fn update_missing_predator<R: $Repository>(repository: &R) {
+                let stored = animal(1, 12);
+                repository.$insert(stored.clone()).await.unwrap();
+                assert_invalid(repository.$update(animal(1, 5)).await.unwrap_err());
+                assert_eq!(repository.$get(stored.id).await.unwrap(), stored);
+            }
+
+            /// Deleting an animal that still eats another fails and keeps it.
+            pub async fn delete_eaten<R: $Repository>(repository: &R) {
+                let id = *PREDATORS.start();
+                let stored = animal(id, id);
+                assert_invalid(repository.$delete(stored.id).await.unwrap_err());
+                assert_eq!(repository.$get(stored.id).await.unwrap(), stored);
+            }
+
+            /// Of inserts of the same animal at once, exactly one succeeds and
+            /// the others conflict.
+            pub async fn concurrent_inserts<R: $Repository>(repository: &R) {
+                let results = at_once([
+                    repository.$insert(animal(1, 11)),
+                    repository.$insert(animal(1, 12)),
+                    repository.$insert(animal(1, 13)),
+                    repository.$insert(animal(1, 14)),
+                ]);
+                let mut stored = None;
+                for (n, result) in (11..).zip(results) {
+                    match result {
+                        Ok(()) => {
+                            assert!(stored.is_none(), "more than one insert succeeded");
+                            stored = Some(animal(1, n));
+                        }
+                        Err(err) => assert_conflict(err, animal(1, n).id),
+                    }
+                }
+                let stored = stored.expect("no insert succeeded");
+                assert_eq!(repository.$get(stored.id).await.unwrap(), stored);
+            }
+
+            /// Writes to different animals at once all take effect.
+            pub async fn concurrent_writes<R: $Repository>(repository: &R) {
+                repository.$insert(animal(3, 11)).await.unwrap();
+                repository.$insert(animal(4, 11)).await.unwrap();
+                let results = at_once([
+                    repository.$insert(animal(1, 12)),
+                    repository.$upsert(animal(2, 12)),
+                    repository.$update(animal(3, 12)),
+                    repository.$delete(animal(4, 11).id),
+                ]);
+                for result in results {
+                    result.unwrap();
+                }
+                for id in 1..=3 {
+                    assert_eq!(
+                        repository.$get(animal(id, 12).id).await.unwrap(),
+                        animal(id, 12)
+                    );
+                }
+                let deleted = animal(4, 11).id;
+                assert_not_found(repository.$get(deleted).await.unwrap_err(), deleted);
+            }
+        }
+    };
+}
+
+checks!(
+    snake,
+    SnakeRepository,
+    Snake(SnakeID, eaten_by: SlugID),
+    get_snake,
+    insert_snake,
+    update_snake,
+    upsert_snake,
+    delete_snake
+);
+checks!(
+    slug,
+    SlugRepository,
+    Slug(SlugID, eaten_by: FrogID),
+    get_slug,
+    insert_slug,
+    update_slug,
+    upsert_slug,
+    delete_slug
+);
+checks!(
+    frog,
+    FrogRepository,
+    Frog(FrogID, eaten_by: SnakeID),
+    get_frog,
+    insert_frog,
+    update_frog,
+    upsert_frog,
+    delete_frog
+);
+
+#[cfg(test)]
+mod test {
+    use super::PREDATORS;
+    use crate::{
+        store::{State, Store},
+        FakeFrogRepository, FakeSlugRepository, FakeSnakeRepository,
+    };
+
+    crate::repository_contract! {
+        setup {
+            let store = Store::new(State::food_chains(PREDATORS));
+        }
+        snake: &FakeSnakeRepository { store: store.clone() },
+        slug: &FakeSlugRepository { store: store.clone() },
+        frog: &FakeFrogRepository { store: store.clone() },
+    }
+}
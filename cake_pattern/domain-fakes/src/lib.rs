@@ -0,0 +1,12 @@
+//! In-memory fakes of the domain's repositories, for tests to run use cases
+//! and services against animals that are actually stored, rather than
+//! against mocks that answer each call with a canned value.
+
+pub mod contract;
+pub mod fixtures;
+mod provider;
+mod repository;
+mod store;
+
+pub use provider::{FakeRepositoryProvider, FakeTransaction};
+pub use repository::{FakeFrogRepository, FakeSlugRepository, FakeSnakeRepository};
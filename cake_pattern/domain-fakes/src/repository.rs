@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use domain::{
+    Frog, FrogID, FrogRepository, Result, Slug, SlugID, SlugRepository, Snake, SnakeID,
+    SnakeRepository,
+};
+
+use crate::store::Store;
+
+/// An in-memory [`SnakeRepository`], handed out by a
+/// [`FakeRepositoryProvider`](crate::FakeRepositoryProvider) whose other
+/// animals it shares, as the predators of its snakes must be stored too.
+#[derive(Debug, Clone)]
+pub struct FakeSnakeRepository {
+    pub(crate) store: Store,
+}
+
+#[async_trait]
+impl SnakeRepository for FakeSnakeRepository {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.store.get(id)
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        self.store.insert(snake)
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        self.store.update(snake)
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        self.store.upsert(snake)
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        self.store.delete::<Snake>(id)
+    }
+}
+
+/// An in-memory [`SlugRepository`], handed out by a
+/// [`FakeRepositoryProvider`](crate::FakeRepositoryProvider) whose other
+/// animals it shares, as the predators of its slugs must be stored too.
+#[derive(Debug, Clone)]
+pub struct FakeSlugRepository {
+    pub(crate) store: Store,
+}
+
+#[async_trait]
+impl SlugRepository for FakeSlugRepository {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.store.get(id)
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        self.store.insert(slug)
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        self.store.update(slug)
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        self.store.upsert(slug)
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        self.store.delete::<Slug>(id)
+    }
+}
+
+/// An in-memory [`FrogRepository`], handed out by a
+/// [`FakeRepositoryProvider`](crate::FakeRepositoryProvider) whose other
+/// animals it shares, as the predators of its frogs must be stored too.
+#[derive(Debug, Clone)]
+pub struct FakeFrogRepository {
+    pub(crate) store: Store,
+}
+
+#[async_trait]
+impl FrogRepository for FakeFrogRepository {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.store.get(id)
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        self.store.insert(frog)
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        self.store.update(frog)
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        self.store.upsert(frog)
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        self.store.delete::<Frog>(id)
+    }
+}
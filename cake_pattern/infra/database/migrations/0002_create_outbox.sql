@@ -0,0 +1,11 @@
+-- Messages written in the same transaction as the rows they describe, to be
+-- published by a relay. `AUTOINCREMENT` keeps IDs from being reused, as the
+-- relay relies on them increasing.
+CREATE TABLE outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    topic TEXT NOT NULL,
+    payload BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at TEXT
+);
+CREATE INDEX outbox_unsent ON outbox (id) WHERE sent_at IS NULL;
@@ -1,15 +1,507 @@
-pub struct DatabaseConnection;
+use std::{
+    panic,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use rusqlite::ErrorCode;
+use thiserror::Error;
+use tokio::task;
+
+mod memory;
+mod migration;
+mod outbox;
+mod pool;
+mod row;
+mod sqlite;
+mod transaction;
+
+use memory::MemoryStore;
+pub use memory::Snapshot;
+pub use migration::{AppliedMigration, Migration, Migrator, MIGRATIONS};
+pub use outbox::OutboxRecord;
+pub use pool::{Pool, PoolConfig, PoolStats, PooledConnection};
+pub use row::{FrogRow, Row, SlugRow, SnakeRow};
+use sqlite::SqliteStore;
+pub use transaction::Transaction;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{table} {id} already exists")]
+    Conflict { table: &'static str, id: u64 },
+    #[error("{table} {id} does not exist")]
+    NotFound { table: &'static str, id: u64 },
+    /// A row refers to one that does not exist. Inside a transaction this is
+    /// only checked on commit, so rows may refer to each other in a cycle.
+    #[error("foreign key violated: a row refers to one that does not exist")]
+    ForeignKey,
+    #[error("invalid database url {0:?}")]
+    InvalidUrl(String),
+    #[error("transaction already committed or rolled back")]
+    Finished,
+    #[error("invalid pool configuration: {0}")]
+    InvalidPool(String),
+    #[error("timed out after {0:?} waiting for a database connection")]
+    Timeout(Duration),
+    #[error("invalid migrations: {0}")]
+    InvalidMigrations(String),
+    #[error("applied migration {0} is unknown to this build")]
+    UnknownMigration(u32),
+    #[error("migration {version} ({name}) was edited after it was applied")]
+    ChecksumMismatch { version: u32, name: &'static str },
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+impl Error {
+    /// Whether the operation may succeed if retried later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Timeout(_) => true,
+            Self::Sqlite(rusqlite::Error::SqliteFailure(err, _)) => matches!(
+                err.code,
+                ErrorCode::DatabaseBusy
+                    | ErrorCode::DatabaseLocked
+                    | ErrorCode::CannotOpen
+                    | ErrorCode::SystemIoFailure
+            ),
+            _ => false,
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Locks the mutex even if a panic poisoned it. A panic cannot leave anything
+/// this crate locks half written, so later callers go on rather than fail.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `f` on Tokio's blocking threads, so it cannot hold up the async
+/// worker awaiting it, and resumes a panic it raised there.
+pub(crate) async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+}
+
+/// Selects the storage backend, parsed from `memory` or `sqlite:<path>`
+/// (`sqlite::memory:` opens a private in-memory SQLite database).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseConfig {
+    Memory,
+    Sqlite(PathBuf),
+}
+
+impl FromStr for DatabaseConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "memory" => Ok(Self::Memory),
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Self::Sqlite(path.into())),
+            _ => Err(Error::InvalidUrl(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Backend {
+    Memory(Arc<MemoryStore>),
+    Sqlite(SqliteStore),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $store:ident => $call:expr) => {
+        match &$self.backend {
+            Backend::Memory($store) => $call,
+            Backend::Sqlite($store) => $call,
+        }
+    };
+}
+
+#[derive(Debug)]
+pub struct DatabaseConnection {
+    pub(crate) backend: Backend,
+}
+
+impl DatabaseConnection {
+    pub fn open(config: &DatabaseConfig) -> Result<Self> {
+        match config {
+            DatabaseConfig::Memory => Ok(Self::in_memory()),
+            DatabaseConfig::Sqlite(path) if path.as_os_str() == ":memory:" => Ok(Self {
+                backend: Backend::Sqlite(SqliteStore::open_in_memory()?),
+            }),
+            DatabaseConfig::Sqlite(path) => Self::sqlite(path),
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        Self {
+            backend: Backend::Memory(Arc::default()),
+        }
+    }
+
+    /// Opens or creates an SQLite database file and its schema.
+    pub fn sqlite(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            backend: Backend::Sqlite(SqliteStore::open(path.into())?),
+        })
+    }
+
+    /// Liveness probe: fails if the connection can no longer serve queries.
+    pub fn ping(&self) -> Result<()> {
+        dispatch!(self, store => store.ping())
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        dispatch!(self, store => store.snapshot())
+    }
+
+    pub fn get<R: Row>(&self, id: u64) -> Result<Option<R>> {
+        dispatch!(self, store => store.get(id))
+    }
+
+    /// Fails with [`Error::Conflict`] if a row with the same ID exists, or
+    /// with [`Error::ForeignKey`] if the row it is eaten by does not.
+    pub fn insert<R: Row>(&self, row: R) -> Result<()> {
+        dispatch!(self, store => store.insert(row))
+    }
+
+    /// Fails with [`Error::NotFound`] if no row with the same ID exists, or
+    /// with [`Error::ForeignKey`] if the row it is eaten by does not.
+    pub fn update<R: Row>(&self, row: R) -> Result<()> {
+        dispatch!(self, store => store.update(row))
+    }
+
+    /// Fails with [`Error::ForeignKey`] if the row it is eaten by does not
+    /// exist.
+    pub fn upsert<R: Row>(&self, row: R) -> Result<()> {
+        dispatch!(self, store => store.upsert(row))
+    }
+
+    /// Fails with [`Error::NotFound`] if no row with the ID exists, or with
+    /// [`Error::ForeignKey`] if another row is eaten by it.
+    pub fn delete<R: Row>(&self, id: u64) -> Result<()> {
+        dispatch!(self, store => store.delete::<R>(id))
+    }
+
+    /// Adds a message to the outbox, to be published to the topic by a relay.
+    pub fn enqueue(&self, topic: &str, payload: impl Into<Vec<u8>>) -> Result<()> {
+        let message = (topic.to_owned(), payload.into());
+        dispatch!(self, store => store.enqueue(message))
+    }
+
+    /// Up to `max` outbox messages not yet marked sent, oldest first.
+    pub fn unsent(&self, max: usize) -> Result<Vec<OutboxRecord>> {
+        dispatch!(self, store => store.unsent(max))
+    }
+
+    /// Marks the outbox message sent; marking it again has no effect.
+    pub fn mark_sent(&self, id: u64) -> Result<()> {
+        dispatch!(self, store => store.mark_sent(id))
+    }
+
+    /// Identifies the outbox of this database, drawn at random when it is
+    /// created: outbox IDs are only unique within one database.
+    pub fn outbox_epoch(&self) -> Result<u64> {
+        dispatch!(self, store => store.outbox_epoch())
+    }
+
+    /// The migrations recorded as applied, oldest first.
+    pub fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        dispatch!(self, store => store.applied_migrations())
+    }
+
+    pub(crate) fn apply_migration(&self, migration: &Migration) -> Result<()> {
+        dispatch!(self, store => store.apply_migration(migration))
+    }
+}
 
 pub struct Database {
-    conn: DatabaseConnection,
+    pool: Pool,
 }
 
 impl Database {
-    pub fn new(conn: DatabaseConnection) -> Self {
-        Self { conn }
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    /// Checks a connection out of the pool; see [`Pool::get`].
+    pub async fn conn(&self) -> Result<PooledConnection> {
+        self.pool.get().await
+    }
+
+    /// Starts a transaction on a connection of its own.
+    pub async fn begin(&self) -> Result<Transaction> {
+        Transaction::begin(self.conn().await?).await
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{env, fs, process, sync::Arc, thread};
+
+    use super::*;
+
+    /// Stores a snake, a slug and a frog with each of the IDs, each eaten by
+    /// the next in the food chain with the same ID, for the rows under test to
+    /// be eaten by.
+    pub(crate) fn seed(conn: &DatabaseConnection, ids: impl IntoIterator<Item = u64>) {
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        match &conn.backend {
+            Backend::Memory(store) => {
+                let write: memory::Write = Box::new(move |tables| {
+                    for &id in &ids {
+                        memory::insert(tables, SnakeRow { id, eaten_by: id })?;
+                        memory::insert(tables, SlugRow { id, eaten_by: id })?;
+                        memory::insert(tables, FrogRow { id, eaten_by: id })?;
+                    }
+                    Ok(())
+                });
+                store.apply([], &[write], &[]).unwrap();
+            }
+            Backend::Sqlite(store) => {
+                store.begin().unwrap();
+                for id in ids {
+                    store.insert(SnakeRow { id, eaten_by: id }).unwrap();
+                    store.insert(SlugRow { id, eaten_by: id }).unwrap();
+                    store.insert(FrogRow { id, eaten_by: id }).unwrap();
+                }
+                store.commit().unwrap();
+            }
+        }
+    }
+
+    fn connections() -> Vec<DatabaseConnection> {
+        let connections = vec![
+            DatabaseConnection::in_memory(),
+            DatabaseConnection::open(&"sqlite::memory:".parse().unwrap()).unwrap(),
+        ];
+        for conn in &connections {
+            Migrator::default().migrate(conn).unwrap();
+        }
+        connections
+    }
+
+    fn snake(id: u64, eaten_by: u64) -> SnakeRow {
+        SnakeRow { id, eaten_by }
+    }
+
+    #[test]
+    fn test_config() {
+        assert_eq!(
+            "memory".parse::<DatabaseConfig>().unwrap(),
+            DatabaseConfig::Memory
+        );
+        assert_eq!(
+            "sqlite:data/app.db".parse::<DatabaseConfig>().unwrap(),
+            DatabaseConfig::Sqlite("data/app.db".into())
+        );
+        assert!(matches!(
+            "sqlite:".parse::<DatabaseConfig>(),
+            Err(Error::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            "postgres://localhost".parse::<DatabaseConfig>(),
+            Err(Error::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_insert_get() {
+        for conn in connections() {
+            seed(&conn, [2, 3]);
+            conn.insert(snake(1, 2)).unwrap();
+            conn.insert(SlugRow { id: 1, eaten_by: 3 }).unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 2)));
+            assert_eq!(
+                conn.get::<SlugRow>(1).unwrap(),
+                Some(SlugRow { id: 1, eaten_by: 3 })
+            );
+            assert_eq!(conn.get::<FrogRow>(1).unwrap(), None);
+            assert!(matches!(
+                conn.insert(snake(1, 5)),
+                Err(Error::Conflict {
+                    table: "snake",
+                    id: 1
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_update_upsert_delete() {
+        for conn in connections() {
+            seed(&conn, [2, 3, 4]);
+            assert!(matches!(
+                conn.update(snake(1, 2)),
+                Err(Error::NotFound {
+                    table: "snake",
+                    id: 1
+                })
+            ));
+            conn.upsert(snake(1, 2)).unwrap();
+            conn.update(snake(1, 3)).unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 3)));
+            conn.upsert(snake(1, 4)).unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 4)));
+            conn.delete::<SnakeRow>(1).unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), None);
+            assert!(matches!(
+                conn.delete::<SnakeRow>(1),
+                Err(Error::NotFound {
+                    table: "snake",
+                    id: 1
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_full_id_range() {
+        for conn in connections() {
+            seed(&conn, [u64::MAX - 1]);
+            conn.insert(snake(u64::MAX, u64::MAX - 1)).unwrap();
+            assert_eq!(
+                conn.get::<SnakeRow>(u64::MAX).unwrap(),
+                Some(snake(u64::MAX, u64::MAX - 1))
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated() {
+        for conn in connections() {
+            seed(&conn, [2, 3]);
+            conn.insert(snake(1, 2)).unwrap();
+            let snapshot = conn.snapshot().unwrap();
+            conn.update(snake(1, 3)).unwrap();
+            conn.insert(snake(4, 3)).unwrap();
+            assert_eq!(snapshot.get::<SnakeRow>(1), Some(&snake(1, 2)));
+            assert_eq!(snapshot.get::<SnakeRow>(4), None);
+            assert_eq!(
+                conn.snapshot().unwrap().get::<SnakeRow>(4),
+                Some(&snake(4, 3))
+            );
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writes() {
+        for conn in connections() {
+            seed(&conn, [9]);
+            let conn = Arc::new(conn);
+            let handles = (1..=8)
+                .map(|id| {
+                    let conn = Arc::clone(&conn);
+                    thread::spawn(move || conn.insert(snake(id, 9)).unwrap())
+                })
+                .collect::<Vec<_>>();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            let snapshot = conn.snapshot().unwrap();
+            assert_eq!(
+                snapshot.rows::<SnakeRow>().map(Row::id).collect::<Vec<_>>(),
+                (1..=9).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        for conn in connections() {
+            assert!(matches!(conn.insert(snake(1, 2)), Err(Error::ForeignKey)));
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), None);
+            seed(&conn, [2]);
+            conn.insert(snake(1, 2)).unwrap();
+            assert!(matches!(conn.update(snake(1, 3)), Err(Error::ForeignKey)));
+            assert!(matches!(conn.upsert(snake(1, 3)), Err(Error::ForeignKey)));
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 2)));
+            // Snake 1 is eaten by slug 2.
+            assert!(matches!(conn.delete::<SlugRow>(2), Err(Error::ForeignKey)));
+            assert_eq!(
+                conn.get::<SlugRow>(2).unwrap(),
+                Some(SlugRow { id: 2, eaten_by: 2 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_outbox() {
+        for conn in connections() {
+            conn.enqueue("snake", "registered").unwrap();
+            conn.enqueue("frog", "eaten").unwrap();
+            let unsent = conn.unsent(10).unwrap();
+            assert_eq!(
+                unsent,
+                [
+                    OutboxRecord {
+                        id: 1,
+                        topic: "snake".to_owned(),
+                        payload: b"registered".to_vec(),
+                    },
+                    OutboxRecord {
+                        id: 2,
+                        topic: "frog".to_owned(),
+                        payload: b"eaten".to_vec(),
+                    }
+                ]
+            );
+            assert_eq!(conn.unsent(1).unwrap(), unsent[..1]);
+            conn.mark_sent(1).unwrap();
+            conn.mark_sent(1).unwrap();
+            assert_eq!(conn.unsent(10).unwrap(), unsent[1..]);
+            conn.enqueue("slug", "registered").unwrap();
+            let ids = conn
+                .unsent(10)
+                .unwrap()
+                .iter()
+                .map(|r| r.id)
+                .collect::<Vec<_>>();
+            assert_eq!(ids, [2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_outbox_epoch() {
+        let [memory, sqlite] = <[_; 2]>::try_from(connections()).unwrap();
+        assert_eq!(
+            memory.outbox_epoch().unwrap(),
+            memory.outbox_epoch().unwrap()
+        );
+        assert_eq!(
+            sqlite.outbox_epoch().unwrap(),
+            sqlite.outbox_epoch().unwrap()
+        );
+        assert_ne!(
+            memory.outbox_epoch().unwrap(),
+            DatabaseConnection::in_memory().outbox_epoch().unwrap()
+        );
     }
 
-    pub fn conn(&self) -> &DatabaseConnection {
-        &self.conn
+    #[test]
+    fn test_sqlite_file_persists() {
+        let path = env::temp_dir().join(format!("database-test-{}.db", process::id()));
+        _ = fs::remove_file(&path);
+        let conn = DatabaseConnection::sqlite(&path).unwrap();
+        Migrator::default().migrate(&conn).unwrap();
+        seed(&conn, [1]);
+        conn.insert(FrogRow { id: 3, eaten_by: 1 }).unwrap();
+        drop(conn);
+        let conn = DatabaseConnection::sqlite(&path).unwrap();
+        assert_eq!(
+            conn.get::<FrogRow>(3).unwrap(),
+            Some(FrogRow { id: 3, eaten_by: 1 })
+        );
+        fs::remove_file(&path).unwrap();
     }
 }
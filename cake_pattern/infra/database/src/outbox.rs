@@ -0,0 +1,8 @@
+/// A message waiting in the outbox to be published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxRecord {
+    /// Assigned in write order, starting at 1.
+    pub id: u64,
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
@@ -0,0 +1,83 @@
+use crate::memory::{Table, Tables};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeRow {
+    pub id: u64,
+    pub eaten_by: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlugRow {
+    pub id: u64,
+    pub eaten_by: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrogRow {
+    pub id: u64,
+    pub eaten_by: u64,
+}
+
+/// A row type stored in one of the database tables.
+pub trait Row: private::Sealed + Clone + PartialEq + Send + Sync + 'static {
+    const TABLE: &'static str;
+
+    fn id(&self) -> u64;
+}
+
+pub(crate) mod private {
+    use super::*;
+
+    pub trait Sealed: Sized {
+        fn new(id: u64, eaten_by: u64) -> Self;
+        fn eaten_by(&self) -> u64;
+        fn table(tables: &Tables) -> &Table<Self>;
+        fn table_mut(tables: &mut Tables) -> &mut Table<Self>;
+        /// Whether the row `eaten_by` refers to exists.
+        fn predator_exists(tables: &Tables, eaten_by: u64) -> bool;
+        /// Whether a row of the table referring to this one exists.
+        fn prey_exists(tables: &Tables, id: u64) -> bool;
+    }
+}
+
+macro_rules! row {
+    ($row:ident, $table:literal, $field:ident, eaten_by: $predator:ident, prey: $prey:ident) => {
+        impl Row for $row {
+            const TABLE: &'static str = $table;
+
+            fn id(&self) -> u64 {
+                self.id
+            }
+        }
+
+        impl private::Sealed for $row {
+            fn new(id: u64, eaten_by: u64) -> Self {
+                Self { id, eaten_by }
+            }
+
+            fn eaten_by(&self) -> u64 {
+                self.eaten_by
+            }
+
+            fn table(tables: &Tables) -> &Table<Self> {
+                &tables.$field
+            }
+
+            fn table_mut(tables: &mut Tables) -> &mut Table<Self> {
+                &mut tables.$field
+            }
+
+            fn predator_exists(tables: &Tables, eaten_by: u64) -> bool {
+                tables.$predator.contains_key(&eaten_by)
+            }
+
+            fn prey_exists(tables: &Tables, id: u64) -> bool {
+                tables.$prey.values().any(|prey| prey.eaten_by == id)
+            }
+        }
+    };
+}
+
+row!(SnakeRow, "snake", snake, eaten_by: slug, prey: frog);
+row!(SlugRow, "slug", slug, eaten_by: frog, prey: snake);
+row!(FrogRow, "frog", frog, eaten_by: snake, prey: slug);
@@ -0,0 +1,275 @@
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use rusqlite::{ffi, params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::{
+    lock,
+    memory::{Snapshot, Tables},
+    AppliedMigration, Error, FrogRow, Migration, OutboxRecord, Result, Row, SlugRow, SnakeRow,
+};
+
+// The table schema is created by migrations; every connection only needs the
+// bookkeeping table and foreign keys enforced. They are declared deferred, so
+// a transaction may write a cycle of rows one by one and is checked on commit.
+const INIT: &str = "
+PRAGMA foreign_keys = ON;
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    checksum INTEGER NOT NULL,
+    applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+";
+
+// SQLite integers are signed; IDs are stored bit-for-bit so the full u64 range
+// round-trips.
+fn to_sql(id: u64) -> i64 {
+    id as i64
+}
+
+fn from_sql(id: i64) -> u64 {
+    id as u64
+}
+
+#[derive(Debug)]
+pub(crate) struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub(crate) fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(INIT)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Has the connection wait up to the timeout for another connection's
+    /// lock rather than fail as busy at once.
+    pub(crate) fn set_busy_timeout(&self, timeout: Duration) -> Result<()> {
+        let conn = lock(&self.conn);
+        conn.busy_timeout(timeout)?;
+        Ok(())
+    }
+
+    pub(crate) fn ping(&self) -> Result<()> {
+        let conn = lock(&self.conn);
+        conn.query_row("SELECT 1", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    pub(crate) fn snapshot(&self) -> Result<Snapshot> {
+        let mut conn = lock(&self.conn);
+        let tx = conn.transaction()?;
+        let mut tables = Tables::default();
+        read_table::<SnakeRow>(&tx, &mut tables)?;
+        read_table::<SlugRow>(&tx, &mut tables)?;
+        read_table::<FrogRow>(&tx, &mut tables)?;
+        tx.commit()?;
+        Ok(Snapshot::new(tables))
+    }
+
+    /// Takes the write lock up front so the transaction cannot fail midway
+    /// on a writer that started after it.
+    pub(crate) fn begin(&self) -> Result<()> {
+        self.batch("BEGIN IMMEDIATE")
+    }
+
+    pub(crate) fn commit(&self) -> Result<()> {
+        self.batch("COMMIT")
+    }
+
+    pub(crate) fn rollback(&self) -> Result<()> {
+        self.batch("ROLLBACK")
+    }
+
+    pub(crate) fn get<R: Row>(&self, id: u64) -> Result<Option<R>> {
+        let conn = lock(&self.conn);
+        let row = conn
+            .query_row(
+                &format!("SELECT id, eaten_by FROM {} WHERE id = ?1", R::TABLE),
+                params![to_sql(id)],
+                |row| Ok(R::new(from_sql(row.get(0)?), from_sql(row.get(1)?))),
+            )
+            .optional()?;
+        Ok(row)
+    }
+
+    pub(crate) fn insert<R: Row>(&self, row: R) -> Result<()> {
+        let changed = self.execute(
+            &format!(
+                "INSERT INTO {} (id, eaten_by) VALUES (?1, ?2) ON CONFLICT (id) DO NOTHING",
+                R::TABLE
+            ),
+            &row,
+        )?;
+        if changed == 0 {
+            return Err(Error::Conflict {
+                table: R::TABLE,
+                id: row.id(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn update<R: Row>(&self, row: R) -> Result<()> {
+        let changed = self.execute(
+            &format!("UPDATE {} SET eaten_by = ?2 WHERE id = ?1", R::TABLE),
+            &row,
+        )?;
+        if changed == 0 {
+            return Err(Error::NotFound {
+                table: R::TABLE,
+                id: row.id(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn upsert<R: Row>(&self, row: R) -> Result<()> {
+        self.execute(
+            &format!(
+                "INSERT INTO {} (id, eaten_by) VALUES (?1, ?2) \
+                 ON CONFLICT (id) DO UPDATE SET eaten_by = excluded.eaten_by",
+                R::TABLE
+            ),
+            &row,
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn delete<R: Row>(&self, id: u64) -> Result<()> {
+        let conn = lock(&self.conn);
+        let changed = conn
+            .execute(
+                &format!("DELETE FROM {} WHERE id = ?1", R::TABLE),
+                params![to_sql(id)],
+            )
+            .map_err(constraint)?;
+        if changed == 0 {
+            return Err(Error::NotFound {
+                table: R::TABLE,
+                id,
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn enqueue(&self, (topic, payload): (String, Vec<u8>)) -> Result<()> {
+        let conn = lock(&self.conn);
+        conn.execute(
+            "INSERT INTO outbox (topic, payload) VALUES (?1, ?2)",
+            params![topic, payload],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn unsent(&self, max: usize) -> Result<Vec<OutboxRecord>> {
+        let conn = lock(&self.conn);
+        let mut statement = conn.prepare(
+            "SELECT id, topic, payload FROM outbox WHERE sent_at IS NULL ORDER BY id LIMIT ?1",
+        )?;
+        let records = statement
+            .query_map(params![i64::try_from(max).unwrap_or(i64::MAX)], |row| {
+                Ok(OutboxRecord {
+                    id: from_sql(row.get(0)?),
+                    topic: row.get(1)?,
+                    payload: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(records)
+    }
+
+    pub(crate) fn mark_sent(&self, id: u64) -> Result<()> {
+        let conn = lock(&self.conn);
+        conn.execute(
+            "UPDATE outbox SET sent_at = CURRENT_TIMESTAMP WHERE id = ?1 AND sent_at IS NULL",
+            params![to_sql(id)],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn outbox_epoch(&self) -> Result<u64> {
+        let conn = lock(&self.conn);
+        let epoch = conn.query_row("SELECT epoch FROM outbox_epoch", [], |row| row.get(0))?;
+        Ok(from_sql(epoch))
+    }
+
+    pub(crate) fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        let conn = lock(&self.conn);
+        let mut statement =
+            conn.prepare("SELECT version, name, checksum FROM schema_migrations ORDER BY version")?;
+        let migrations = statement
+            .query_map([], |row| {
+                Ok(AppliedMigration {
+                    version: row.get(0)?,
+                    name: row.get(1)?,
+                    checksum: from_sql(row.get(2)?),
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(migrations)
+    }
+
+    /// Runs the migration and records it in one transaction, so a failing
+    /// migration leaves neither schema changes nor a record behind.
+    pub(crate) fn apply_migration(&self, migration: &Migration) -> Result<()> {
+        let mut conn = lock(&self.conn);
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES (?1, ?2, ?3)",
+            params![
+                migration.version,
+                migration.name,
+                to_sql(migration.checksum())
+            ],
+        )?;
+        Ok(tx.commit()?)
+    }
+
+    fn batch(&self, sql: &str) -> Result<()> {
+        let conn = lock(&self.conn);
+        conn.execute_batch(sql).map_err(constraint)
+    }
+
+    fn execute<R: Row>(&self, sql: &str, row: &R) -> Result<usize> {
+        let conn = lock(&self.conn);
+        conn.execute(sql, params![to_sql(row.id()), to_sql(row.eaten_by())])
+            .map_err(constraint)
+    }
+}
+
+/// Tells foreign key violations apart from other failures.
+fn constraint(err: rusqlite::Error) -> Error {
+    match err {
+        rusqlite::Error::SqliteFailure(failure, _)
+            if failure.extended_code == ffi::SQLITE_CONSTRAINT_FOREIGNKEY =>
+        {
+            Error::ForeignKey
+        }
+        err => err.into(),
+    }
+}
+
+fn read_table<R: Row>(conn: &Connection, tables: &mut Tables) -> Result<()> {
+    let mut statement = conn.prepare(&format!("SELECT id, eaten_by FROM {}", R::TABLE))?;
+    let rows = statement.query_map([], |row| {
+        Ok(R::new(from_sql(row.get(0)?), from_sql(row.get(1)?)))
+    })?;
+    let table = R::table_mut(tables);
+    for row in rows {
+        let row = row?;
+        table.insert(row.id(), row);
+    }
+    Ok(())
+}
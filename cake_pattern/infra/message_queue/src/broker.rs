@@ -0,0 +1,484 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs, future,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::sync::watch;
+
+use crate::{
+    envelope::Envelope,
+    group::{Group, PartitionedTopic},
+    lock,
+    log::{Log, LogConfig, MemoryLog, SegmentLog},
+    queue::{OverflowPolicy, Queue, TopicConfig, TopicStats},
+    schedule::Scheduler,
+    Error, Result,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    /// Position of the message in its topic, starting at 0.
+    pub offset: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Decodes the payload as an [`Envelope`].
+    pub fn envelope(&self) -> Result<Envelope> {
+        Envelope::from_bytes(&self.payload)
+    }
+}
+
+#[derive(Debug)]
+struct Topic {
+    log: Log,
+    subscribers: Vec<Arc<Queue>>,
+    config: TopicConfig,
+    /// Signalled whenever a subscriber receives, to wake blocked publishers.
+    space: Arc<watch::Sender<()>>,
+    dropped: u64,
+    overflows: u64,
+}
+
+impl Topic {
+    fn new(log: Log) -> Self {
+        Self {
+            log,
+            subscribers: Vec::new(),
+            config: TopicConfig::default(),
+            space: Arc::new(watch::channel(()).0),
+            dropped: 0,
+            overflows: 0,
+        }
+    }
+
+    /// Logs the message and enqueues it for every subscriber, or fails with
+    /// [`Error::Full`] if a subscriber that holds the publish back has no
+    /// room.
+    fn append(&mut self, topic: &str, payload: &[u8]) -> Result<u64> {
+        self.append_with(topic, payload, |log| log.append(payload))
+    }
+
+    /// Like [`Topic::append`], logging the message with `append`.
+    fn append_with(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        append: impl FnOnce(&mut Log) -> Result<u64>,
+    ) -> Result<u64> {
+        // Dropped subscriptions are pruned lazily, on the next publish.
+        self.subscribers
+            .retain(|subscriber| subscriber.is_attached());
+        if let Some(capacity) = self.config.capacity {
+            let full = self
+                .subscribers
+                .iter()
+                .any(|queue| queue.holds_back(&self.config) && queue.len() >= capacity);
+            if full {
+                self.overflows += 1;
+                return Err(Error::Full(topic.to_owned()));
+            }
+        }
+        let offset = append(&mut self.log)?;
+        let message = Message {
+            topic: topic.to_owned(),
+            offset,
+            payload: payload.to_vec(),
+        };
+        for subscriber in &self.subscribers {
+            self.dropped += u64::from(subscriber.push(message.clone(), &self.config));
+        }
+        Ok(offset)
+    }
+
+    fn stats(&self) -> TopicStats {
+        let live = || {
+            self.subscribers
+                .iter()
+                .filter(|subscriber| subscriber.is_attached())
+        };
+        TopicStats {
+            subscribers: live().count(),
+            depth: live().map(|queue| queue.len()).max().unwrap_or(0),
+            capacity: self.config.capacity,
+            dropped: self.dropped,
+            overflows: self.overflows,
+        }
+    }
+}
+
+impl Drop for Topic {
+    fn drop(&mut self) {
+        for subscriber in &self.subscribers {
+            subscriber.close();
+        }
+    }
+}
+
+/// A topic, opened on first use under its own lock, so a slow open does not
+/// hold up the other topics.
+type TopicSlot = Arc<Mutex<Option<Topic>>>;
+
+/// Records replayed to a subscription per turn of the topic lock, unless the
+/// topic's capacity is smaller.
+pub(crate) const REPLAY_PAGE: usize = 256;
+
+/// Fans each published message out to the topic's subscribers. Offsets are
+/// assigned and messages enqueued under the topic's lock, so every
+/// subscriber sees a topic in publish order.
+#[derive(Debug)]
+pub(crate) struct Broker {
+    /// Held only to find a topic's slot; the log is read, appended to and
+    /// synced under the topic's own lock.
+    topics: Mutex<HashMap<String, TopicSlot>>,
+    /// Consumer groups by topic and group name.
+    groups: Mutex<HashMap<(String, String), Arc<Group>>>,
+    scheduler: Arc<Scheduler>,
+    /// Where topic logs are stored, if not in memory.
+    dir: Option<PathBuf>,
+    config: LogConfig,
+}
+
+impl Broker {
+    /// A broker that keeps every topic's log in memory, within the config's
+    /// retention limits.
+    pub(crate) fn in_memory(config: LogConfig) -> Self {
+        Self {
+            topics: Mutex::default(),
+            groups: Mutex::default(),
+            scheduler: Arc::default(),
+            dir: None,
+            config,
+        }
+    }
+
+    /// A broker that appends every topic to a log in its own subdirectory.
+    pub(crate) fn durable(dir: PathBuf, config: LogConfig) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        // Topic names cannot start with a `.`, so this is no topic's log.
+        let scheduler = Scheduler::durable(dir.join(".scheduled"), &config)?;
+        Ok(Self {
+            topics: Mutex::default(),
+            groups: Mutex::default(),
+            scheduler: Arc::new(scheduler),
+            dir: Some(dir),
+            config,
+        })
+    }
+
+    /// Holds back the messages published for later.
+    pub(crate) fn scheduler(&self) -> &Arc<Scheduler> {
+        &self.scheduler
+    }
+
+    /// Publishes without waiting, so a topic that blocks publishers fails
+    /// with [`Error::Full`] while a subscriber has no room.
+    pub(crate) fn publish(&self, topic: &str, payload: &[u8]) -> Result<u64> {
+        self.with_topic(topic, |state| state.append(topic, payload))
+    }
+
+    /// Publishes, waiting for room while the topic blocks publishers.
+    pub(crate) async fn publish_waiting(&self, topic: &str, payload: &[u8]) -> Result<u64> {
+        self.wait_for_room(topic, |state| state.append(topic, payload))
+            .await
+    }
+
+    /// Publishes unless the producer already published `sequence`, or a later
+    /// one, to the topic. The log records the sequence together with the
+    /// append, see [`Log::append_once`].
+    pub(crate) async fn publish_once(
+        &self,
+        topic: &str,
+        producer: &str,
+        sequence: u64,
+        payload: &[u8],
+    ) -> Result<Option<u64>> {
+        validate(producer)?;
+        self.wait_for_room(topic, |state| {
+            if state.log.producer_sequence(producer)? > Some(sequence) {
+                return Ok(None);
+            }
+            let offset = state.append_with(topic, payload, |log| {
+                log.append_once(producer, sequence, payload)
+            })?;
+            Ok(Some(offset))
+        })
+        .await
+    }
+
+    /// Subscribes to live messages only, or with `from` first replays the
+    /// logged ones from that offset on. The replay is read a page at a time,
+    /// each once the subscriber has received the last, so it holds no more
+    /// than the topic's capacity of them at once. A `lossless` subscription
+    /// never has messages dropped, see [`OverflowPolicy`].
+    pub(crate) fn subscribe(
+        self: &Arc<Self>,
+        topic: &str,
+        from: Option<u64>,
+        lossless: bool,
+    ) -> Result<Subscription> {
+        let queue = self.with_topic(topic, |state| {
+            let queue = Arc::new(Queue::new(Arc::clone(&state.space), lossless));
+            if from.is_none() {
+                state.subscribers.push(Arc::clone(&queue));
+            }
+            Ok(queue)
+        })?;
+        let mut replay = None;
+        if let Some(mut next) = from {
+            if !self.replay(topic, &queue, &mut next)? {
+                replay = Some(Replay {
+                    broker: Arc::downgrade(self),
+                    next,
+                });
+            }
+        }
+        Ok(Subscription {
+            topic: topic.to_owned(),
+            queue,
+            replay,
+        })
+    }
+
+    /// Queues the next page of a replay from `next` on and returns whether
+    /// that caught the subscription up. If so, it goes live under the same
+    /// turn of the topic lock, so no message is missed or repeated on the
+    /// switch.
+    fn replay(&self, topic: &str, queue: &Arc<Queue>, next: &mut u64) -> Result<bool> {
+        self.with_topic(topic, |state| {
+            let page = state
+                .config
+                .capacity
+                .map_or(REPLAY_PAGE, |capacity| capacity.clamp(1, REPLAY_PAGE));
+            let records = state.log.read(*next, page)?;
+            let caught_up = records.len() < page;
+            for (offset, payload) in records {
+                *next = offset + 1;
+                let message = Message {
+                    topic: topic.to_owned(),
+                    offset,
+                    payload,
+                };
+                queue.push(message, &TopicConfig::default());
+            }
+            if caught_up {
+                state.subscribers.push(Arc::clone(queue));
+            }
+            Ok(caught_up)
+        })
+    }
+
+    pub(crate) fn read(&self, topic: &str, from: u64, max: usize) -> Result<Vec<Message>> {
+        self.with_topic(topic, |state| {
+            let records = state.log.read(from, max)?;
+            Ok(records
+                .into_iter()
+                .map(|(offset, payload)| Message {
+                    topic: topic.to_owned(),
+                    offset,
+                    payload,
+                })
+                .collect())
+        })
+    }
+
+    pub(crate) fn committed(&self, topic: &str, consumer: &str) -> Result<Option<u64>> {
+        validate(consumer)?;
+        self.with_topic(topic, |state| state.log.committed(consumer))
+    }
+
+    pub(crate) fn commit(&self, topic: &str, consumer: &str, offset: u64) -> Result<()> {
+        validate(consumer)?;
+        self.with_topic(topic, |state| state.log.commit(consumer, offset))
+    }
+
+    /// Sets the capacity and overflow policy of the topic's subscriber
+    /// queues, from the next publish on.
+    pub(crate) fn configure(&self, topic: &str, config: TopicConfig) -> Result<()> {
+        self.with_topic(topic, |state| {
+            state.config = config;
+            Ok(())
+        })
+    }
+
+    pub(crate) fn stats(&self, topic: &str) -> Result<TopicStats> {
+        self.with_topic(topic, |state| Ok(state.stats()))
+    }
+
+    /// Runs `f` on the topic until it stops failing with [`Error::Full`],
+    /// waiting for a subscriber to receive in between, unless the topic's
+    /// policy is to fail.
+    async fn wait_for_room<T>(
+        &self,
+        topic: &str,
+        mut f: impl FnMut(&mut Topic) -> Result<T>,
+    ) -> Result<T> {
+        loop {
+            let space = self.with_topic(topic, |state| match f(state) {
+                Err(Error::Full(_)) if state.config.overflow != OverflowPolicy::Error => {
+                    Ok(Err(state.space.subscribe()))
+                }
+                result => result.map(Ok),
+            })?;
+            match space {
+                Ok(value) => return Ok(value),
+                // The topic, and with it the sender, lives as long as the
+                // broker.
+                Err(mut space) => _ = space.changed().await,
+            }
+        }
+    }
+
+    /// Returns the named consumer group of the topic, created on first use.
+    pub(crate) fn group(&self, topic: &PartitionedTopic, name: &str) -> Result<Arc<Group>> {
+        let mut groups = lock(&self.groups);
+        let key = (topic.name().to_owned(), name.to_owned());
+        let group = match groups.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Arc::new(Group::new(topic.clone(), name)?)),
+        };
+        if group.topic() != topic {
+            return Err(Error::InvalidPartitions {
+                topic: topic.name().to_owned(),
+                partitions: topic.partitions(),
+            });
+        }
+        Ok(Arc::clone(group))
+    }
+
+    /// Runs `f` on the topic under its lock, first opening (and recovering)
+    /// its log if this is the first use since startup.
+    fn with_topic<T>(&self, topic: &str, f: impl FnOnce(&mut Topic) -> Result<T>) -> Result<T> {
+        validate(topic)?;
+        let slot = {
+            let mut topics = lock(&self.topics);
+            Arc::clone(topics.entry(topic.to_owned()).or_default())
+        };
+        let mut slot = lock(&slot);
+        let state = match &mut *slot {
+            Some(state) => state,
+            None => {
+                let log = match &self.dir {
+                    Some(dir) => {
+                        Log::Segment(SegmentLog::open(dir.join(topic), self.config.clone())?)
+                    }
+                    None => Log::Memory(MemoryLog::new(self.config.clone())),
+                };
+                slot.insert(Topic::new(log))
+            }
+        };
+        f(state)
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.scheduler.close();
+    }
+}
+
+/// Topic and consumer names become file names once messages are persisted, so
+/// they are limited to ASCII letters, digits, `.`, `-` and `_`.
+pub(crate) fn validate(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'-' | b'_'));
+    if !valid {
+        return Err(Error::InvalidName(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// The messages of a topic in publish order. The stream ends once every
+/// connection to the broker is dropped, or if a page of a replay cannot be
+/// read, as no later message could be delivered in order.
+#[derive(Debug)]
+pub struct Subscription {
+    topic: String,
+    queue: Arc<Queue>,
+    /// Set until a replay catches up and the subscription goes live.
+    replay: Option<Replay>,
+}
+
+#[derive(Debug)]
+struct Replay {
+    broker: Weak<Broker>,
+    /// The offset of the next logged message to replay.
+    next: u64,
+}
+
+impl Subscription {
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub async fn recv(&mut self) -> Option<Message> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Reads the next page of a replay once the last one is received.
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        if let Some(replay) = &mut self.replay {
+            if self.queue.len() == 0 {
+                let Some(broker) = replay.broker.upgrade() else {
+                    return Poll::Ready(None);
+                };
+                match broker.replay(&self.topic, &self.queue, &mut replay.next) {
+                    Ok(true) => self.replay = None,
+                    Ok(false) => {}
+                    Err(_) => return Poll::Ready(None),
+                }
+            }
+        }
+        self.queue.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.queue.detach();
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Message;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MessageQueue, MessageQueueConnection};
+
+    #[tokio::test]
+    async fn test_replay_is_paged_by_capacity() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let config = TopicConfig {
+            capacity: Some(2),
+            overflow: OverflowPolicy::Block,
+        };
+        message_queue.configure_topic("frog", config).await.unwrap();
+        for n in 0..5 {
+            message_queue.publish("frog", n.to_string()).await.unwrap();
+        }
+        let mut subscription = message_queue.subscribe_from("frog", 1).await.unwrap();
+        for offset in 1..5 {
+            assert!(subscription.queue.len() <= 2);
+            assert_eq!(subscription.recv().await.unwrap().offset, offset);
+        }
+        message_queue.publish("frog", "live").await.unwrap();
+        assert_eq!(subscription.recv().await.unwrap().payload, b"live");
+        assert!(subscription.replay.is_none());
+    }
+}
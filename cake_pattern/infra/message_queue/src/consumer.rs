@@ -0,0 +1,426 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::{self, Instant};
+
+use crate::{
+    broker::{Broker, Message, Subscription},
+    group::Fence,
+    lock, Error, Result,
+};
+
+/// The topic that messages of `topic` are moved to once they exhaust their
+/// deliveries.
+pub(crate) fn dead_letter_topic(topic: &str) -> String {
+    format!("{topic}.dlq")
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    /// How long a delivered message may stay unacked before it is delivered
+    /// again.
+    pub visibility_timeout: Duration,
+    /// Deliveries after which an unacked message is dead-lettered instead of
+    /// delivered again.
+    pub max_deliveries: u32,
+}
+
+impl Default for ConsumerConfig {
+    fn default() -> Self {
+        Self {
+            visibility_timeout: Duration::from_secs(30),
+            max_deliveries: 5,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct InFlight {
+    message: Message,
+    deliveries: u32,
+    visible_at: Instant,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Delivered messages not yet acked, by offset.
+    in_flight: BTreeMap<u64, InFlight>,
+    /// One past the last offset received from the topic.
+    received: u64,
+}
+
+#[derive(Debug)]
+struct Shared {
+    broker: Arc<Broker>,
+    topic: String,
+    name: String,
+    state: Mutex<State>,
+    /// Set for the consumers of a group member's partitions.
+    fence: Option<Fence>,
+}
+
+impl Shared {
+    /// Commits the offset before which every message was acked or
+    /// dead-lettered, so a restarted consumer resumes at the oldest message
+    /// still owed a delivery.
+    fn commit(&self, state: &State) -> Result<()> {
+        if let Some(fence) = &self.fence {
+            if !fence.is_current() {
+                return Ok(());
+            }
+        }
+        let offset = state
+            .in_flight
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(state.received);
+        self.broker.commit(&self.topic, &self.name, offset)
+    }
+}
+
+/// A named consumer of a topic with at-least-once delivery: every message is
+/// delivered until it is acked, or dead-lettered after
+/// [`ConsumerConfig::max_deliveries`].
+#[derive(Debug)]
+pub struct Consumer {
+    shared: Arc<Shared>,
+    config: ConsumerConfig,
+    subscription: Subscription,
+}
+
+impl Consumer {
+    pub(crate) fn new(
+        broker: Arc<Broker>,
+        topic: &str,
+        name: &str,
+        config: ConsumerConfig,
+        fence: Option<Fence>,
+    ) -> Result<Self> {
+        let received = broker.committed(topic, name)?.unwrap_or(0);
+        let subscription = broker.subscribe(topic, Some(received), true)?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                broker,
+                topic: topic.to_owned(),
+                name: name.to_owned(),
+                state: Mutex::new(State {
+                    in_flight: BTreeMap::new(),
+                    received,
+                }),
+                fence,
+            }),
+            config,
+            subscription,
+        })
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.shared.topic
+    }
+
+    /// Waits for the next delivery: a message whose visibility timeout
+    /// expired, or else the next one published.
+    pub async fn recv(&mut self) -> Result<Delivery> {
+        loop {
+            let deadline = match self.redeliver()? {
+                Redelivery::Now(delivery) => return Ok(delivery),
+                Redelivery::Next(deadline) => deadline,
+            };
+            let message = tokio::select! {
+                message = self.subscription.recv() => message,
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => continue,
+            };
+            // The consumer keeps the broker, and with it the topic, alive,
+            // so this is only a safeguard.
+            let Some(message) = message else {
+                return Err(Error::Closed(self.shared.topic.clone()));
+            };
+            let mut state = lock(&self.shared.state);
+            state.received = message.offset + 1;
+            state.in_flight.insert(
+                message.offset,
+                InFlight {
+                    message: message.clone(),
+                    deliveries: 1,
+                    visible_at: Instant::now() + self.config.visibility_timeout,
+                },
+            );
+            return Ok(Delivery {
+                shared: Arc::clone(&self.shared),
+                message,
+                attempt: 1,
+            });
+        }
+    }
+
+    /// Redelivers the oldest message whose visibility timeout expired,
+    /// dead-lettering those out of deliveries on the way. Otherwise returns
+    /// when the next one expires.
+    fn redeliver(&self) -> Result<Redelivery> {
+        let mut state = lock(&self.shared.state);
+        let now = Instant::now();
+        loop {
+            let Some((&offset, in_flight)) = state
+                .in_flight
+                .iter_mut()
+                .find(|(_, in_flight)| in_flight.visible_at <= now)
+            else {
+                let deadline = state.in_flight.values().map(|i| i.visible_at).min();
+                return Ok(Redelivery::Next(deadline));
+            };
+            if in_flight.deliveries < self.config.max_deliveries {
+                in_flight.deliveries += 1;
+                in_flight.visible_at = now + self.config.visibility_timeout;
+                return Ok(Redelivery::Now(Delivery {
+                    shared: Arc::clone(&self.shared),
+                    message: in_flight.message.clone(),
+                    attempt: in_flight.deliveries,
+                }));
+            }
+            // The message stays in flight, to be dead-lettered on the next
+            // call, unless it reaches the dead letter topic.
+            let topic = dead_letter_topic(&self.shared.topic);
+            self.shared
+                .broker
+                .publish(&topic, &in_flight.message.payload)?;
+            state.in_flight.remove(&offset);
+            self.shared.commit(&state)?;
+        }
+    }
+}
+
+enum Redelivery {
+    Now(Delivery),
+    /// When the earliest in-flight message becomes visible again, if any.
+    Next(Option<Instant>),
+}
+
+/// A delivered message, to be acked once handled or nacked to have it
+/// delivered again right away. Dropping it unacked leaves the message to be
+/// redelivered after the visibility timeout.
+#[derive(Debug)]
+pub struct Delivery {
+    shared: Arc<Shared>,
+    message: Message,
+    attempt: u32,
+}
+
+impl Delivery {
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    /// 1 on the first delivery of the message, 2 on the second, and so on.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub async fn ack(self) -> Result<()> {
+        let mut state = lock(&self.shared.state);
+        if state.in_flight.remove(&self.message.offset).is_some() {
+            self.shared.commit(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the message visible again, unless it was redelivered since.
+    pub async fn nack(self) -> Result<()> {
+        let mut state = lock(&self.shared.state);
+        if let Some(in_flight) = state.in_flight.get_mut(&self.message.offset) {
+            if in_flight.deliveries == self.attempt {
+                in_flight.visible_at = Instant::now();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        log::test::TempDir, LogConfig, MessageQueue, MessageQueueConnection, OverflowPolicy,
+        TopicConfig,
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(20);
+
+    fn config(max_deliveries: u32) -> ConsumerConfig {
+        ConsumerConfig {
+            visibility_timeout: TIMEOUT,
+            max_deliveries,
+        }
+    }
+
+    async fn publish(message_queue: &MessageQueue, payloads: &[&str]) {
+        for payload in payloads {
+            message_queue.publish("frog", *payload).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ack() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        publish(&message_queue, &["1", "2"]).await;
+        let mut consumer = message_queue
+            .consumer("frog", "eater", config(3))
+            .await
+            .unwrap();
+        let first = consumer.recv().await.unwrap();
+        let second = consumer.recv().await.unwrap();
+        assert_eq!((first.message().offset, first.attempt()), (0, 1));
+        assert_eq!((second.message().offset, second.attempt()), (1, 1));
+        second.ack().await.unwrap();
+        // The first message is still owed an ack.
+        assert_eq!(
+            message_queue.committed("frog", "eater").await.unwrap(),
+            Some(0)
+        );
+        first.ack().await.unwrap();
+        assert_eq!(
+            message_queue.committed("frog", "eater").await.unwrap(),
+            Some(2)
+        );
+        let result = time::timeout(TIMEOUT * 3, consumer.recv()).await;
+        assert!(result.is_err(), "acked messages are not redelivered");
+    }
+
+    #[tokio::test]
+    async fn test_nack_redelivers_at_once() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        publish(&message_queue, &["1"]).await;
+        let mut consumer = message_queue
+            .consumer("frog", "eater", ConsumerConfig::default())
+            .await
+            .unwrap();
+        consumer.recv().await.unwrap().nack().await.unwrap();
+        let delivery = time::timeout(TIMEOUT, consumer.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((delivery.message().offset, delivery.attempt()), (0, 2));
+    }
+
+    #[tokio::test]
+    async fn test_visibility_timeout_redelivers() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut consumer = message_queue
+            .consumer("frog", "eater", config(3))
+            .await
+            .unwrap();
+        publish(&message_queue, &["1"]).await;
+        let start = Instant::now();
+        drop(consumer.recv().await.unwrap());
+        let delivery = consumer.recv().await.unwrap();
+        assert!(start.elapsed() >= TIMEOUT);
+        assert_eq!(
+            (delivery.message().payload.as_slice(), delivery.attempt()),
+            (b"1".as_slice(), 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter_and_replay() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        publish(&message_queue, &["poison", "1"]).await;
+        let mut consumer = message_queue
+            .consumer("frog", "eater", config(2))
+            .await
+            .unwrap();
+        let mut attempts = Vec::new();
+        for _ in 0..3 {
+            let delivery = consumer.recv().await.unwrap();
+            attempts.push((delivery.message().offset, delivery.attempt()));
+            if delivery.message().payload == b"poison" {
+                delivery.nack().await.unwrap();
+            } else {
+                delivery.ack().await.unwrap();
+            }
+        }
+        assert_eq!(attempts, [(0, 1), (0, 2), (1, 1)]);
+        // The poison message is dead-lettered on its next due delivery.
+        let result = time::timeout(TIMEOUT, consumer.recv()).await;
+        assert!(result.is_err());
+        assert_eq!(
+            message_queue.committed("frog", "eater").await.unwrap(),
+            Some(2)
+        );
+        let dead_letters = message_queue.dead_letters("frog", 0, 10).await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].payload, b"poison");
+
+        assert_eq!(message_queue.replay_dead_letters("frog").await.unwrap(), 1);
+        assert_eq!(message_queue.replay_dead_letters("frog").await.unwrap(), 0);
+        let delivery = consumer.recv().await.unwrap();
+        assert_eq!((delivery.message().offset, delivery.attempt()), (2, 1));
+        assert_eq!(delivery.message().payload, b"poison");
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter_kept_until_published() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let full = TopicConfig {
+            capacity: Some(1),
+            overflow: OverflowPolicy::Error,
+        };
+        message_queue
+            .configure_topic("frog.dlq", full)
+            .await
+            .unwrap();
+        let mut dead_letters = message_queue.subscribe("frog.dlq").await.unwrap();
+        message_queue.publish("frog.dlq", "earlier").await.unwrap();
+        publish(&message_queue, &["poison"]).await;
+        let mut consumer = message_queue
+            .consumer("frog", "eater", config(1))
+            .await
+            .unwrap();
+        consumer.recv().await.unwrap().nack().await.unwrap();
+        assert!(matches!(consumer.recv().await, Err(Error::Full(_))));
+        assert_ne!(
+            message_queue.committed("frog", "eater").await.unwrap(),
+            Some(1)
+        );
+        // Once the dead letter topic has room, the message is moved there.
+        assert_eq!(dead_letters.recv().await.unwrap().payload, b"earlier");
+        let result = time::timeout(TIMEOUT, consumer.recv()).await;
+        assert!(result.is_err());
+        let dead_letter = time::timeout(TIMEOUT, dead_letters.recv()).await.unwrap();
+        assert_eq!(dead_letter.unwrap().payload, b"poison");
+        assert_eq!(
+            message_queue.committed("frog", "eater").await.unwrap(),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resumes_at_oldest_unacked() {
+        let dir = TempDir::new("consumer");
+        let durable = || {
+            MessageQueue::new(
+                MessageQueueConnection::durable(&dir.0, LogConfig::default()).unwrap(),
+            )
+        };
+        let message_queue = durable();
+        publish(&message_queue, &["1", "2", "3"]).await;
+        let mut consumer = message_queue
+            .consumer("frog", "eater", ConsumerConfig::default())
+            .await
+            .unwrap();
+        let first = consumer.recv().await.unwrap();
+        let second = consumer.recv().await.unwrap();
+        first.ack().await.unwrap();
+        drop((second, consumer, message_queue));
+
+        let message_queue = durable();
+        let mut consumer = message_queue
+            .consumer("frog", "eater", ConsumerConfig::default())
+            .await
+            .unwrap();
+        let delivery = consumer.recv().await.unwrap();
+        assert_eq!((delivery.message().offset, delivery.attempt()), (1, 1));
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    collections::BTreeSet,
+    future::{self, Future},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+
+use tokio::sync::watch;
+
+use crate::{
+    broker::{self, Broker},
+    consumer::{Consumer, ConsumerConfig, Delivery},
+    lock, Error, Result,
+};
+
+/// A topic split into partitions, each kept as the topic `<name>.<n>`.
+/// Messages with the same key go to the same partition, so they are consumed
+/// in publish order. Every publisher and group of a topic must agree on its
+/// partition count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionedTopic {
+    name: String,
+    partitions: u32,
+}
+
+impl PartitionedTopic {
+    pub fn new(name: impl Into<String>, partitions: u32) -> Result<Self> {
+        let name = name.into();
+        broker::validate(&name)?;
+        if partitions == 0 {
+            return Err(Error::InvalidPartitions {
+                topic: name,
+                partitions,
+            });
+        }
+        Ok(Self { name, partitions })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn partitions(&self) -> u32 {
+        self.partitions
+    }
+
+    /// 64-bit FNV-1a of the key, so keys map to the same partition across
+    /// restarts and Rust releases.
+    pub fn partition(&self, key: &[u8]) -> u32 {
+        let hash = key.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        (hash % u64::from(self.partitions)) as u32
+    }
+
+    pub fn partition_topic(&self, partition: u32) -> String {
+        format!("{}.{partition}", self.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Assignment {
+    /// Bumped on every rebalance.
+    generation: u64,
+    /// The member owning each partition, if there are any members.
+    owners: Vec<Option<u64>>,
+}
+
+#[derive(Debug)]
+struct Members {
+    next_id: u64,
+    ids: BTreeSet<u64>,
+}
+
+/// The members of a consumer group of one topic and their partitions.
+#[derive(Debug)]
+pub(crate) struct Group {
+    topic: PartitionedTopic,
+    name: String,
+    members: Mutex<Members>,
+    assignment: watch::Sender<Assignment>,
+}
+
+impl Group {
+    pub(crate) fn new(topic: PartitionedTopic, name: &str) -> Result<Self> {
+        broker::validate(name)?;
+        let (assignment, _) = watch::channel(Assignment {
+            generation: 0,
+            owners: vec![None; topic.partitions as usize],
+        });
+        Ok(Self {
+            topic,
+            name: name.to_owned(),
+            members: Mutex::new(Members {
+                next_id: 0,
+                ids: BTreeSet::new(),
+            }),
+            assignment,
+        })
+    }
+
+    pub(crate) fn topic(&self) -> &PartitionedTopic {
+        &self.topic
+    }
+
+    fn join(&self) -> u64 {
+        let mut members = lock(&self.members);
+        let id = members.next_id;
+        members.next_id += 1;
+        members.ids.insert(id);
+        self.rebalance(&members.ids);
+        id
+    }
+
+    fn leave(&self, id: u64) {
+        let mut members = lock(&self.members);
+        members.ids.remove(&id);
+        self.rebalance(&members.ids);
+    }
+
+    /// Deals the partitions out round-robin over the members in join order.
+    fn rebalance(&self, ids: &BTreeSet<u64>) {
+        let ids = ids.iter().copied().collect::<Vec<_>>();
+        let generation = self.assignment.borrow().generation + 1;
+        let owners = (0..self.topic.partitions as usize)
+            .map(|partition| ids.get(partition % ids.len().max(1)).copied())
+            .collect();
+        self.assignment
+            .send_replace(Assignment { generation, owners });
+    }
+}
+
+/// Ties a partition consumer to the rebalance generation it was created in.
+/// Once the group rebalances its acks are dropped rather than committed, as
+/// the partition's new owner resumes from the last committed offset.
+#[derive(Debug)]
+pub(crate) struct Fence {
+    assignment: watch::Receiver<Assignment>,
+    generation: u64,
+}
+
+impl Fence {
+    pub(crate) fn is_current(&self) -> bool {
+        self.assignment.borrow().generation == self.generation
+    }
+}
+
+/// A member of a consumer group, consuming the partitions assigned to it.
+/// Every rebalance revokes all partitions and reassigns them, so messages not
+/// yet acked by then are delivered again, possibly to another member.
+/// Dropping the member leaves the group.
+#[derive(Debug)]
+pub struct GroupMember {
+    group: Arc<Group>,
+    broker: Arc<Broker>,
+    id: u64,
+    config: ConsumerConfig,
+    assignment: watch::Receiver<Assignment>,
+    /// The consumers of the assigned partitions.
+    consumers: Vec<Consumer>,
+    /// Which consumer `recv` polls first, so no partition starves the rest.
+    next: usize,
+}
+
+impl GroupMember {
+    pub(crate) fn join(
+        group: Arc<Group>,
+        broker: Arc<Broker>,
+        config: ConsumerConfig,
+    ) -> Result<Self> {
+        let id = group.join();
+        let assignment = group.assignment.subscribe();
+        let mut member = Self {
+            group,
+            broker,
+            id,
+            config,
+            assignment,
+            consumers: Vec::new(),
+            next: 0,
+        };
+        member.assign()?;
+        Ok(member)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The partitions currently assigned to the member.
+    pub fn partitions(&self) -> Vec<u32> {
+        let assignment = self.assignment.borrow();
+        (0..)
+            .zip(&assignment.owners)
+            .filter(|(_, owner)| **owner == Some(self.id))
+            .map(|(partition, _)| partition)
+            .collect()
+    }
+
+    /// Waits for the next delivery from any assigned partition, switching
+    /// partitions first if the group rebalanced.
+    pub async fn recv(&mut self) -> Result<Delivery> {
+        enum Next {
+            Delivery(Result<Delivery>),
+            Rebalanced,
+        }
+
+        loop {
+            if self.assignment.has_changed().unwrap_or(false) {
+                self.assign()?;
+            }
+            let start = self.next % self.consumers.len().max(1);
+            self.next = self.next.wrapping_add(1);
+            let (before, after) = self.consumers.split_at_mut(start);
+            let mut receives = after
+                .iter_mut()
+                .chain(before)
+                .map(|consumer| Box::pin(consumer.recv()))
+                .collect::<Vec<_>>();
+            let mut changed = Box::pin(self.assignment.changed());
+            let next = future::poll_fn(|cx| {
+                if changed.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Next::Rebalanced);
+                }
+                for receive in &mut receives {
+                    if let Poll::Ready(delivery) = Pin::new(receive).poll(cx) {
+                        return Poll::Ready(Next::Delivery(delivery));
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+            if let Next::Delivery(delivery) = next {
+                return delivery;
+            }
+        }
+    }
+
+    /// Replaces the partition consumers with ones for the latest assignment.
+    fn assign(&mut self) -> Result<()> {
+        let generation = self.assignment.borrow_and_update().generation;
+        self.consumers.clear();
+        for partition in self.partitions() {
+            let fence = Fence {
+                assignment: self.group.assignment.subscribe(),
+                generation,
+            };
+            let consumer = Consumer::new(
+                Arc::clone(&self.broker),
+                &self.group.topic.partition_topic(partition),
+                &self.group.name,
+                self.config.clone(),
+                Some(fence),
+            )?;
+            self.consumers.push(consumer);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GroupMember {
+    fn drop(&mut self) {
+        self.group.leave(self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use super::*;
+    use crate::{MessageQueue, MessageQueueConnection};
+
+    fn topic() -> PartitionedTopic {
+        PartitionedTopic::new("snake", 4).unwrap()
+    }
+
+    fn config() -> ConsumerConfig {
+        ConsumerConfig {
+            visibility_timeout: Duration::from_secs(60),
+            max_deliveries: 5,
+        }
+    }
+
+    /// Publishes keys 0..count and returns the partition of each.
+    async fn publish(message_queue: &MessageQueue, count: u64) -> Vec<u32> {
+        let mut partitions = Vec::new();
+        for key in 0..count {
+            let (partition, _) = message_queue
+                .publish_keyed(&topic(), key.to_le_bytes(), key.to_le_bytes())
+                .await
+                .unwrap();
+            partitions.push(partition);
+        }
+        partitions
+    }
+
+    fn key(delivery: &Delivery) -> u64 {
+        u64::from_le_bytes(delivery.message().payload.as_slice().try_into().unwrap())
+    }
+
+    #[test]
+    fn test_partition_is_stable() {
+        let topic = topic();
+        assert_eq!(topic.partition(b"snake-1"), topic.partition(b"snake-1"));
+        let partitions = (0..64_u64)
+            .map(|key| topic.partition(&key.to_le_bytes()))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(partitions, (0..4).collect());
+        assert_eq!(topic.partition_topic(3), "snake.3");
+        assert!(matches!(
+            PartitionedTopic::new("snake", 0),
+            Err(Error::InvalidPartitions { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_single_member_keeps_key_order() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut member = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        assert_eq!(member.partitions(), [0, 1, 2, 3]);
+        for round in 0..3_u8 {
+            message_queue
+                .publish_keyed(&topic(), "snake-1", [round])
+                .await
+                .unwrap();
+        }
+        for round in 0..3_u8 {
+            let delivery = member.recv().await.unwrap();
+            assert_eq!(delivery.message().payload, [round]);
+            delivery.ack().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_on_join_and_leave() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut first = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        let second = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        assert_eq!(first.partitions(), [0, 2]);
+        assert_eq!(second.partitions(), [1, 3]);
+
+        let partitions = publish(&message_queue, 32).await;
+        let odd = partitions.iter().filter(|p| *p % 2 == 1).count();
+        // Members are Send, so each can run on a worker of its own.
+        let second = tokio::spawn(async move {
+            let mut second = second;
+            let mut keys = Vec::new();
+            for _ in 0..odd {
+                let delivery = second.recv().await.unwrap();
+                keys.push(key(&delivery));
+                delivery.ack().await.unwrap();
+            }
+            (second, keys)
+        });
+        let (second, keys) = second.await.unwrap();
+        assert!(keys.iter().all(|key| partitions[*key as usize] % 2 == 1));
+
+        // Everything left unacked is for the first member, which takes over
+        // all partitions once the second leaves.
+        drop(second);
+        assert_eq!(first.partitions(), [0, 1, 2, 3]);
+        let mut keys = Vec::new();
+        for _ in 0..partitions.len() - odd {
+            let delivery = first.recv().await.unwrap();
+            keys.push(key(&delivery));
+            delivery.ack().await.unwrap();
+        }
+        assert!(keys.iter().all(|key| partitions[*key as usize] % 2 == 0));
+        let result = time::timeout(Duration::from_millis(50), first.recv()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unacked_messages_move_on_rebalance() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut first = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        let topic = topic();
+        let key = (0..)
+            .map(u64::to_le_bytes)
+            .find(|key| topic.partition(key) == 1)
+            .unwrap();
+        message_queue.publish_keyed(&topic, key, "1").await.unwrap();
+        let stale = first.recv().await.unwrap();
+
+        let mut second = message_queue
+            .join_group(&topic, "digest", config())
+            .await
+            .unwrap();
+        // The ack is from before the rebalance, so it is not committed.
+        stale.ack().await.unwrap();
+        assert_eq!(
+            message_queue
+                .committed(&topic.partition_topic(1), "digest")
+                .await
+                .unwrap(),
+            None
+        );
+        let delivery = second.recv().await.unwrap();
+        assert_eq!(delivery.message().topic, "snake.1");
+        assert_eq!((delivery.message().offset, delivery.attempt()), (0, 1));
+        delivery.ack().await.unwrap();
+        assert_eq!(
+            message_queue
+                .committed(&topic.partition_topic(1), "digest")
+                .await
+                .unwrap(),
+            Some(1)
+        );
+        drop(first);
+    }
+
+    #[tokio::test]
+    async fn test_partition_count_must_match() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let _member = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        let other = PartitionedTopic::new("snake", 2).unwrap();
+        assert!(matches!(
+            message_queue.join_group(&other, "digest", config()).await,
+            Err(Error::InvalidPartitions { partitions: 2, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_leave_after_poisoning() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let first = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        let second = message_queue
+            .join_group(&topic(), "digest", config())
+            .await
+            .unwrap();
+        std::thread::scope(|scope| {
+            let poisoner = scope.spawn(|| {
+                let _members = lock(&first.group.members);
+                panic!("poisoning the members lock");
+            });
+            assert!(poisoner.join().is_err());
+        });
+        assert!(first.group.members.is_poisoned());
+
+        drop(second);
+        assert_eq!(first.partitions(), [0, 1, 2, 3]);
+    }
+}
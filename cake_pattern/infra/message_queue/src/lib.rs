@@ -1,4 +1,147 @@
-pub struct MessageQueueConnection;
+use std::{
+    io,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use thiserror::Error;
+
+mod broker;
+mod consumer;
+mod envelope;
+mod group;
+mod log;
+mod queue;
+mod schedule;
+
+use broker::Broker;
+pub use broker::{Message, Subscription};
+use consumer::dead_letter_topic;
+pub use consumer::{Consumer, ConsumerConfig, Delivery};
+pub use envelope::{
+    correlate, correlate_sync, correlation_id, Bincode, Codec, Codecs, DefaultCodecs, Envelope,
+    Json, MessageID, Text,
+};
+pub use group::{GroupMember, PartitionedTopic};
+pub use log::{FsyncPolicy, LogConfig};
+pub use queue::{OverflowPolicy, TopicConfig, TopicStats};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid topic or consumer name {0:?}")]
+    InvalidName(String),
+    #[error("invalid message queue url {0:?}")]
+    InvalidUrl(String),
+    #[error("topic {0:?} is full")]
+    Full(String),
+    #[error("topic {0:?} is closed")]
+    Closed(String),
+    #[error("invalid partition count {partitions} for topic {topic:?}")]
+    InvalidPartitions { topic: String, partitions: u32 },
+    #[error("{path:?} is corrupt at byte {position}")]
+    Corrupt { path: PathBuf, position: u64 },
+    #[error("invalid message id {0:?}")]
+    InvalidMessageID(String),
+    #[error("invalid envelope")]
+    InvalidEnvelope(#[source] bincode::Error),
+    #[error("unsupported content type {0:?}")]
+    UnsupportedContentType(String),
+    #[error("{content_type} codec failed")]
+    Codec {
+        content_type: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Locks the mutex even if a panic poisoned it. Every change made under the
+/// crate's locks leaves what they guard whole, and the paths that must run
+/// regardless, such as closing topics and leaving groups on drop, could not
+/// report a poisoned lock anyway.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Selects where messages are kept, parsed from `memory` or `file:<dir>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageQueueConfig {
+    Memory,
+    File(PathBuf),
+}
+
+impl FromStr for MessageQueueConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "memory" => Ok(Self::Memory),
+            Some(("file", dir)) if !dir.is_empty() => Ok(Self::File(dir.into())),
+            _ => Err(Error::InvalidUrl(s.to_owned())),
+        }
+    }
+}
+
+/// The history an in-memory topic keeps by default, beyond which its oldest
+/// messages are deleted.
+pub const MEMORY_RETENTION_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A connection to an in-process broker; clones share its topics.
+#[derive(Debug, Clone)]
+pub struct MessageQueueConnection {
+    broker: Arc<Broker>,
+}
+
+impl Default for MessageQueueConnection {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl MessageQueueConnection {
+    pub fn open(config: &MessageQueueConfig) -> Result<Self> {
+        match config {
+            MessageQueueConfig::Memory => Ok(Self::in_memory()),
+            MessageQueueConfig::File(dir) => Self::durable(dir, LogConfig::default()),
+        }
+    }
+
+    /// Keeps every topic's history in memory for as long as the broker
+    /// lives, up to [`MEMORY_RETENTION_BYTES`] of it.
+    pub fn in_memory() -> Self {
+        Self::in_memory_with(LogConfig {
+            retention_bytes: Some(MEMORY_RETENTION_BYTES),
+            ..LogConfig::default()
+        })
+    }
+
+    /// Like [`in_memory`](Self::in_memory), deleting history beyond the
+    /// retention limits of `config` instead, whose other settings apply to
+    /// segment logs only.
+    pub fn in_memory_with(config: LogConfig) -> Self {
+        Self {
+            broker: Arc::new(Broker::in_memory(config)),
+        }
+    }
+
+    /// Keeps every topic in a segment log under `dir`, so messages and
+    /// consumer offsets survive restarts. In-memory topics keep their
+    /// history only while the broker lives.
+    ///
+    /// Messages scheduled for later are persisted too, and those that came
+    /// due while the broker was down are published when it is opened inside
+    /// a Tokio runtime, or else on the first message scheduled.
+    pub fn durable(dir: impl Into<PathBuf>, config: LogConfig) -> Result<Self> {
+        let broker = Arc::new(Broker::durable(dir.into(), config)?);
+        broker.scheduler().start(Arc::downgrade(&broker));
+        Ok(Self { broker })
+    }
+}
 
 pub struct MessageQueue {
     conn: MessageQueueConnection,
@@ -12,4 +155,369 @@ impl MessageQueue {
     pub fn conn(&self) -> &MessageQueueConnection {
         &self.conn
     }
+
+    /// Delivers the payload to every current subscriber of the topic and
+    /// returns its offset in the topic.
+    pub async fn publish(&self, topic: &str, payload: impl Into<Vec<u8>>) -> Result<u64> {
+        self.conn
+            .broker
+            .publish_waiting(topic, &payload.into())
+            .await
+    }
+
+    /// Bounds the topic's subscriber queues, see [`TopicConfig`]. Topics are
+    /// unbounded until configured.
+    pub async fn configure_topic(&self, topic: &str, config: TopicConfig) -> Result<()> {
+        self.conn.broker.configure(topic, config)
+    }
+
+    pub async fn topic_stats(&self, topic: &str) -> Result<TopicStats> {
+        self.conn.broker.stats(topic)
+    }
+
+    /// Publishes the payload once `at` has come; until then no consumer
+    /// sees it. Messages due at the same time are published in the order
+    /// they were scheduled.
+    pub async fn publish_at(
+        &self,
+        topic: &str,
+        at: SystemTime,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        let broker = &self.conn.broker;
+        broker.scheduler().schedule(topic, at, payload.into())?;
+        broker.scheduler().start(Arc::downgrade(broker));
+        Ok(())
+    }
+
+    /// Publishes the payload once `delay` has passed, see
+    /// [`MessageQueue::publish_at`].
+    pub async fn publish_after(
+        &self,
+        topic: &str,
+        delay: Duration,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        self.publish_at(topic, SystemTime::now() + delay, payload)
+            .await
+    }
+
+    /// Publishes the envelope and returns its offset in the topic.
+    pub async fn publish_envelope(&self, topic: &str, envelope: &Envelope) -> Result<u64> {
+        self.publish(topic, envelope.to_bytes()?).await
+    }
+
+    /// Publishes the payload unless the producer already published this or a
+    /// later sequence number to the topic, so a producer retrying after a
+    /// failure publishes each message once. Returns the offset, or `None` for
+    /// a duplicate. Sequence numbers must increase per producer and topic.
+    pub async fn publish_once(
+        &self,
+        topic: &str,
+        producer: &str,
+        sequence: u64,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<Option<u64>> {
+        self.conn
+            .broker
+            .publish_once(topic, producer, sequence, &payload.into())
+            .await
+    }
+
+    /// Subscribes to the messages published to the topic from now on.
+    pub async fn subscribe(&self, topic: &str) -> Result<Subscription> {
+        self.conn.broker.subscribe(topic, None, false)
+    }
+
+    /// Replays the logged messages from `offset` on, then continues with live
+    /// ones. Messages already deleted by retention are skipped.
+    pub async fn subscribe_from(&self, topic: &str, offset: u64) -> Result<Subscription> {
+        self.conn.broker.subscribe(topic, Some(offset), false)
+    }
+
+    /// Resumes the consumer after the offset it last committed, or replays
+    /// the whole topic if it never committed one.
+    pub async fn consume(&self, topic: &str, consumer: &str) -> Result<Subscription> {
+        let offset = self.conn.broker.committed(topic, consumer)?;
+        self.subscribe_from(topic, offset.unwrap_or(0)).await
+    }
+
+    /// Records that the consumer is done with every message before `offset`.
+    pub async fn commit(&self, topic: &str, consumer: &str, offset: u64) -> Result<()> {
+        self.conn.broker.commit(topic, consumer, offset)
+    }
+
+    pub async fn committed(&self, topic: &str, consumer: &str) -> Result<Option<u64>> {
+        self.conn.broker.committed(topic, consumer)
+    }
+
+    /// Returns up to `max` logged messages from `offset` on.
+    pub async fn read(&self, topic: &str, offset: u64, max: usize) -> Result<Vec<Message>> {
+        self.conn.broker.read(topic, offset, max)
+    }
+
+    /// Starts or resumes a consumer that acks each message; see [`Consumer`].
+    pub async fn consumer(
+        &self,
+        topic: &str,
+        consumer: &str,
+        config: ConsumerConfig,
+    ) -> Result<Consumer> {
+        Consumer::new(Arc::clone(&self.conn.broker), topic, consumer, config, None)
+    }
+
+    /// Publishes the payload to the key's partition and returns the partition
+    /// and the offset in it.
+    pub async fn publish_keyed(
+        &self,
+        topic: &PartitionedTopic,
+        key: impl AsRef<[u8]>,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<(u32, u64)> {
+        let partition = topic.partition(key.as_ref());
+        let offset = self
+            .publish(&topic.partition_topic(partition), payload)
+            .await?;
+        Ok((partition, offset))
+    }
+
+    /// Joins the consumer group, which rebalances the topic's partitions over
+    /// its members; see [`GroupMember`]. Offsets are committed per partition
+    /// under the group name.
+    pub async fn join_group(
+        &self,
+        topic: &PartitionedTopic,
+        group: &str,
+        config: ConsumerConfig,
+    ) -> Result<GroupMember> {
+        let group = self.conn.broker.group(topic, group)?;
+        GroupMember::join(group, Arc::clone(&self.conn.broker), config)
+    }
+
+    /// Returns up to `max` of the topic's dead-lettered messages from
+    /// `offset` on.
+    pub async fn dead_letters(&self, topic: &str, offset: u64, max: usize) -> Result<Vec<Message>> {
+        self.read(&dead_letter_topic(topic), offset, max).await
+    }
+
+    /// Publishes the dead letters not yet replayed back to the topic and
+    /// returns how many there were.
+    pub async fn replay_dead_letters(&self, topic: &str) -> Result<usize> {
+        let dead_letters = dead_letter_topic(topic);
+        let offset = self.committed(&dead_letters, "replay").await?.unwrap_or(0);
+        let messages = self.read(&dead_letters, offset, usize::MAX).await?;
+        for message in &messages {
+            self.publish(topic, message.payload.clone()).await?;
+            self.commit(&dead_letters, "replay", message.offset + 1)
+                .await?;
+        }
+        Ok(messages.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{future, pin::Pin};
+
+    use futures_core::Stream;
+
+    use super::*;
+    use crate::{broker::REPLAY_PAGE, log::test::TempDir};
+
+    fn message_queue() -> MessageQueue {
+        MessageQueue::new(MessageQueueConnection::in_memory())
+    }
+
+    fn payloads(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| std::str::from_utf8(&message.payload).unwrap())
+            .collect()
+    }
+
+    async fn next(subscription: &mut Subscription) -> Option<Message> {
+        future::poll_fn(|cx| Pin::new(&mut *subscription).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_receive_in_order() {
+        let message_queue = message_queue();
+        let mut first = message_queue.subscribe("snake").await.unwrap();
+        let mut second = message_queue.subscribe("snake").await.unwrap();
+        for payload in ["1", "2", "3"] {
+            message_queue.publish("snake", payload).await.unwrap();
+        }
+        for subscription in [&mut first, &mut second] {
+            let mut messages = Vec::new();
+            for _ in 0..3 {
+                messages.push(subscription.recv().await.unwrap());
+            }
+            assert_eq!(payloads(&messages), ["1", "2", "3"]);
+            assert_eq!(
+                messages.iter().map(|m| m.offset).collect::<Vec<_>>(),
+                [0, 1, 2]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_topics_are_separate() {
+        let message_queue = message_queue();
+        let mut snake = message_queue.subscribe("snake").await.unwrap();
+        let mut frog = message_queue.subscribe("frog").await.unwrap();
+        message_queue.publish("frog", "eaten").await.unwrap();
+        message_queue.publish("snake", "registered").await.unwrap();
+        let message = frog.recv().await.unwrap();
+        assert_eq!((message.topic.as_str(), message.offset), ("frog", 0));
+        let message = snake.recv().await.unwrap();
+        assert_eq!((message.topic.as_str(), message.offset), ("snake", 0));
+        assert_eq!(message.payload, b"registered");
+    }
+
+    #[tokio::test]
+    async fn test_late_subscriber_misses_earlier_messages() {
+        let message_queue = message_queue();
+        message_queue.publish("slug", "early").await.unwrap();
+        let mut subscription = message_queue.subscribe("slug").await.unwrap();
+        message_queue.publish("slug", "late").await.unwrap();
+        let message = subscription.recv().await.unwrap();
+        assert_eq!((message.offset, message.payload), (1, b"late".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_with_broker() {
+        let message_queue = message_queue();
+        let mut subscription = message_queue.subscribe("snake").await.unwrap();
+        message_queue.publish("snake", "last").await.unwrap();
+        drop(message_queue);
+        assert_eq!(next(&mut subscription).await.unwrap().payload, b"last");
+        assert_eq!(next(&mut subscription).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_name() {
+        let message_queue = message_queue();
+        for name in ["", ".hidden", "snake/slug", "frog eaten"] {
+            assert!(matches!(
+                message_queue.publish(name, "").await,
+                Err(Error::InvalidName(_))
+            ));
+            assert!(matches!(
+                message_queue.subscribe(name).await,
+                Err(Error::InvalidName(_))
+            ));
+            assert!(matches!(
+                message_queue.commit("snake", name, 0).await,
+                Err(Error::InvalidName(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_once() {
+        let message_queue = message_queue();
+        let publish =
+            |sequence, payload| message_queue.publish_once("snake", "outbox", sequence, payload);
+        assert_eq!(publish(3, "3").await.unwrap(), Some(0));
+        assert_eq!(publish(3, "3").await.unwrap(), None);
+        assert_eq!(publish(1, "1").await.unwrap(), None);
+        assert_eq!(publish(7, "7").await.unwrap(), Some(1));
+        assert_eq!(
+            payloads(&message_queue.read("snake", 0, 10).await.unwrap()),
+            ["3", "7"]
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        assert_eq!(
+            "memory".parse::<MessageQueueConfig>().unwrap(),
+            MessageQueueConfig::Memory
+        );
+        assert_eq!(
+            "file:data/events".parse::<MessageQueueConfig>().unwrap(),
+            MessageQueueConfig::File("data/events".into())
+        );
+        assert!(matches!(
+            "kafka://localhost".parse::<MessageQueueConfig>(),
+            Err(Error::InvalidUrl(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_history() {
+        let message_queue = message_queue();
+        for payload in ["1", "2"] {
+            message_queue.publish("snake", payload).await.unwrap();
+        }
+        let mut subscription = message_queue.subscribe_from("snake", 1).await.unwrap();
+        assert_eq!(subscription.recv().await.unwrap().payload, b"2");
+        message_queue.commit("snake", "reader", 1).await.unwrap();
+        let mut subscription = message_queue.consume("snake", "reader").await.unwrap();
+        assert_eq!(subscription.recv().await.unwrap().payload, b"2");
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_retention() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory_with(LogConfig {
+            retention_bytes: Some(2),
+            ..LogConfig::default()
+        }));
+        for payload in ["1", "2", "3"] {
+            message_queue.publish("snake", payload).await.unwrap();
+        }
+        let messages = message_queue.read("snake", 0, 10).await.unwrap();
+        assert_eq!(payloads(&messages), ["2", "3"]);
+        assert_eq!(messages[0].offset, 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay_spans_pages() {
+        let message_queue = message_queue();
+        let count = 2 * REPLAY_PAGE + 1;
+        for n in 0..count {
+            message_queue.publish("snake", n.to_string()).await.unwrap();
+        }
+        let mut subscription = message_queue.subscribe_from("snake", 1).await.unwrap();
+        message_queue.publish("snake", "live").await.unwrap();
+        for offset in 1..count as u64 {
+            assert_eq!(subscription.recv().await.unwrap().offset, offset);
+        }
+        assert_eq!(subscription.recv().await.unwrap().payload, b"live");
+    }
+
+    #[tokio::test]
+    async fn test_durable_consumer_resumes() {
+        let dir = TempDir::new("resume");
+        let durable = || {
+            MessageQueue::new(
+                MessageQueueConnection::durable(&dir.0, LogConfig::default()).unwrap(),
+            )
+        };
+        let message_queue = durable();
+        for payload in ["1", "2", "3"] {
+            message_queue.publish("snake", payload).await.unwrap();
+        }
+        let mut subscription = message_queue.consume("snake", "reader").await.unwrap();
+        let first = subscription.recv().await.unwrap();
+        assert_eq!(first.payload, b"1");
+        message_queue
+            .commit("snake", "reader", first.offset + 1)
+            .await
+            .unwrap();
+        drop((subscription, message_queue));
+
+        let message_queue = durable();
+        let mut subscription = message_queue.consume("snake", "reader").await.unwrap();
+        message_queue.publish("snake", "4").await.unwrap();
+        let mut messages = Vec::new();
+        for _ in 0..3 {
+            messages.push(subscription.recv().await.unwrap());
+        }
+        assert_eq!(payloads(&messages), ["2", "3", "4"]);
+        assert_eq!(
+            payloads(&message_queue.read("snake", 0, 2).await.unwrap()),
+            ["1", "2"]
+        );
+    }
 }
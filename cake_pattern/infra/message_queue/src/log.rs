@@ -0,0 +1,876 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{Error, Result};
+
+/// When appended records are flushed from the OS page cache to disk. Records
+/// reach the page cache on every append, so all policies survive a crash of
+/// the process; only `Always` also survives losing power.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    Always,
+    /// At most once per interval, on the first append after it elapsed.
+    Interval(Duration),
+    Never,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub fsync: FsyncPolicy,
+    /// Size at which the active segment is closed and a new one started.
+    pub segment_bytes: u64,
+    /// Closed segments are deleted, oldest first, while the topic is larger.
+    /// In memory, the oldest records are.
+    pub retention_bytes: Option<u64>,
+    /// Closed segments last written longer ago than this are deleted. In
+    /// memory, records appended longer ago are.
+    pub retention_age: Option<Duration>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            fsync: FsyncPolicy::Interval(Duration::from_secs(1)),
+            segment_bytes: 16 * 1024 * 1024,
+            retention_bytes: None,
+            retention_age: None,
+        }
+    }
+}
+
+// Each record is `len: u32 | crc32(body): u32 | body`, where the body is
+// `offset: u64 | payload`, all little-endian.
+const HEADER: usize = 8;
+const OFFSET: usize = 8;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+fn encode(offset: u64, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(OFFSET + payload.len());
+    body.extend_from_slice(&offset.to_le_bytes());
+    body.extend_from_slice(payload);
+    let mut record = Vec::with_capacity(HEADER + body.len());
+    record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32(&body).to_le_bytes());
+    record.extend_from_slice(&body);
+    record
+}
+
+/// Decodes the record at the start of `bytes` into its offset and payload,
+/// with its length, unless it is incomplete or damaged.
+fn decode(bytes: &[u8]) -> Option<(u64, &[u8], usize)> {
+    let header = bytes.get(..HEADER)?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let body = bytes.get(HEADER..HEADER + len)?;
+    if len < OFFSET || crc32(body) != crc {
+        return None;
+    }
+    let offset = u64::from_le_bytes(body[..OFFSET].try_into().unwrap());
+    Some((offset, &body[OFFSET..], HEADER + len))
+}
+
+#[derive(Debug)]
+struct Segment {
+    /// Offset of the first record, which also names the file.
+    base: u64,
+    path: PathBuf,
+    size: u64,
+    /// Position in the file of each record, the one at `base` first.
+    positions: Vec<u64>,
+    /// Length of the prefix holding complete, intact records; short of
+    /// `size` only if a closed segment was damaged, or the active one by an
+    /// append that could not be undone.
+    valid: u64,
+}
+
+impl Segment {
+    fn new(dir: &Path, base: u64) -> Self {
+        Self {
+            base,
+            path: dir.join(format!("{base:020}.log")),
+            size: 0,
+            positions: Vec::new(),
+            valid: 0,
+        }
+    }
+
+    /// Opens the segment file at `path`, indexing the records of its intact
+    /// prefix.
+    fn open(path: PathBuf, base: u64) -> Result<Self> {
+        let bytes = fs::read(&path)?;
+        let mut positions = Vec::new();
+        let mut position = 0;
+        while let Some((offset, _, len)) = decode(&bytes[position..]) {
+            if offset != base + positions.len() as u64 {
+                break;
+            }
+            positions.push(position as u64);
+            position += len;
+        }
+        Ok(Self {
+            base,
+            path,
+            size: bytes.len() as u64,
+            positions,
+            valid: position as u64,
+        })
+    }
+
+    fn corrupt(&self, position: u64) -> Error {
+        Error::Corrupt {
+            path: self.path.clone(),
+            position,
+        }
+    }
+
+    /// Returns up to `max` records from `from` on, reading only their bytes.
+    fn read(&self, from: u64, max: usize) -> Result<Vec<(u64, Vec<u8>)>> {
+        let first = from.saturating_sub(self.base) as usize;
+        let Some(&start) = self.positions.get(first) else {
+            return Ok(Vec::new());
+        };
+        let last = first.saturating_add(max).min(self.positions.len());
+        let end = self.positions.get(last).copied().unwrap_or(self.valid);
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0; (end - start) as usize];
+        file.read_exact(&mut bytes)?;
+        let mut records = Vec::with_capacity(last - first);
+        let mut position = 0;
+        for expected in self.base + first as u64..self.base + last as u64 {
+            match decode(&bytes[position..]) {
+                Some((offset, payload, len)) if offset == expected => {
+                    records.push((offset, payload.to_vec()));
+                    position += len;
+                }
+                _ => return Err(self.corrupt(start + position as u64)),
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// An append-only log of one topic, stored as a directory of segment files
+/// named after their first offset. Consumer offsets and producer sequences
+/// are kept next to them.
+#[derive(Debug)]
+pub(crate) struct SegmentLog {
+    dir: PathBuf,
+    config: LogConfig,
+    /// Oldest first; the last one is active and open for appending.
+    segments: Vec<Segment>,
+    active: File,
+    next_offset: u64,
+    synced: Instant,
+    /// The next sequence expected of each producer, once recovered.
+    producers: HashMap<String, u64>,
+}
+
+impl SegmentLog {
+    /// Opens the log in `dir`, creating it if needed. A record torn by a
+    /// crash at the end of the active segment is truncated away.
+    pub(crate) fn open(dir: PathBuf, config: LogConfig) -> Result<Self> {
+        fs::create_dir_all(dir.join("consumers"))?;
+        fs::create_dir_all(dir.join("producers"))?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let base = path
+                .file_name()
+                .and_then(|name| name.to_str()?.strip_suffix(".log")?.parse().ok());
+            if let Some(base) = base {
+                segments.push(Segment::open(path, base)?);
+            }
+        }
+        segments.sort_by_key(|segment| segment.base);
+        if segments.is_empty() {
+            segments.push(Segment::new(&dir, 0));
+        }
+        let last = segments.last_mut().unwrap();
+        let active = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&last.path)?;
+        if last.valid < last.size {
+            active.set_len(last.valid)?;
+            active.sync_data()?;
+        }
+        last.size = last.valid;
+        let next_offset = last.base + last.positions.len() as u64;
+        let mut log = Self {
+            dir,
+            config,
+            segments,
+            active,
+            next_offset,
+            synced: Instant::now(),
+            producers: HashMap::new(),
+        };
+        log.retain()?;
+        Ok(log)
+    }
+
+    pub(crate) fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    pub(crate) fn append(&mut self, payload: &[u8]) -> Result<u64> {
+        let offset = self.next_offset;
+        let record = encode(offset, payload);
+        let active = self.segments.last().unwrap();
+        if active.valid < active.size {
+            return Err(active.corrupt(active.valid));
+        }
+        if active.size > 0 && active.size + record.len() as u64 > self.config.segment_bytes {
+            self.roll()?;
+        }
+        let write = self.active.write_all(&record);
+        let active = self.segments.last_mut().unwrap();
+        if let Err(err) = write {
+            // Part of the record may have been written; it is cut off, or the
+            // next append would land behind it. Failing that, the segment is
+            // marked damaged from there on and no longer appended to.
+            let truncated = OpenOptions::new()
+                .write(true)
+                .open(&active.path)
+                .and_then(|file| file.set_len(active.size));
+            if truncated.is_err() {
+                active.size += record.len() as u64;
+            }
+            return Err(err.into());
+        }
+        active.positions.push(active.size);
+        active.size += record.len() as u64;
+        active.valid = active.size;
+        self.next_offset += 1;
+        match self.config.fsync {
+            FsyncPolicy::Always => self.sync()?,
+            FsyncPolicy::Interval(interval) if self.synced.elapsed() >= interval => self.sync()?,
+            _ => {}
+        }
+        Ok(offset)
+    }
+
+    /// Returns up to `max` records from `from` on, starting at the oldest
+    /// retained record if `from` was already deleted. Only the segments
+    /// holding them are read, from the first record on.
+    pub(crate) fn read(&self, from: u64, max: usize) -> Result<Vec<(u64, Vec<u8>)>> {
+        let mut records = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            let end = self.segments.get(i + 1).map_or(u64::MAX, |next| next.base);
+            if end <= from {
+                continue;
+            }
+            records.extend(segment.read(from, max - records.len())?);
+            if records.len() == max {
+                break;
+            }
+            if segment.valid < segment.size {
+                return Err(segment.corrupt(segment.valid));
+            }
+        }
+        Ok(records)
+    }
+
+    /// The offset the consumer will read next, if it ever committed one.
+    pub(crate) fn committed(&self, consumer: &str) -> Result<Option<u64>> {
+        match fs::read_to_string(self.offset_path(consumer)) {
+            Ok(offset) => offset.trim().parse().map(Some).map_err(|_| Error::Corrupt {
+                path: self.offset_path(consumer),
+                position: 0,
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Records that the consumer will read `offset` next. The file is
+    /// replaced atomically, so a crash leaves either offset behind.
+    pub(crate) fn commit(&self, consumer: &str, offset: u64) -> Result<()> {
+        replace(&self.offset_path(consumer), &offset.to_string())
+    }
+
+    fn offset_path(&self, consumer: &str) -> PathBuf {
+        self.dir
+            .join("consumers")
+            .join(format!("{consumer}.offset"))
+    }
+
+    /// The sequence the producer is expected to publish next, if it ever
+    /// published one.
+    pub(crate) fn producer_sequence(&mut self, producer: &str) -> Result<Option<u64>> {
+        if let Some(&sequence) = self.producers.get(producer) {
+            return Ok(Some(sequence));
+        }
+        let path = self.sequence_path(producer);
+        let corrupt = || Error::Corrupt {
+            path: path.clone(),
+            position: 0,
+        };
+        let line = match fs::read_to_string(&path) {
+            Ok(line) => line,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let fields = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|_| corrupt())?;
+        let [sequence, offset, crc] = fields[..] else {
+            return Err(corrupt());
+        };
+        // The sequence was recorded before its append; it counts as
+        // published only if the record it names made it into the log. A
+        // record since deleted by retention can no longer be checked, but
+        // only deleted at all once later ones were appended, so counts.
+        let appended = offset < self.segments[0].base
+            || self
+                .read(offset, 1)?
+                .first()
+                .map_or(false, |(found, payload)| {
+                    *found == offset && u64::from(crc32(payload)) == crc
+                });
+        let sequence = if appended { sequence + 1 } else { sequence };
+        self.producers.insert(producer.to_owned(), sequence);
+        Ok(Some(sequence))
+    }
+
+    /// Appends the payload as the producer's `sequence`. The sequence is
+    /// recorded first, with the offset and checksum of the record to come,
+    /// so whether it was published is decided by the log alone, whether or
+    /// not the append then fails or the process crashes.
+    pub(crate) fn append_once(
+        &mut self,
+        producer: &str,
+        sequence: u64,
+        payload: &[u8],
+    ) -> Result<u64> {
+        self.producers.remove(producer);
+        let line = format!("{sequence} {} {}", self.next_offset, crc32(payload));
+        replace(&self.sequence_path(producer), &line)?;
+        let offset = self.append(payload)?;
+        self.producers.insert(producer.to_owned(), sequence + 1);
+        Ok(offset)
+    }
+
+    fn sequence_path(&self, producer: &str) -> PathBuf {
+        self.dir
+            .join("producers")
+            .join(format!("{producer}.sequence"))
+    }
+
+    /// The offsets held by each segment but the active one, oldest first.
+    pub(crate) fn closed_segments(&self) -> Vec<Range<u64>> {
+        self.segments
+            .windows(2)
+            .map(|pair| pair[0].base..pair[1].base)
+            .collect()
+    }
+
+    /// Deletes the closed segments whose records all precede `offset`.
+    pub(crate) fn delete_before(&mut self, offset: u64) -> Result<()> {
+        while self.segments.len() > 1 && self.segments[1].base <= offset {
+            fs::remove_file(&self.segments[0].path)?;
+            self.segments.remove(0);
+        }
+        Ok(())
+    }
+
+    fn roll(&mut self) -> Result<()> {
+        self.active.sync_data()?;
+        let segment = Segment::new(&self.dir, self.next_offset);
+        self.active = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&segment.path)?;
+        self.segments.push(segment);
+        self.synced = Instant::now();
+        self.retain()
+    }
+
+    /// Deletes closed segments beyond the retention limits; the active one is
+    /// always kept.
+    fn retain(&mut self) -> Result<()> {
+        let mut size = self
+            .segments
+            .iter()
+            .map(|segment| segment.size)
+            .sum::<u64>();
+        while self.segments.len() > 1 {
+            let oldest = &self.segments[0];
+            let too_big = self.config.retention_bytes.map_or(false, |max| size > max);
+            let too_old = match self.config.retention_age {
+                Some(max) => {
+                    let modified = fs::metadata(&oldest.path)?.modified()?;
+                    SystemTime::now()
+                        .duration_since(modified)
+                        .map_or(false, |age| age >= max)
+                }
+                None => false,
+            };
+            if !too_big && !too_old {
+                break;
+            }
+            fs::remove_file(&oldest.path)?;
+            size -= oldest.size;
+            self.segments.remove(0);
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.active.sync_data()?;
+        self.synced = Instant::now();
+        Ok(())
+    }
+}
+
+/// Replaces the file's contents atomically, so a crash leaves either the old
+/// or the new ones behind.
+fn replace(path: &Path, contents: &str) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_data()?;
+    fs::rename(temporary, path)?;
+    Ok(())
+}
+
+impl Drop for SegmentLog {
+    fn drop(&mut self) {
+        if self.config.fsync != FsyncPolicy::Never {
+            _ = self.active.sync_data();
+        }
+    }
+}
+
+/// The history of a topic on an in-memory broker, kept within the retention
+/// limits of its config until the broker is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryLog {
+    config: LogConfig,
+    /// Oldest first, with when each was appended.
+    records: VecDeque<(Instant, Vec<u8>)>,
+    /// Offset of the oldest record retained.
+    base: u64,
+    /// Total size of the payloads retained.
+    size: u64,
+    committed: HashMap<String, u64>,
+    producers: HashMap<String, u64>,
+}
+
+impl MemoryLog {
+    pub(crate) fn new(config: LogConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn append(&mut self, payload: &[u8]) -> Result<u64> {
+        let offset = self.base + self.records.len() as u64;
+        self.records.push_back((Instant::now(), payload.to_vec()));
+        self.size += payload.len() as u64;
+        self.retain();
+        Ok(offset)
+    }
+
+    /// Returns up to `max` records from `from` on, starting at the oldest
+    /// retained record if `from` was already deleted.
+    pub(crate) fn read(&self, from: u64, max: usize) -> Vec<(u64, Vec<u8>)> {
+        let from = from.max(self.base);
+        (from..)
+            .zip(self.records.iter().skip((from - self.base) as usize))
+            .take(max)
+            .map(|(offset, (_, payload))| (offset, payload.clone()))
+            .collect()
+    }
+
+    /// Deletes the oldest records beyond the retention limits; the newest one
+    /// is always kept.
+    fn retain(&mut self) {
+        while self.records.len() > 1 {
+            let (appended, payload) = &self.records[0];
+            let too_big = self
+                .config
+                .retention_bytes
+                .map_or(false, |max| self.size > max);
+            let too_old = self
+                .config
+                .retention_age
+                .map_or(false, |max| appended.elapsed() >= max);
+            if !too_big && !too_old {
+                break;
+            }
+            self.size -= payload.len() as u64;
+            self.records.pop_front();
+            self.base += 1;
+        }
+    }
+}
+
+/// A topic's history, on disk or in memory.
+#[derive(Debug)]
+pub(crate) enum Log {
+    Memory(MemoryLog),
+    Segment(SegmentLog),
+}
+
+impl Log {
+    pub(crate) fn append(&mut self, payload: &[u8]) -> Result<u64> {
+        match self {
+            Self::Memory(log) => log.append(payload),
+            Self::Segment(log) => log.append(payload),
+        }
+    }
+
+    pub(crate) fn read(&self, from: u64, max: usize) -> Result<Vec<(u64, Vec<u8>)>> {
+        match self {
+            Self::Memory(log) => Ok(log.read(from, max)),
+            Self::Segment(log) => log.read(from, max),
+        }
+    }
+
+    pub(crate) fn producer_sequence(&mut self, producer: &str) -> Result<Option<u64>> {
+        match self {
+            Self::Memory(log) => Ok(log.producers.get(producer).copied()),
+            Self::Segment(log) => log.producer_sequence(producer),
+        }
+    }
+
+    /// Appends the payload as the producer's `sequence`, recording the
+    /// sequence in the same step.
+    pub(crate) fn append_once(
+        &mut self,
+        producer: &str,
+        sequence: u64,
+        payload: &[u8],
+    ) -> Result<u64> {
+        match self {
+            Self::Memory(log) => {
+                log.producers.insert(producer.to_owned(), sequence + 1);
+                log.append(payload)
+            }
+            Self::Segment(log) => log.append_once(producer, sequence, payload),
+        }
+    }
+
+    pub(crate) fn committed(&self, consumer: &str) -> Result<Option<u64>> {
+        match self {
+            Self::Memory(log) => Ok(log.committed.get(consumer).copied()),
+            Self::Segment(log) => log.committed(consumer),
+        }
+    }
+
+    pub(crate) fn commit(&mut self, consumer: &str, offset: u64) -> Result<()> {
+        match self {
+            Self::Memory(log) => {
+                log.committed.insert(consumer.to_owned(), offset);
+                Ok(())
+            }
+            Self::Segment(log) => log.commit(consumer, offset),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{env, process};
+
+    use super::*;
+
+    /// A scratch directory removed again on drop.
+    pub(crate) struct TempDir(pub(crate) PathBuf);
+
+    impl TempDir {
+        pub(crate) fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("message-queue-{}-{name}", process::id()));
+            _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn config(segment_bytes: u64) -> LogConfig {
+        LogConfig {
+            fsync: FsyncPolicy::Always,
+            segment_bytes,
+            ..LogConfig::default()
+        }
+    }
+
+    fn offsets(records: &[(u64, Vec<u8>)]) -> Vec<u64> {
+        records.iter().map(|(offset, _)| *offset).collect()
+    }
+
+    fn segment_count(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("log".as_ref()))
+            .count()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = TempDir::new("reopen");
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.append(b"snake").unwrap(), 0);
+        assert_eq!(log.append(b"frog").unwrap(), 1);
+        drop(log);
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.next_offset(), 2);
+        assert_eq!(log.append(b"slug").unwrap(), 2);
+        assert_eq!(
+            log.read(1, 10).unwrap(),
+            [(1, b"frog".to_vec()), (2, b"slug".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_torn_record_is_truncated() {
+        let dir = TempDir::new("torn");
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        log.append(b"snake").unwrap();
+        log.append(b"frog").unwrap();
+        drop(log);
+        let path = dir.0.join(format!("{:020}.log", 0));
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 2)
+            .unwrap();
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.next_offset(), 1);
+        assert_eq!(log.append(b"slug").unwrap(), 1);
+        assert_eq!(
+            log.read(0, 10).unwrap(),
+            [(0, b"snake".to_vec()), (1, b"slug".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_failed_append_is_truncated() {
+        let dir = TempDir::new("failed-append");
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        log.append(b"snake").unwrap();
+        let path = dir.0.join(format!("{:020}.log", 0));
+        let len = fs::metadata(&path).unwrap().len();
+        // As if the write of the next record failed halfway: part of it is
+        // on disk, and the handle refuses the rest.
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&encode(1, b"frog")[..5])
+            .unwrap();
+        log.active = File::open(&path).unwrap();
+        log.append(b"frog").unwrap_err();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        log.active = OpenOptions::new().append(true).open(&path).unwrap();
+        assert_eq!(log.append(b"slug").unwrap(), 1);
+        assert_eq!(
+            log.read(0, 10).unwrap(),
+            [(0, b"snake".to_vec()), (1, b"slug".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = TempDir::new("rotation");
+        let record = encode(0, b"snake").len() as u64;
+        let mut log = SegmentLog::open(dir.0.clone(), config(2 * record)).unwrap();
+        for _ in 0..5 {
+            log.append(b"snake").unwrap();
+        }
+        assert_eq!(segment_count(&dir.0), 3);
+        assert_eq!(offsets(&log.read(1, 3).unwrap()), [1, 2, 3]);
+        drop(log);
+        let log = SegmentLog::open(dir.0.clone(), config(2 * record)).unwrap();
+        assert_eq!(log.next_offset(), 5);
+        assert_eq!(offsets(&log.read(0, 10).unwrap()), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_damaged_segment() {
+        let dir = TempDir::new("damaged");
+        let record = encode(0, b"snake").len() as u64;
+        let mut log = SegmentLog::open(dir.0.clone(), config(2 * record)).unwrap();
+        for _ in 0..3 {
+            log.append(b"snake").unwrap();
+        }
+        drop(log);
+        // Damage the payload of offset 1, the last of the closed segment.
+        let path = dir.0.join(format!("{:020}.log", 0));
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        let log = SegmentLog::open(dir.0.clone(), config(2 * record)).unwrap();
+        // Records before the damage are read without reaching it.
+        assert_eq!(offsets(&log.read(0, 1).unwrap()), [0]);
+        assert_eq!(offsets(&log.read(2, 10).unwrap()), [2]);
+        let err = log.read(0, 10).unwrap_err();
+        assert!(matches!(err, Error::Corrupt { position, .. } if position == record));
+    }
+
+    #[test]
+    fn test_retention_by_size() {
+        let dir = TempDir::new("retention-size");
+        let record = encode(0, b"snake").len() as u64;
+        let config = LogConfig {
+            retention_bytes: Some(3 * record),
+            ..config(2 * record)
+        };
+        let mut log = SegmentLog::open(dir.0.clone(), config).unwrap();
+        for _ in 0..6 {
+            log.append(b"snake").unwrap();
+        }
+        // Checked when a segment is closed: [0, 1] went when [4, 5] started.
+        assert_eq!(segment_count(&dir.0), 2);
+        assert_eq!(offsets(&log.read(0, 10).unwrap()), [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_retention_by_age() {
+        let dir = TempDir::new("retention-age");
+        let record = encode(0, b"snake").len() as u64;
+        let config = LogConfig {
+            retention_age: Some(Duration::ZERO),
+            ..config(record)
+        };
+        let mut log = SegmentLog::open(dir.0.clone(), config).unwrap();
+        for _ in 0..3 {
+            log.append(b"snake").unwrap();
+        }
+        assert_eq!(segment_count(&dir.0), 1);
+        assert_eq!(offsets(&log.read(0, 10).unwrap()), [2]);
+    }
+
+    #[test]
+    fn test_memory_retention() {
+        let mut log = MemoryLog::new(LogConfig {
+            retention_bytes: Some(10),
+            ..LogConfig::default()
+        });
+        for payload in ["snake", "slug", "frog"] {
+            log.append(payload.as_bytes()).unwrap();
+        }
+        assert_eq!(offsets(&log.read(0, 10)), [1, 2]);
+        assert_eq!(offsets(&log.read(2, 10)), [2]);
+        let mut log = MemoryLog::new(LogConfig {
+            retention_age: Some(Duration::ZERO),
+            ..LogConfig::default()
+        });
+        for payload in ["snake", "slug", "frog"] {
+            log.append(payload.as_bytes()).unwrap();
+        }
+        assert_eq!(log.read(0, 10), [(2, b"frog".to_vec())]);
+        assert_eq!(log.append(b"snake").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_producer_sequences() {
+        let dir = TempDir::new("producers");
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.producer_sequence("outbox").unwrap(), None);
+        assert_eq!(log.append_once("outbox", 3, b"snake").unwrap(), 0);
+        assert_eq!(log.producer_sequence("outbox").unwrap(), Some(4));
+        drop(log);
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.producer_sequence("outbox").unwrap(), Some(4));
+        assert_eq!(log.producer_sequence("relay").unwrap(), None);
+    }
+
+    #[test]
+    fn test_producer_sequence_without_its_append() {
+        let dir = TempDir::new("producers-torn");
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        log.append_once("outbox", 3, b"snake").unwrap();
+        // As if the process died between recording sequence 4 and appending
+        // it, and another publish took the offset meant for it.
+        let line = format!("4 {} {}", log.next_offset(), crc32(b"frog"));
+        replace(&log.sequence_path("outbox"), &line).unwrap();
+        log.append(b"slug").unwrap();
+        drop(log);
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.producer_sequence("outbox").unwrap(), Some(4));
+        assert_eq!(log.append_once("outbox", 4, b"frog").unwrap(), 2);
+        drop(log);
+        let mut log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.producer_sequence("outbox").unwrap(), Some(5));
+    }
+
+    #[test]
+    fn test_producer_sequence_past_retention() {
+        let dir = TempDir::new("producers-retained");
+        let record = encode(0, b"snake").len() as u64;
+        let config = LogConfig {
+            retention_bytes: Some(record),
+            ..config(record)
+        };
+        let mut log = SegmentLog::open(dir.0.clone(), config.clone()).unwrap();
+        log.append_once("outbox", 3, b"snake").unwrap();
+        log.append(b"slug").unwrap();
+        log.append(b"frog").unwrap();
+        assert_eq!(offsets(&log.read(0, 10).unwrap()), [1, 2]);
+        drop(log);
+        let mut log = SegmentLog::open(dir.0.clone(), config).unwrap();
+        assert_eq!(log.producer_sequence("outbox").unwrap(), Some(4));
+    }
+
+    #[test]
+    fn test_consumer_offsets() {
+        let dir = TempDir::new("offsets");
+        let log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.committed("reader").unwrap(), None);
+        log.commit("reader", 3).unwrap();
+        log.commit("reader", 4).unwrap();
+        drop(log);
+        let log = SegmentLog::open(dir.0.clone(), config(1024)).unwrap();
+        assert_eq!(log.committed("reader").unwrap(), Some(4));
+        assert_eq!(log.committed("writer").unwrap(), None);
+    }
+}
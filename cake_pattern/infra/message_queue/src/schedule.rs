@@ -0,0 +1,479 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{runtime::Handle, sync::Notify, time};
+
+use crate::{
+    broker::{validate, Broker},
+    lock,
+    log::{LogConfig, SegmentLog},
+    Error, Result,
+};
+
+/// How long the timer waits before retrying a delivery that failed.
+const RETRY: Duration = Duration::from_secs(1);
+
+/// The offset before which every scheduled message was delivered.
+const DELIVERED: &str = "delivered";
+
+// Each schedule log record is either `0 | due secs: u64 | due nanos: u32 |
+// topic len: u16 | topic | payload`, for a scheduled message, or
+// `1 | offset: u64`, once the message scheduled at that offset is delivered,
+// all little-endian.
+const SCHEDULED_TAG: u8 = 0;
+const DELIVERED_TAG: u8 = 1;
+
+#[derive(Debug)]
+struct Scheduled {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Messages not yet due, by due time and offset in the schedule log.
+    pending: BTreeMap<(SystemTime, u64), Scheduled>,
+    /// Set when messages are persisted.
+    log: Option<SegmentLog>,
+    /// Numbers the messages of an in-memory schedule.
+    next_offset: u64,
+}
+
+/// Holds messages back until they are due, then publishes them. Pending
+/// messages are kept in a priority queue by due time; on a durable broker
+/// they are also appended to a log of their own, so they survive a restart.
+///
+/// Delivery is at least once: a message published just before a crash may be
+/// published again on recovery.
+#[derive(Debug, Default)]
+pub(crate) struct Scheduler {
+    state: Mutex<State>,
+    /// Wakes the timer when an earlier message is scheduled or the broker
+    /// is dropped.
+    notify: Notify,
+    started: AtomicBool,
+    closed: AtomicBool,
+}
+
+impl Scheduler {
+    /// A scheduler that persists its messages under `dir` and recovers the
+    /// ones not yet delivered. The configured retention does not apply, so
+    /// no pending message is deleted; segments are deleted instead once every
+    /// message in them is delivered.
+    pub(crate) fn durable(dir: PathBuf, config: &LogConfig) -> Result<Self> {
+        let config = LogConfig {
+            retention_bytes: None,
+            retention_age: None,
+            ..config.clone()
+        };
+        let mut log = SegmentLog::open(dir.clone(), config)?;
+        let from = log.committed(DELIVERED)?.unwrap_or(0);
+        log.delete_before(from)?;
+        let mut pending = HashMap::new();
+        for (offset, record) in log.read(from, usize::MAX)? {
+            let corrupt = || Error::Corrupt {
+                path: dir.clone(),
+                position: offset,
+            };
+            match decode(&record).ok_or_else(corrupt)? {
+                Record::Scheduled(due, scheduled) => {
+                    pending.insert(offset, (due, scheduled));
+                }
+                Record::Delivered(delivered) => {
+                    pending.remove(&delivered);
+                }
+            }
+        }
+        Ok(Self {
+            state: Mutex::new(State {
+                pending: pending
+                    .into_iter()
+                    .map(|(offset, (due, scheduled))| ((due, offset), scheduled))
+                    .collect(),
+                log: Some(log),
+                next_offset: 0,
+            }),
+            ..Self::default()
+        })
+    }
+
+    pub(crate) fn schedule(&self, topic: &str, due: SystemTime, payload: Vec<u8>) -> Result<()> {
+        validate(topic)?;
+        let mut state = lock(&self.state);
+        let offset = match &mut state.log {
+            Some(log) => log.append(&encode_scheduled(topic, due, &payload))?,
+            None => {
+                state.next_offset += 1;
+                state.next_offset - 1
+            }
+        };
+        let earliest = state
+            .pending
+            .keys()
+            .next()
+            .map_or(true, |&key| (due, offset) < key);
+        state.pending.insert(
+            (due, offset),
+            Scheduled {
+                topic: topic.to_owned(),
+                payload,
+            },
+        );
+        if earliest {
+            self.notify.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Starts the timer that publishes due messages to the broker, unless it
+    /// is running already or there is no Tokio runtime to run it on. It stops
+    /// once the broker is dropped.
+    pub(crate) fn start(self: &Arc<Self>, broker: Weak<Broker>) {
+        let Ok(runtime) = Handle::try_current() else {
+            return;
+        };
+        if self.started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let scheduler = Arc::clone(self);
+        runtime.spawn(async move { scheduler.run(broker).await });
+    }
+
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+
+    async fn run(&self, broker: Weak<Broker>) {
+        while !self.closed.load(Ordering::Acquire) {
+            let Ok(next) = self.next_due() else {
+                return;
+            };
+            let wait = match next {
+                Some(due) => match due.duration_since(SystemTime::now()) {
+                    Ok(wait) => Some(wait),
+                    Err(_) => {
+                        let Some(broker) = broker.upgrade() else {
+                            return;
+                        };
+                        if self.deliver_due(&broker).is_err() {
+                            time::sleep(RETRY).await;
+                        }
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            tokio::select! {
+                _ = self.notify.notified() => {}
+                _ = time::sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+            }
+        }
+    }
+
+    fn next_due(&self) -> Result<Option<SystemTime>> {
+        let state = lock(&self.state);
+        Ok(state.pending.keys().next().map(|&(due, _)| due))
+    }
+
+    /// Publishes the messages that are due, earliest first, and records each
+    /// as delivered. A topic that refuses a message, say because it is full,
+    /// is skipped until the next attempt, so it holds back neither the other
+    /// topics nor its own later messages out of order; the first such error
+    /// is returned once the rest are delivered.
+    fn deliver_due(&self, broker: &Broker) -> Result<()> {
+        let mut state = lock(&self.state);
+        let State { pending, log, .. } = &mut *state;
+        let due = pending
+            .range(..=(SystemTime::now(), u64::MAX))
+            .map(|(&key, _)| key)
+            .collect::<Vec<_>>();
+        let mut refused = HashSet::new();
+        let mut result = Ok(());
+        for key in due {
+            let scheduled = &pending[&key];
+            if refused.contains(&scheduled.topic) {
+                continue;
+            }
+            if let Err(err) = broker.publish(&scheduled.topic, &scheduled.payload) {
+                refused.insert(scheduled.topic.clone());
+                result = result.and(Err(err));
+                continue;
+            }
+            pending.remove(&key);
+            if let Some(log) = log {
+                log.append(&encode_delivered(key.1))?;
+            }
+        }
+        if let Some(log) = log {
+            compact(pending, log)?;
+            let oldest = pending.keys().map(|&(_, offset)| offset).min();
+            let oldest = oldest.unwrap_or_else(|| log.next_offset());
+            log.commit(DELIVERED, oldest)?;
+            log.delete_before(oldest)?;
+        }
+        result
+    }
+
+    /// The number of messages not yet due.
+    #[cfg(test)]
+    pub(crate) fn pending(&self) -> usize {
+        lock(&self.state).pending.len()
+    }
+}
+
+/// Once two or more closed segments are kept, moves the messages still
+/// pending in the oldest one to the end of the log, so a message scheduled
+/// far ahead does not keep every record appended after it.
+fn compact(
+    pending: &mut BTreeMap<(SystemTime, u64), Scheduled>,
+    log: &mut SegmentLog,
+) -> Result<()> {
+    let closed = log.closed_segments();
+    if closed.len() < 2 {
+        return Ok(());
+    }
+    let stranded = pending
+        .keys()
+        .filter(|(_, offset)| closed[0].contains(offset))
+        .copied()
+        .collect::<Vec<_>>();
+    for (due, offset) in stranded {
+        let scheduled = pending.remove(&(due, offset)).unwrap();
+        let moved = log.append(&encode_scheduled(&scheduled.topic, due, &scheduled.payload))?;
+        log.append(&encode_delivered(offset))?;
+        pending.insert((due, moved), scheduled);
+    }
+    Ok(())
+}
+
+enum Record {
+    Scheduled(SystemTime, Scheduled),
+    Delivered(u64),
+}
+
+fn encode_scheduled(topic: &str, due: SystemTime, payload: &[u8]) -> Vec<u8> {
+    // Times before the epoch are due at once anyway.
+    let due = due.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut record = vec![SCHEDULED_TAG];
+    record.extend_from_slice(&due.as_secs().to_le_bytes());
+    record.extend_from_slice(&due.subsec_nanos().to_le_bytes());
+    // Valid topic names are far shorter than `u16::MAX`.
+    record.extend_from_slice(&(topic.len() as u16).to_le_bytes());
+    record.extend_from_slice(topic.as_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+fn encode_delivered(offset: u64) -> Vec<u8> {
+    let mut record = vec![DELIVERED_TAG];
+    record.extend_from_slice(&offset.to_le_bytes());
+    record
+}
+
+fn decode(record: &[u8]) -> Option<Record> {
+    let (&tag, rest) = record.split_first()?;
+    match tag {
+        SCHEDULED_TAG => {
+            let secs = u64::from_le_bytes(rest.get(..8)?.try_into().ok()?);
+            let nanos = u32::from_le_bytes(rest.get(8..12)?.try_into().ok()?);
+            let len = u16::from_le_bytes(rest.get(12..14)?.try_into().ok()?) as usize;
+            let topic = std::str::from_utf8(rest.get(14..14 + len)?).ok()?;
+            let due = UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?;
+            Some(Record::Scheduled(
+                due,
+                Scheduled {
+                    topic: topic.to_owned(),
+                    payload: rest[14 + len..].to_vec(),
+                },
+            ))
+        }
+        DELIVERED_TAG => Some(Record::Delivered(u64::from_le_bytes(rest.try_into().ok()?))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        log::test::TempDir, MessageQueue, MessageQueueConnection, OverflowPolicy, TopicConfig,
+    };
+
+    const DELAY: Duration = Duration::from_millis(50);
+
+    async fn payloads(message_queue: &MessageQueue) -> Vec<Vec<u8>> {
+        message_queue
+            .read("frog", 0, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| message.payload)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_publish_after() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        message_queue
+            .publish_after("frog", DELAY * 2, "later")
+            .await
+            .unwrap();
+        message_queue
+            .publish_after("frog", DELAY, "sooner")
+            .await
+            .unwrap();
+        message_queue.publish("frog", "now").await.unwrap();
+        let start = time::Instant::now();
+        assert_eq!(subscription.recv().await.unwrap().payload, b"now");
+        assert_eq!(subscription.recv().await.unwrap().payload, b"sooner");
+        assert!(start.elapsed() >= DELAY - Duration::from_millis(5));
+        assert_eq!(subscription.recv().await.unwrap().payload, b"later");
+        assert!(start.elapsed() >= DELAY * 2 - Duration::from_millis(5));
+    }
+
+    #[tokio::test]
+    async fn test_publish_at_past_is_due_at_once() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        message_queue
+            .publish_at("frog", UNIX_EPOCH, "overdue")
+            .await
+            .unwrap();
+        let message = time::timeout(DELAY, subscription.recv()).await.unwrap();
+        assert_eq!(message.unwrap().payload, b"overdue");
+        assert!(matches!(
+            message_queue.publish_at("frog eaten", UNIX_EPOCH, "").await,
+            Err(Error::InvalidName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_durable_schedule_survives_restart() {
+        let dir = TempDir::new("schedule");
+        let durable = || {
+            MessageQueue::new(
+                MessageQueueConnection::durable(&dir.0, LogConfig::default()).unwrap(),
+            )
+        };
+        let message_queue = durable();
+        message_queue
+            .publish_after("frog", Duration::ZERO, "first")
+            .await
+            .unwrap();
+        message_queue
+            .publish_after("frog", DELAY, "second")
+            .await
+            .unwrap();
+        time::sleep(DELAY / 5).await;
+        assert_eq!(payloads(&message_queue).await, [b"first"]);
+        drop(message_queue);
+
+        let message_queue = durable();
+        assert_eq!(message_queue.conn().broker.scheduler().pending(), 1);
+        time::sleep(DELAY * 2).await;
+        assert_eq!(
+            payloads(&message_queue).await,
+            [b"first".as_slice(), b"second"]
+        );
+        drop(message_queue);
+
+        let message_queue = durable();
+        assert_eq!(message_queue.conn().broker.scheduler().pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_full_topic_holds_back_only_itself() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let full = TopicConfig {
+            capacity: Some(1),
+            overflow: OverflowPolicy::Error,
+        };
+        message_queue.configure_topic("slug", full).await.unwrap();
+        let mut slugs = message_queue.subscribe("slug").await.unwrap();
+        let mut frogs = message_queue.subscribe("frog").await.unwrap();
+        message_queue.publish("slug", "earlier").await.unwrap();
+        message_queue
+            .publish_at("slug", UNIX_EPOCH, "held")
+            .await
+            .unwrap();
+        message_queue
+            .publish_at("frog", UNIX_EPOCH, "overdue")
+            .await
+            .unwrap();
+        let message = time::timeout(DELAY, frogs.recv()).await.unwrap();
+        assert_eq!(message.unwrap().payload, b"overdue");
+        assert_eq!(message_queue.conn().broker.scheduler().pending(), 1);
+        // Once the topic has room, the message is published on the next try.
+        assert_eq!(slugs.recv().await.unwrap().payload, b"earlier");
+        let message = time::timeout(RETRY * 2, slugs.recv()).await.unwrap();
+        assert_eq!(message.unwrap().payload, b"held");
+    }
+
+    #[test]
+    fn test_durable_schedule_is_compacted() {
+        let dir = TempDir::new("schedule-compaction");
+        let config = LogConfig {
+            segment_bytes: 64,
+            ..LogConfig::default()
+        };
+        let segments = || {
+            fs::read_dir(&dir.0)
+                .unwrap()
+                .filter(|entry| {
+                    let path = entry.as_ref().unwrap().path();
+                    path.extension()
+                        .map_or(false, |extension| extension == "log")
+                })
+                .count()
+        };
+        let broker = Broker::in_memory(LogConfig::default());
+        let scheduler = Scheduler::durable(dir.0.clone(), &config).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        scheduler
+            .schedule("frog", later, b"later".to_vec())
+            .unwrap();
+        for _ in 0..20 {
+            scheduler
+                .schedule("frog", UNIX_EPOCH, b"now".to_vec())
+                .unwrap();
+            scheduler.deliver_due(&broker).unwrap();
+        }
+        assert!(segments() <= 4, "{} segments kept", segments());
+        drop(scheduler);
+
+        let scheduler = Scheduler::durable(dir.0.clone(), &config).unwrap();
+        assert_eq!(scheduler.pending(), 1);
+    }
+
+    #[test]
+    fn test_codec() {
+        let due = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        let Some(Record::Scheduled(decoded, scheduled)) =
+            decode(&encode_scheduled("frog", due, b"eaten"))
+        else {
+            panic!("not a scheduled message");
+        };
+        assert_eq!(decoded, due);
+        assert_eq!(
+            (scheduled.topic.as_str(), scheduled.payload.as_slice()),
+            ("frog", b"eaten".as_slice())
+        );
+        assert!(matches!(
+            decode(&encode_delivered(7)),
+            Some(Record::Delivered(7))
+        ));
+        assert!(decode(&[SCHEDULED_TAG, 1, 2]).is_none());
+        assert!(decode(&[2]).is_none());
+    }
+}
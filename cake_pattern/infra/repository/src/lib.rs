@@ -1,23 +1,28 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use database::Database;
 use domain::{
-    Frog, FrogID, FrogRepository, RepositoryProvider, Slug, SlugID, SlugRepository, Snake, SnakeID,
-    SnakeRepository,
+    Frog, FrogID, FrogRepository, RepositoryProvider, Result, Slug, SlugID, SlugRepository, Snake,
+    SnakeID, SnakeRepository, Transaction, TransactionProvider,
 };
-use message_queue::MessageQueue;
+use tracing::instrument;
+
+mod cache;
+mod outbox;
+mod store;
+
+pub use cache::{
+    Cache, CacheConfig, CacheStats, CachedRepository, CachedTransaction, TransactionRepository,
+};
+pub use outbox::OutboxRelay;
+use store::Scope;
 
 pub struct RepositoryProviderImpl<'r> {
     database: &'r Database,
-    message_queue: &'r MessageQueue,
 }
 
 impl<'r> RepositoryProviderImpl<'r> {
-    pub fn new(database: &'r Database, message_queue: &'r MessageQueue) -> Self {
-        Self {
-            database,
-            message_queue,
-        }
+    pub fn new(database: &'r Database) -> Self {
+        Self { database }
     }
 }
 
@@ -28,71 +33,350 @@ impl<'r> RepositoryProvider for RepositoryProviderImpl<'r> {
 
     fn snake_repository(&self) -> Self::SnakeRepository<'_> {
         Self::SnakeRepository {
-            database: self.database,
-            message_queue: self.message_queue,
+            scope: Scope::Database(self.database),
         }
     }
     fn slug_repository(&self) -> Self::SlugRepository<'_> {
         Self::SlugRepository {
-            database: self.database,
-            message_queue: self.message_queue,
+            scope: Scope::Database(self.database),
         }
     }
     fn frog_repository(&self) -> Self::FrogRepository<'_> {
         Self::FrogRepository {
-            database: self.database,
-            message_queue: self.message_queue,
+            scope: Scope::Database(self.database),
         }
     }
 }
 
+#[async_trait]
+impl<'r> TransactionProvider for RepositoryProviderImpl<'r> {
+    type Transaction = RepositoryTransaction;
+
+    #[instrument(skip_all)]
+    async fn begin(&self) -> Result<Self::Transaction> {
+        Ok(RepositoryTransaction {
+            transaction: store::begin(self.database).await?,
+        })
+    }
+}
+
+pub struct RepositoryTransaction {
+    transaction: database::Transaction,
+}
+
+#[async_trait]
+impl Transaction for RepositoryTransaction {
+    type SnakeRepository = SnakeRepositoryImpl<'static>;
+    type SlugRepository = SlugRepositoryImpl<'static>;
+    type FrogRepository = FrogRepositoryImpl<'static>;
+
+    fn snake_repository(&self) -> Self::SnakeRepository {
+        Self::SnakeRepository {
+            scope: Scope::Transaction(self.transaction.clone()),
+        }
+    }
+    fn slug_repository(&self) -> Self::SlugRepository {
+        Self::SlugRepository {
+            scope: Scope::Transaction(self.transaction.clone()),
+        }
+    }
+    fn frog_repository(&self) -> Self::FrogRepository {
+        Self::FrogRepository {
+            scope: Scope::Transaction(self.transaction.clone()),
+        }
+    }
+    #[instrument(skip_all)]
+    async fn commit(self) -> Result<()> {
+        store::commit(self.transaction).await
+    }
+    #[instrument(skip_all)]
+    async fn rollback(self) -> Result<()> {
+        store::rollback(self.transaction).await
+    }
+}
+
 pub struct SnakeRepositoryImpl<'a> {
-    database: &'a Database,
-    message_queue: &'a MessageQueue,
+    scope: Scope<'a>,
 }
 
 #[async_trait]
 impl<'a> SnakeRepository for SnakeRepositoryImpl<'a> {
+    #[instrument(skip_all, fields(snake = %id))]
     async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
-        _ = self.database.conn();
-        _ = self.message_queue.conn();
-        Ok(Snake {
-            id,
-            ..Default::default()
-        })
+        store::get(&self.scope, id).await
+    }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        store::insert(&self.scope, &snake).await
+    }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        store::update(&self.scope, &snake).await
+    }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        store::upsert(&self.scope, &snake).await
+    }
+    #[instrument(skip_all, fields(snake = %id))]
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        store::delete::<Snake>(&self.scope, id).await
     }
 }
 
 #[async_trait]
 impl<'a> SlugRepository for SlugRepositoryImpl<'a> {
+    #[instrument(skip_all, fields(slug = %id))]
     async fn get_slug(&self, id: SlugID) -> Result<Slug> {
-        _ = self.database.conn();
-        _ = self.message_queue.conn();
-        Ok(Slug {
-            id,
-            ..Default::default()
-        })
+        store::get(&self.scope, id).await
+    }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        store::insert(&self.scope, &slug).await
+    }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        store::update(&self.scope, &slug).await
+    }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        store::upsert(&self.scope, &slug).await
+    }
+    #[instrument(skip_all, fields(slug = %id))]
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        store::delete::<Slug>(&self.scope, id).await
     }
 }
 
 pub struct SlugRepositoryImpl<'a> {
-    database: &'a Database,
-    message_queue: &'a MessageQueue,
+    scope: Scope<'a>,
 }
 
 pub struct FrogRepositoryImpl<'a> {
-    database: &'a Database,
-    message_queue: &'a MessageQueue,
+    scope: Scope<'a>,
 }
 
 #[async_trait]
 impl<'a> FrogRepository for FrogRepositoryImpl<'a> {
+    #[instrument(skip_all, fields(frog = %id))]
     async fn get_frog(&self, id: FrogID) -> Result<Frog> {
-        _ = self.database.conn();
-        _ = self.message_queue.conn();
-        Ok(Frog {
-            id,
-            ..Default::default()
-        })
+        store::get(&self.scope, id).await
+    }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        store::insert(&self.scope, &frog).await
+    }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        store::update(&self.scope, &frog).await
+    }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        store::upsert(&self.scope, &frog).await
+    }
+    #[instrument(skip_all, fields(frog = %id))]
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        store::delete::<Frog>(&self.scope, id).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use database::{DatabaseConfig, Pool, PoolConfig};
+    use domain::{EntityID, Error};
+
+    use super::*;
+    use crate::store::test::{seed, sqlite_database};
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
+
+    fn database() -> Database {
+        Database::new(Pool::open(&DatabaseConfig::Memory, PoolConfig::default()).unwrap())
+    }
+
+    /// A database holding slug 2, eaten by frog 2, eaten by snake 2, eaten by
+    /// slug 2, for [`snake`] to be eaten by.
+    async fn seeded_database() -> Database {
+        let database = database();
+        seed(&database, [2]).await;
+        database
+    }
+
+    #[tokio::test]
+    async fn test_commit() {
+        let database = seeded_database().await;
+        let repository = RepositoryProviderImpl::new(&database);
+        let transaction = repository.begin().await.unwrap();
+        transaction
+            .snake_repository()
+            .insert_snake(snake())
+            .await
+            .unwrap();
+        transaction
+            .frog_repository()
+            .insert_frog(frog())
+            .await
+            .unwrap();
+        assert_eq!(
+            transaction
+                .snake_repository()
+                .get_snake(snake().id)
+                .await
+                .unwrap(),
+            snake()
+        );
+        transaction.commit().await.unwrap();
+        assert_eq!(
+            repository
+                .snake_repository()
+                .get_snake(snake().id)
+                .await
+                .unwrap(),
+            snake()
+        );
+        assert_eq!(
+            repository
+                .frog_repository()
+                .get_frog(frog().id)
+                .await
+                .unwrap(),
+            frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        let database = database();
+        let repository = RepositoryProviderImpl::new(&database);
+        let transaction = repository.begin().await.unwrap();
+        transaction
+            .snake_repository()
+            .insert_snake(snake())
+            .await
+            .unwrap();
+        transaction.rollback().await.unwrap();
+        let transaction = repository.begin().await.unwrap();
+        transaction
+            .frog_repository()
+            .insert_frog(frog())
+            .await
+            .unwrap();
+        drop(transaction);
+        assert!(matches!(
+            repository.snake_repository().get_snake(snake().id).await,
+            Err(Error::NotFound(EntityID::Snake(_)))
+        ));
+        assert!(matches!(
+            repository.frog_repository().get_frog(frog().id).await,
+            Err(Error::NotFound(EntityID::Frog(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_commit_conflict() {
+        let database = seeded_database().await;
+        let repository = RepositoryProviderImpl::new(&database);
+        let transaction = repository.begin().await.unwrap();
+        transaction
+            .snake_repository()
+            .insert_snake(snake())
+            .await
+            .unwrap();
+        repository
+            .snake_repository()
+            .insert_snake(snake())
+            .await
+            .unwrap();
+        assert!(matches!(
+            transaction.commit().await,
+            Err(Error::Conflict(EntityID::Snake(id))) if id == snake().id
+        ));
+    }
+
+    /// The repositories behave as the in-memory fakes do, on both backends,
+    /// outside and inside transactions and behind the cache.
+    mod contract {
+        use std::sync::Arc;
+
+        use domain_fakes::contract;
+
+        use super::*;
+        use crate::{Cache, CacheConfig, CachedRepository};
+
+        mod repository {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let database = database();
+                    seed(&database, contract::PREDATORS).await;
+                    let repository = RepositoryProviderImpl::new(&database);
+                }
+                snake: &repository.snake_repository(),
+                slug: &repository.slug_repository(),
+                frog: &repository.frog_repository(),
+            }
+        }
+
+        mod sqlite {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let (database, _file) = sqlite_database().await;
+                    seed(&database, contract::PREDATORS).await;
+                    let repository = RepositoryProviderImpl::new(&database);
+                }
+                snake: &repository.snake_repository(),
+                slug: &repository.slug_repository(),
+                frog: &repository.frog_repository(),
+            }
+        }
+
+        mod transaction {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let database = database();
+                    seed(&database, contract::PREDATORS).await;
+                    let repository = RepositoryProviderImpl::new(&database);
+                    let transaction = repository.begin().await.unwrap();
+                }
+                foreign_keys: deferred,
+                snake: &transaction.snake_repository(),
+                slug: &transaction.slug_repository(),
+                frog: &transaction.frog_repository(),
+            }
+        }
+
+        mod cached {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let database = database();
+                    seed(&database, contract::PREDATORS).await;
+                    let repository = CachedRepository::new(
+                        RepositoryProviderImpl::new(&database),
+                        Arc::new(Cache::new(CacheConfig::default())),
+                    );
+                }
+                snake: &repository.snake_repository(),
+                slug: &repository.slug_repository(),
+                frog: &repository.frog_repository(),
+            }
+        }
     }
 }
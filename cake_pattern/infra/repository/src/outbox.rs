@@ -0,0 +1,328 @@
+use std::time::Duration;
+
+use database::{Database, Transaction};
+use domain::{Error, Event, Result};
+use message_queue::{Envelope, MessageQueue, Text};
+
+use crate::store::unclassified;
+
+/// The producer the relay publishes the outbox of a database as, see
+/// [`MessageQueue::publish_once`]. It names the outbox epoch, as the same
+/// outbox ID means another message in another database.
+fn producer_name(epoch: u64) -> String {
+    format!("outbox-{epoch:016x}")
+}
+
+/// The most [`OutboxRelay::run`] stretches its interval by while the database
+/// or the message queue is unavailable.
+const MAX_BACKOFF: u32 = 32;
+
+/// Events are published in their text form, see [`Event`], with the [`Text`]
+/// codec.
+const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// Adds the event to the outbox of the transaction, so that it is published
+/// if and only if the transaction commits.
+pub(crate) fn enqueue(transaction: &Transaction, event: &Event) -> Result<()> {
+    transaction
+        .enqueue(event.topic(), encode_event(event)?)
+        .map_err(unclassified)
+}
+
+/// The payload an event is published with.
+pub(crate) fn encode_event(event: &Event) -> Result<Vec<u8>> {
+    let envelope = Envelope {
+        timestamp: event.occurred_at,
+        ..Envelope::encode::<Text, _>(&event.to_string(), EVENT_SCHEMA_VERSION)
+            .map_err(Error::internal)?
+    };
+    envelope.to_bytes().map_err(Error::internal)
+}
+
+/// The event a payload carries, if it is one.
+pub(crate) fn decode_event(payload: &[u8]) -> Option<Event> {
+    let envelope = Envelope::from_bytes(payload).ok()?;
+    envelope.decode_with::<Text, String>().ok()?.parse().ok()
+}
+
+/// Publishes the messages left in the outbox by committed transactions,
+/// oldest first, and marks them sent.
+///
+/// Each message is published with its outbox ID as sequence number, so one
+/// that was published but not yet marked sent when the relay failed is not
+/// published twice. The sequence numbers are those of the database's outbox
+/// epoch, so a new in-memory database does not have its messages taken for
+/// ones a durable queue already holds. Only one relay may run per database.
+pub struct OutboxRelay<'r> {
+    database: &'r Database,
+    message_queue: &'r MessageQueue,
+    batch_size: usize,
+}
+
+impl<'r> OutboxRelay<'r> {
+    pub fn new(database: &'r Database, message_queue: &'r MessageQueue) -> Self {
+        Self {
+            database,
+            message_queue,
+            batch_size: 100,
+        }
+    }
+
+    /// Relays messages until the outbox is empty and returns how many were
+    /// published. No connection is held while publishing, which may wait
+    /// for room in a topic for as long as its subscribers take.
+    pub async fn relay(&self) -> Result<usize> {
+        let conn = self.database.conn().await.map_err(unclassified)?;
+        let epoch = conn.run(|conn| conn.outbox_epoch()).await;
+        let producer = producer_name(epoch.map_err(unclassified)?);
+        drop(conn);
+        let mut published = 0;
+        loop {
+            let batch_size = self.batch_size;
+            let conn = self.database.conn().await.map_err(unclassified)?;
+            let records = conn.run(move |conn| conn.unsent(batch_size)).await;
+            let records = records.map_err(unclassified)?;
+            drop(conn);
+            if records.is_empty() {
+                return Ok(published);
+            }
+            for record in records {
+                let offset = self
+                    .message_queue
+                    .publish_once(&record.topic, &producer, record.id, record.payload)
+                    .await
+                    .map_err(queue_error)?;
+                published += usize::from(offset.is_some());
+                let conn = self.database.conn().await.map_err(unclassified)?;
+                conn.run(move |conn| conn.mark_sent(record.id))
+                    .await
+                    .map_err(unclassified)?;
+            }
+        }
+    }
+
+    /// Relays the outbox every `interval`. While the database or the message
+    /// queue is unavailable the interval doubles, up to [`MAX_BACKOFF`]
+    /// times; any other failure stops the relay.
+    pub async fn run(&self, interval: Duration) -> Result<()> {
+        let mut backoff = 1;
+        loop {
+            match self.relay().await {
+                Ok(_) => backoff = 1,
+                Err(err) if err.is_transient() => {
+                    tracing::warn!(error = %err, "relaying the outbox failed");
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(err) => return Err(err),
+            }
+            tokio::time::sleep(interval * backoff).await;
+        }
+    }
+}
+
+/// A topic that is full, say because a subscriber fell behind, or closed
+/// while publishing is as unavailable as one that cannot be written to.
+fn queue_error(err: message_queue::Error) -> Error {
+    match err {
+        message_queue::Error::Io(_)
+        | message_queue::Error::Full(_)
+        | message_queue::Error::Closed(_) => Error::unavailable(err),
+        err => Error::internal(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use database::{DatabaseConfig, Migrator, Pool, PoolConfig};
+    use domain::{EventKind, SnakeID};
+    use message_queue::{MessageQueueConfig, MessageQueueConnection, OverflowPolicy, TopicConfig};
+
+    use super::*;
+
+    async fn database() -> Database {
+        database_with(PoolConfig::default()).await
+    }
+
+    async fn database_with(config: PoolConfig) -> Database {
+        let pool = Pool::open(&DatabaseConfig::Memory, config).unwrap();
+        Migrator::default()
+            .migrate(&pool.get().await.unwrap())
+            .unwrap();
+        Database::new(pool)
+    }
+
+    #[tokio::test]
+    async fn test_relay() {
+        let database = database().await;
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut subscription = message_queue.subscribe("snake").await.unwrap();
+        let relay = OutboxRelay {
+            batch_size: 2,
+            ..OutboxRelay::new(&database, &message_queue)
+        };
+        let tx = database.begin().await.unwrap();
+        for payload in ["1", "2", "3"] {
+            tx.enqueue("snake", payload).unwrap();
+        }
+        tx.commit().await.unwrap();
+        assert_eq!(relay.relay().await.unwrap(), 3);
+        assert_eq!(relay.relay().await.unwrap(), 0);
+        for payload in ["1", "2", "3"] {
+            assert_eq!(
+                subscription.recv().await.unwrap().payload,
+                payload.as_bytes()
+            );
+        }
+        assert!(database
+            .conn()
+            .await
+            .unwrap()
+            .unsent(10)
+            .unwrap()
+            .is_empty());
+    }
+
+    /// An event is published once the transaction it was enqueued in
+    /// commits, and never if it rolls back.
+    #[tokio::test]
+    async fn test_enqueue() {
+        let database = database().await;
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut subscription = message_queue.subscribe("snake").await.unwrap();
+        let relay = OutboxRelay::new(&database, &message_queue);
+        let eaten = |id| {
+            Event::now(EventKind::SnakeEaten {
+                snake: SnakeID::new(id).unwrap(),
+            })
+        };
+        let tx = database.begin().await.unwrap();
+        enqueue(&tx, &eaten(1)).unwrap();
+        tx.rollback().await.unwrap();
+        let tx = database.begin().await.unwrap();
+        enqueue(&tx, &eaten(2)).unwrap();
+        assert_eq!(relay.relay().await.unwrap(), 0);
+        tx.commit().await.unwrap();
+        assert_eq!(relay.relay().await.unwrap(), 1);
+        let message = subscription.recv().await.unwrap();
+        assert_eq!(decode_event(&message.payload).unwrap().kind, eaten(2).kind);
+    }
+
+    #[tokio::test]
+    async fn test_relay_skips_published() {
+        let database = database().await;
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let conn = database.conn().await.unwrap();
+        conn.enqueue("frog", "eaten").unwrap();
+        conn.enqueue("frog", "registered").unwrap();
+        drop(conn);
+        // A relay that failed after publishing the first message, before
+        // marking it sent.
+        let epoch = database.conn().await.unwrap().outbox_epoch().unwrap();
+        message_queue
+            .publish_once("frog", &producer_name(epoch), 1, "eaten")
+            .await
+            .unwrap();
+        let relay = OutboxRelay::new(&database, &message_queue);
+        assert_eq!(relay.relay().await.unwrap(), 1);
+        let messages = message_queue.read("frog", 0, 10).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].payload, b"registered");
+    }
+
+    /// Every in-memory database numbers its outbox from 1, so a durable
+    /// queue outliving one must not take the next one's messages for those
+    /// it already published.
+    #[tokio::test]
+    async fn test_relay_fresh_database_to_durable_queue() {
+        let dir = env::temp_dir().join(format!("outbox-relay-{}", process::id()));
+        _ = fs::remove_dir_all(&dir);
+        for payload in ["1", "2"] {
+            let database = database().await;
+            let message_queue = MessageQueue::new(
+                MessageQueueConnection::open(&MessageQueueConfig::File(dir.clone())).unwrap(),
+            );
+            database
+                .conn()
+                .await
+                .unwrap()
+                .enqueue("snake", payload)
+                .unwrap();
+            let relay = OutboxRelay::new(&database, &message_queue);
+            assert_eq!(relay.relay().await.unwrap(), 1);
+        }
+        let message_queue = MessageQueue::new(
+            MessageQueueConnection::open(&MessageQueueConfig::File(dir.clone())).unwrap(),
+        );
+        let messages = message_queue.read("snake", 0, 10).await.unwrap();
+        drop(message_queue);
+        fs::remove_dir_all(&dir).unwrap();
+        let payloads = messages.iter().map(|m| &m.payload[..]).collect::<Vec<_>>();
+        assert_eq!(payloads, [b"1", b"2"]);
+    }
+
+    #[tokio::test]
+    async fn test_relay_releases_connection_while_publishing() {
+        let database = database_with(PoolConfig {
+            max_size: 1,
+            checkout_timeout: Duration::from_millis(50),
+            ..PoolConfig::default()
+        })
+        .await;
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let config = TopicConfig {
+            capacity: Some(1),
+            overflow: OverflowPolicy::Block,
+        };
+        message_queue.configure_topic("slug", config).await.unwrap();
+        let mut subscription = message_queue.subscribe("slug").await.unwrap();
+        let conn = database.conn().await.unwrap();
+        conn.enqueue("slug", "1").unwrap();
+        conn.enqueue("slug", "2").unwrap();
+        drop(conn);
+        let relay = OutboxRelay::new(&database, &message_queue);
+        let relaying = relay.relay();
+        tokio::pin!(relaying);
+        // The second message waits for the subscriber to make room.
+        let blocked = tokio::time::timeout(Duration::from_millis(20), &mut relaying).await;
+        assert!(blocked.is_err());
+        let conn = database.conn().await.unwrap();
+        assert_eq!(conn.unsent(10).unwrap().len(), 1);
+        drop(conn);
+        assert_eq!(subscription.recv().await.unwrap().payload, b"1");
+        assert_eq!(relaying.await.unwrap(), 2);
+    }
+
+    /// A subscriber falling behind a topic that refuses to overflow holds
+    /// the relay back until it catches up, rather than stopping it.
+    #[tokio::test]
+    async fn test_run_waits_for_full_topic() {
+        let database = database().await;
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let config = TopicConfig {
+            capacity: Some(1),
+            overflow: OverflowPolicy::Error,
+        };
+        message_queue.configure_topic("slug", config).await.unwrap();
+        let mut subscription = message_queue.subscribe("slug").await.unwrap();
+        let conn = database.conn().await.unwrap();
+        conn.enqueue("slug", "1").unwrap();
+        conn.enqueue("slug", "2").unwrap();
+        drop(conn);
+        let relay = OutboxRelay::new(&database, &message_queue);
+        assert!(relay.relay().await.unwrap_err().is_transient());
+
+        let running = relay.run(Duration::from_millis(1));
+        tokio::pin!(running);
+        let received = async {
+            let first = subscription.recv().await.unwrap();
+            let second = subscription.recv().await.unwrap();
+            [first.payload, second.payload]
+        };
+        tokio::select! {
+            result = &mut running => panic!("the relay stopped: {result:?}"),
+            payloads = received => assert_eq!(payloads, [b"1", b"2"]),
+        }
+    }
+}
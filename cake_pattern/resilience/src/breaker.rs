@@ -0,0 +1,665 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use domain::{
+    Error, Frog, FrogID, FrogRepository, RepositoryProvider, Result, Slug, SlugID, SlugRepository,
+    Snake, SnakeID, SnakeRepository, Transaction, TransactionProvider,
+};
+use thiserror::Error;
+
+/// The error calls fail fast with while the circuit is open, as the source of
+/// an [`Error::Unavailable`].
+#[derive(Debug, Error)]
+#[error("circuit {0} is open")]
+pub struct CircuitOpen(pub String);
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    /// The share of failed calls in the window at which the circuit opens.
+    pub failure_rate: f64,
+    /// How far back calls count towards the failure rate.
+    pub window: Duration,
+    /// Calls the window must hold before the circuit can open, so a single
+    /// early failure does not.
+    pub minimum_calls: usize,
+    /// How long the circuit stays open before letting probes through.
+    pub open_for: Duration,
+    /// Probes let through at once while half-open; the circuit closes once
+    /// that many succeed and opens again as soon as one fails.
+    pub probes: u32,
+    /// Which failures count against the backend. Others, such as
+    /// [`Error::NotFound`], mean it is answering.
+    pub failure: fn(&Error) -> bool,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_rate: 0.5,
+            window: Duration::from_secs(10),
+            minimum_calls: 10,
+            open_for: Duration::from_secs(5),
+            probes: 3,
+            failure: Error::is_transient,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through; their outcomes are counted.
+    Closed,
+    /// Calls fail fast without reaching the backend.
+    Open,
+    /// A few probe calls go through to see whether the backend recovered.
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half-open",
+        })
+    }
+}
+
+/// A point-in-time view of a circuit, for health reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerStats {
+    pub state: CircuitState,
+    /// Calls in the window while closed.
+    pub calls: usize,
+    pub failures: usize,
+    /// Times the circuit opened.
+    pub opened: u64,
+    /// Calls failed fast while open.
+    pub rejected: u64,
+}
+
+impl fmt::Display for BreakerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}/{} failed, opened {} times, {} rejected",
+            self.state, self.failures, self.calls, self.opened, self.rejected
+        )
+    }
+}
+
+#[derive(Debug)]
+enum Circuit {
+    /// The outcomes in the window, oldest first; `true` for a failure.
+    Closed(VecDeque<(Instant, bool)>),
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        in_flight: u32,
+        succeeded: u32,
+    },
+}
+
+#[derive(Debug)]
+struct State {
+    circuit: Circuit,
+    /// Bumped on every transition, so outcomes of calls admitted before one
+    /// are ignored.
+    epoch: u64,
+    opened: u64,
+    rejected: u64,
+}
+
+impl State {
+    fn transition(&mut self, circuit: Circuit) {
+        if matches!(circuit, Circuit::Open { .. }) {
+            self.opened += 1;
+        }
+        self.circuit = circuit;
+        self.epoch += 1;
+    }
+}
+
+/// Stops calling a backend that keeps failing, giving it time to recover.
+///
+/// Closed, it counts the failures in a sliding window and opens once they
+/// reach the failure rate. Open, calls fail fast with [`CircuitOpen`] until
+/// it is time to probe. Half-open, it lets a few probes through and closes or
+/// opens again depending on how they fare.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    config: BreakerConfig,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: impl Into<String>, config: BreakerConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            state: Mutex::new(State {
+                circuit: Circuit::Closed(VecDeque::new()),
+                epoch: 0,
+                opened: 0,
+                rejected: 0,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.stats().state
+    }
+
+    pub fn stats(&self) -> BreakerStats {
+        let mut state = self.lock();
+        let now = Instant::now();
+        let (state_now, calls, failures) = match &mut state.circuit {
+            Circuit::Closed(outcomes) => {
+                prune(outcomes, now, self.config.window);
+                let failures = outcomes.iter().filter(|(_, failed)| *failed).count();
+                (CircuitState::Closed, outcomes.len(), failures)
+            }
+            Circuit::Open { until } if *until <= now => (CircuitState::HalfOpen, 0, 0),
+            Circuit::Open { .. } => (CircuitState::Open, 0, 0),
+            Circuit::HalfOpen { .. } => (CircuitState::HalfOpen, 0, 0),
+        };
+        BreakerStats {
+            state: state_now,
+            calls,
+            failures,
+            opened: state.opened,
+            rejected: state.rejected,
+        }
+    }
+
+    /// Runs the call if the circuit lets it through, and counts its outcome.
+    pub async fn call<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let mut admission = self.admit()?;
+        let result = call.await;
+        let failed = result.as_ref().err().is_some_and(self.config.failure);
+        self.record(&mut admission, failed);
+        result
+    }
+
+    fn admit(&self) -> Result<Admission<'_>> {
+        let mut state = self.lock();
+        let now = Instant::now();
+        if let Circuit::Open { until } = state.circuit {
+            if until <= now {
+                state.transition(Circuit::HalfOpen {
+                    in_flight: 0,
+                    succeeded: 0,
+                });
+            }
+        }
+        let probe = match &mut state.circuit {
+            Circuit::Closed(_) => false,
+            Circuit::HalfOpen {
+                in_flight,
+                succeeded,
+            } if *in_flight + *succeeded < self.config.probes => {
+                *in_flight += 1;
+                true
+            }
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => {
+                state.rejected += 1;
+                return Err(Error::unavailable(CircuitOpen(self.name.clone())));
+            }
+        };
+        Ok(Admission {
+            breaker: self,
+            epoch: state.epoch,
+            probe,
+            recorded: false,
+        })
+    }
+
+    fn record(&self, admission: &mut Admission<'_>, failed: bool) {
+        admission.recorded = true;
+        let mut state = self.lock();
+        if state.epoch != admission.epoch {
+            return;
+        }
+        let now = Instant::now();
+        let config = &self.config;
+        let next = match &mut state.circuit {
+            Circuit::Closed(outcomes) => {
+                outcomes.push_back((now, failed));
+                prune(outcomes, now, config.window);
+                let failures = outcomes.iter().filter(|(_, failed)| *failed).count();
+                let tripped = outcomes.len() >= config.minimum_calls
+                    && failures as f64 >= config.failure_rate * outcomes.len() as f64;
+                tripped.then(|| Circuit::Open {
+                    until: now + config.open_for,
+                })
+            }
+            Circuit::HalfOpen { .. } if failed => Some(Circuit::Open {
+                until: now + config.open_for,
+            }),
+            Circuit::HalfOpen {
+                in_flight,
+                succeeded,
+            } => {
+                *in_flight -= 1;
+                *succeeded += 1;
+                (*succeeded >= config.probes).then(|| Circuit::Closed(VecDeque::new()))
+            }
+            Circuit::Open { .. } => None,
+        };
+        if let Some(next) = next {
+            state.transition(next);
+        }
+    }
+
+    /// Frees the probe slot of a call that was dropped before it finished.
+    fn release(&self, admission: &Admission<'_>) {
+        let mut state = self.lock();
+        if state.epoch != admission.epoch {
+            return;
+        }
+        if let Circuit::HalfOpen { in_flight, .. } = &mut state.circuit {
+            *in_flight -= 1;
+        }
+    }
+
+    /// Every transition leaves a circuit the breaker can go on from, so a
+    /// panic while the state was locked does not stop it guarding calls.
+    /// Releasing a probe runs on drop, where panicking again would abort.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn prune(outcomes: &mut VecDeque<(Instant, bool)>, now: Instant, window: Duration) {
+    while outcomes
+        .front()
+        .is_some_and(|(at, _)| now.duration_since(*at) > window)
+    {
+        outcomes.pop_front();
+    }
+}
+
+/// A call let through, until its outcome is recorded.
+struct Admission<'a> {
+    breaker: &'a CircuitBreaker,
+    epoch: u64,
+    probe: bool,
+    recorded: bool,
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            self.breaker.release(self);
+        }
+    }
+}
+
+/// Guards the calls of the inner repository with a [`CircuitBreaker`].
+pub struct Breaker<R> {
+    inner: R,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<R> Breaker<R> {
+    pub fn new(inner: R, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn wrap<U>(&self, inner: U) -> Breaker<U> {
+        Breaker::new(inner, Arc::clone(&self.breaker))
+    }
+}
+
+#[async_trait]
+impl<R: SnakeRepository> SnakeRepository for Breaker<R> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.breaker.call(self.inner.get_snake(id)).await
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        self.breaker.call(self.inner.insert_snake(snake)).await
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        self.breaker.call(self.inner.update_snake(snake)).await
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        self.breaker.call(self.inner.upsert_snake(snake)).await
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        self.breaker.call(self.inner.delete_snake(id)).await
+    }
+}
+
+#[async_trait]
+impl<R: SlugRepository> SlugRepository for Breaker<R> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.breaker.call(self.inner.get_slug(id)).await
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        self.breaker.call(self.inner.insert_slug(slug)).await
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        self.breaker.call(self.inner.update_slug(slug)).await
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        self.breaker.call(self.inner.upsert_slug(slug)).await
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        self.breaker.call(self.inner.delete_slug(id)).await
+    }
+}
+
+#[async_trait]
+impl<R: FrogRepository> FrogRepository for Breaker<R> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.breaker.call(self.inner.get_frog(id)).await
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        self.breaker.call(self.inner.insert_frog(frog)).await
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        self.breaker.call(self.inner.update_frog(frog)).await
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        self.breaker.call(self.inner.upsert_frog(frog)).await
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        self.breaker.call(self.inner.delete_frog(id)).await
+    }
+}
+
+/// Hands out repositories guarded by the same circuit.
+impl<R: RepositoryProvider> RepositoryProvider for Breaker<R> {
+    type SnakeRepository<'a> = Breaker<R::SnakeRepository<'a>> where Self: 'a;
+    type SlugRepository<'a> = Breaker<R::SlugRepository<'a>> where Self: 'a;
+    type FrogRepository<'a> = Breaker<R::FrogRepository<'a>> where Self: 'a;
+
+    fn snake_repository(&self) -> Self::SnakeRepository<'_> {
+        self.wrap(self.inner.snake_repository())
+    }
+    fn slug_repository(&self) -> Self::SlugRepository<'_> {
+        self.wrap(self.inner.slug_repository())
+    }
+    fn frog_repository(&self) -> Self::FrogRepository<'_> {
+        self.wrap(self.inner.frog_repository())
+    }
+}
+
+#[async_trait]
+impl<R: TransactionProvider> TransactionProvider for Breaker<R> {
+    type Transaction = Breaker<R::Transaction>;
+
+    async fn begin(&self) -> Result<Self::Transaction> {
+        let transaction = self.breaker.call(self.inner.begin()).await?;
+        Ok(self.wrap(transaction))
+    }
+}
+
+/// Guards the transaction's calls and its commit with the circuit it was
+/// begun through. Rolling back is let through even while the circuit is
+/// open, so a transaction can always be abandoned.
+#[async_trait]
+impl<T: Transaction> Transaction for Breaker<T> {
+    type SnakeRepository = Breaker<T::SnakeRepository>;
+    type SlugRepository = Breaker<T::SlugRepository>;
+    type FrogRepository = Breaker<T::FrogRepository>;
+
+    fn snake_repository(&self) -> Self::SnakeRepository {
+        self.wrap(self.inner.snake_repository())
+    }
+    fn slug_repository(&self) -> Self::SlugRepository {
+        self.wrap(self.inner.slug_repository())
+    }
+    fn frog_repository(&self) -> Self::FrogRepository {
+        self.wrap(self.inner.frog_repository())
+    }
+    async fn commit(self) -> Result<()> {
+        self.breaker.call(self.inner.commit()).await
+    }
+    async fn rollback(self) -> Result<()> {
+        self.inner.rollback().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        error::Error as _,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use domain::{EntityID, MockSnakeRepository, MockTransaction, MockTransactionProvider};
+    use tokio::time;
+
+    use super::*;
+
+    const OPEN_FOR: Duration = Duration::from_millis(20);
+
+    fn breaker(probes: u32) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "database",
+            BreakerConfig {
+                failure_rate: 0.5,
+                minimum_calls: 4,
+                open_for: OPEN_FOR,
+                probes,
+                ..BreakerConfig::default()
+            },
+        )
+    }
+
+    async fn fail(breaker: &CircuitBreaker) -> Result<()> {
+        breaker
+            .call(async { Err(Error::unavailable("database busy")) })
+            .await
+    }
+
+    async fn succeed(breaker: &CircuitBreaker) -> Result<()> {
+        breaker.call(async { Ok(()) }).await
+    }
+
+    fn is_open(result: Result<()>) -> bool {
+        matches!(result, Err(Error::Unavailable(source)) if source.is::<CircuitOpen>())
+    }
+
+    #[tokio::test]
+    async fn test_opens_at_failure_rate() {
+        let breaker = breaker(1);
+        for _ in 0..3 {
+            fail(&breaker).await.unwrap_err();
+        }
+        // Too few calls yet to judge.
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        succeed(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(is_open(succeed(&breaker).await));
+        let stats = breaker.stats();
+        assert_eq!((stats.opened, stats.rejected), (1, 1));
+        assert_eq!(
+            stats.to_string(),
+            "open, 0/0 failed, opened 1 times, 1 rejected"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stays_closed_below_failure_rate() {
+        let breaker = breaker(1);
+        for _ in 0..3 {
+            succeed(&breaker).await.unwrap();
+        }
+        fail(&breaker).await.unwrap_err();
+        let stats = breaker.stats();
+        assert_eq!(stats.state, CircuitState::Closed);
+        assert_eq!((stats.failures, stats.calls), (1, 4));
+    }
+
+    #[tokio::test]
+    async fn test_only_backend_failures_count() {
+        let breaker = breaker(1);
+        for _ in 0..8 {
+            let result = breaker
+                .call(async { Err::<(), _>(Error::NotFound(SnakeID::new(1).unwrap().into())) })
+                .await;
+            assert!(matches!(result, Err(Error::NotFound(EntityID::Snake(_)))));
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_probes_close() {
+        let breaker = breaker(2);
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        time::sleep(OPEN_FOR).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        succeed(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        succeed(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_failed_probe_reopens() {
+        let breaker = breaker(2);
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        time::sleep(OPEN_FOR).await;
+        fail(&breaker).await.unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.stats().opened, 2);
+    }
+
+    #[tokio::test]
+    async fn test_probes_are_limited() {
+        let breaker = breaker(1);
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        time::sleep(OPEN_FOR).await;
+        let release = Arc::new(tokio::sync::Notify::new());
+        let probing = AtomicBool::new(false);
+        let probe = breaker.call(async {
+            probing.store(true, Ordering::Relaxed);
+            release.notified().await;
+            Ok(())
+        });
+        let other = async {
+            while !probing.load(Ordering::Relaxed) {
+                tokio::task::yield_now().await;
+            }
+            let rejected = is_open(succeed(&breaker).await);
+            release.notify_one();
+            rejected
+        };
+        let (probed, rejected) = tokio::join!(probe, other);
+        probed.unwrap();
+        assert!(rejected, "only one probe at a time");
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_probe_frees_its_slot() {
+        let breaker = breaker(1);
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        time::sleep(OPEN_FOR).await;
+        let abandoned = time::timeout(
+            Duration::from_millis(1),
+            breaker.call(std::future::pending::<Result<()>>()),
+        );
+        abandoned.await.unwrap_err();
+        succeed(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_breaker_goes_on() {
+        let breaker = Arc::new(breaker(1));
+        let poisoner = Arc::clone(&breaker);
+        std::thread::spawn(move || {
+            let _state = poisoner.lock();
+            panic!("poisoning the circuit lock");
+        })
+        .join()
+        .unwrap_err();
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(is_open(succeed(&breaker).await));
+    }
+
+    #[tokio::test]
+    async fn test_repository() {
+        let mut inner = MockSnakeRepository::new();
+        inner
+            .expect_get_snake()
+            .times(4)
+            .returning(|_| Err(Error::unavailable("database busy")));
+        let repository = Breaker::new(inner, Arc::new(breaker(1)));
+        let id = SnakeID::new(1).unwrap();
+        for _ in 0..4 {
+            repository.get_snake(id).await.unwrap_err();
+        }
+        let err = repository.get_snake(id).await.unwrap_err();
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            "circuit database is open"
+        );
+        assert_eq!(repository.breaker().state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let mut transaction = MockTransaction::new();
+        transaction.expect_snake_repository().returning(|| {
+            let mut snake_repository = MockSnakeRepository::new();
+            snake_repository
+                .expect_get_snake()
+                .returning(|_| Err(Error::unavailable("database busy")));
+            snake_repository
+        });
+        transaction.expect_commit().never();
+        transaction.expect_rollback().return_once(|| Ok(()));
+        let mut inner = MockTransactionProvider::new();
+        inner.expect_begin().return_once(move || Ok(transaction));
+        let repository = Breaker::new(inner, Arc::new(breaker(1)));
+        let transaction = repository.begin().await.unwrap();
+        let id = SnakeID::new(1).unwrap();
+        for _ in 0..3 {
+            transaction
+                .snake_repository()
+                .get_snake(id)
+                .await
+                .unwrap_err();
+        }
+        assert_eq!(repository.breaker().state(), CircuitState::Open);
+        assert!(is_open(
+            transaction.snake_repository().get_snake(id).await.map(drop)
+        ));
+        transaction.rollback().await.unwrap();
+    }
+}
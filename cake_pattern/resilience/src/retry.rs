@@ -0,0 +1,493 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use domain::{
+    Error, FoodChainUseCase, Frog, FrogID, FrogRepository, FrogUseCase, RepositoryProvider, Result,
+    Slug, SlugID, SlugRepository, SlugUseCase, Snake, SnakeID, SnakeRepository, SnakeUseCase,
+    TransactionProvider, UseCaseProvider,
+};
+use tokio::time::{self, Instant};
+
+use crate::CircuitOpen;
+
+/// How often and how patiently a failed call is attempted again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first one included.
+    pub max_attempts: u32,
+    /// The backoff before the second attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// What the backoff is multiplied by after each attempt.
+    pub multiplier: f64,
+    /// The fraction of each backoff that is random, from 0 for none to 1 for
+    /// anywhere between no wait and the full backoff. Spreads out the retries
+    /// of callers that failed together.
+    pub jitter: f64,
+    /// The time all attempts and backoffs together may take; an attempt still
+    /// running then is abandoned.
+    pub deadline: Option<Duration>,
+    /// Which failures are worth another attempt, by default
+    /// [`is_retryable`] ones.
+    pub retryable: fn(&Error) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter: 0.5,
+            deadline: Some(Duration::from_secs(5)),
+            retryable: is_retryable,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Calls `attempt` until it succeeds, fails for good or the policy gives
+    /// up, and returns its last result.
+    pub async fn run<T, F, Fut>(&self, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+        let mut backoff = self.initial_backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = match deadline {
+                Some(deadline) => match time::timeout_at(deadline, attempt()).await {
+                    Ok(result) => result,
+                    Err(elapsed) => {
+                        return Err(Error::unavailable(elapsed)
+                            .context(format!("deadline exceeded on attempt {attempts}")))
+                    }
+                },
+                None => attempt().await,
+            };
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(err) if !(self.retryable)(&err) => return Err(err),
+                Err(err) => err,
+            };
+            let delay = self.jittered(backoff);
+            let out_of_time = deadline.is_some_and(|deadline| Instant::now() + delay >= deadline);
+            if attempts >= self.max_attempts || out_of_time {
+                return Err(err.context(format!("gave up after {attempts} attempts")));
+            }
+            time::sleep(delay).await;
+            backoff = backoff.mul_f64(self.multiplier).min(self.max_backoff);
+        }
+    }
+
+    fn jittered(&self, backoff: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        backoff.mul_f64(1.0 - jitter * rand::random::<f64>())
+    }
+}
+
+/// Whether a failure is worth another attempt: it is transient, but not a
+/// call the circuit breaker turned away, since the circuit stays open for
+/// longer than a retry would wait.
+pub fn is_retryable(err: &Error) -> bool {
+    err.is_transient()
+        && !matches!(err.root(), Error::Unavailable(source) if source.is::<CircuitOpen>())
+}
+
+/// Retries the calls of the inner repository or use case that fail with a
+/// retryable error, as the policy says.
+///
+/// A write that failed may still have taken effect, so a retried insert can
+/// fail with [`Error::Conflict`]. Wrap one layer only: retries at two layers
+/// multiply.
+pub struct Retry<T> {
+    inner: T,
+    policy: Arc<RetryPolicy>,
+}
+
+impl<T> Retry<T> {
+    pub fn new(inner: T, policy: Arc<RetryPolicy>) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn wrap<U>(&self, inner: U) -> Retry<U> {
+        Retry::new(inner, Arc::clone(&self.policy))
+    }
+}
+
+#[async_trait]
+impl<R: SnakeRepository> SnakeRepository for Retry<R> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.policy.run(|| self.inner.get_snake(id)).await
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        self.policy
+            .run(|| self.inner.insert_snake(snake.clone()))
+            .await
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        self.policy
+            .run(|| self.inner.update_snake(snake.clone()))
+            .await
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        self.policy
+            .run(|| self.inner.upsert_snake(snake.clone()))
+            .await
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        self.policy.run(|| self.inner.delete_snake(id)).await
+    }
+}
+
+#[async_trait]
+impl<R: SlugRepository> SlugRepository for Retry<R> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.policy.run(|| self.inner.get_slug(id)).await
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        self.policy
+            .run(|| self.inner.insert_slug(slug.clone()))
+            .await
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        self.policy
+            .run(|| self.inner.update_slug(slug.clone()))
+            .await
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        self.policy
+            .run(|| self.inner.upsert_slug(slug.clone()))
+            .await
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        self.policy.run(|| self.inner.delete_slug(id)).await
+    }
+}
+
+#[async_trait]
+impl<R: FrogRepository> FrogRepository for Retry<R> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.policy.run(|| self.inner.get_frog(id)).await
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        self.policy
+            .run(|| self.inner.insert_frog(frog.clone()))
+            .await
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        self.policy
+            .run(|| self.inner.update_frog(frog.clone()))
+            .await
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        self.policy
+            .run(|| self.inner.upsert_frog(frog.clone()))
+            .await
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        self.policy.run(|| self.inner.delete_frog(id)).await
+    }
+}
+
+#[async_trait]
+impl<U: SnakeUseCase> SnakeUseCase for Retry<U> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.policy.run(|| self.inner.get_snake(id)).await
+    }
+    async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake> {
+        self.policy
+            .run(|| self.inner.get_snake_eating_frog(frog_id))
+            .await
+    }
+    async fn register_snake(&self, snake: Snake) -> Result<Snake> {
+        self.policy
+            .run(|| self.inner.register_snake(snake.clone()))
+            .await
+    }
+    async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog> {
+        self.policy
+            .run(|| self.inner.set_snake_eating_frog(frog_id, snake_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl<U: SlugUseCase> SlugUseCase for Retry<U> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.policy.run(|| self.inner.get_slug(id)).await
+    }
+    async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug> {
+        self.policy
+            .run(|| self.inner.get_slug_eating_snake(snake_id))
+            .await
+    }
+    async fn register_slug(&self, slug: Slug) -> Result<Slug> {
+        self.policy
+            .run(|| self.inner.register_slug(slug.clone()))
+            .await
+    }
+    async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake> {
+        self.policy
+            .run(|| self.inner.set_slug_eating_snake(snake_id, slug_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl<U: FrogUseCase> FrogUseCase for Retry<U> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.policy.run(|| self.inner.get_frog(id)).await
+    }
+    async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog> {
+        self.policy
+            .run(|| self.inner.get_frog_eating_slug(slug_id))
+            .await
+    }
+    async fn register_frog(&self, frog: Frog) -> Result<Frog> {
+        self.policy
+            .run(|| self.inner.register_frog(frog.clone()))
+            .await
+    }
+    async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug> {
+        self.policy
+            .run(|| self.inner.set_frog_eating_slug(slug_id, frog_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl<U: FoodChainUseCase> FoodChainUseCase for Retry<U> {
+    async fn register_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        self.policy
+            .run(|| {
+                self.inner
+                    .register_food_chain(snakes.clone(), slugs.clone(), frogs.clone())
+            })
+            .await
+    }
+}
+
+/// Hands out repositories that retry. Transactions are begun again if that
+/// fails, but their repositories do not retry: a transaction is retried as a
+/// whole, by retrying the use case that runs it.
+impl<R: RepositoryProvider> RepositoryProvider for Retry<R> {
+    type SnakeRepository<'a> = Retry<R::SnakeRepository<'a>> where Self: 'a;
+    type SlugRepository<'a> = Retry<R::SlugRepository<'a>> where Self: 'a;
+    type FrogRepository<'a> = Retry<R::FrogRepository<'a>> where Self: 'a;
+
+    fn snake_repository(&self) -> Self::SnakeRepository<'_> {
+        self.wrap(self.inner.snake_repository())
+    }
+    fn slug_repository(&self) -> Self::SlugRepository<'_> {
+        self.wrap(self.inner.slug_repository())
+    }
+    fn frog_repository(&self) -> Self::FrogRepository<'_> {
+        self.wrap(self.inner.frog_repository())
+    }
+}
+
+#[async_trait]
+impl<R: TransactionProvider> TransactionProvider for Retry<R> {
+    type Transaction = R::Transaction;
+
+    async fn begin(&self) -> Result<Self::Transaction> {
+        self.policy.run(|| self.inner.begin()).await
+    }
+}
+
+impl<U: UseCaseProvider> UseCaseProvider for Retry<U> {
+    type SnakeUseCase<'a> = Retry<U::SnakeUseCase<'a>> where Self: 'a;
+    type SlugUseCase<'a> = Retry<U::SlugUseCase<'a>> where Self: 'a;
+    type FrogUseCase<'a> = Retry<U::FrogUseCase<'a>> where Self: 'a;
+    type FoodChainUseCase<'a> = Retry<U::FoodChainUseCase<'a>> where Self: 'a;
+
+    fn snake_use_case(&self) -> Self::SnakeUseCase<'_> {
+        self.wrap(self.inner.snake_use_case())
+    }
+    fn slug_use_case(&self) -> Self::SlugUseCase<'_> {
+        self.wrap(self.inner.slug_use_case())
+    }
+    fn frog_use_case(&self) -> Self::FrogUseCase<'_> {
+        self.wrap(self.inner.frog_use_case())
+    }
+    fn food_chain_use_case(&self) -> Self::FoodChainUseCase<'_> {
+        self.wrap(self.inner.food_chain_use_case())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use domain::{EntityID, MockFrogUseCase, MockSnakeRepository};
+
+    use super::*;
+    use crate::{Breaker, BreakerConfig, CircuitBreaker};
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn policy(max_attempts: u32) -> Arc<RetryPolicy> {
+        Arc::new(RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        })
+    }
+
+    /// A repository that is unavailable for the first `failures` calls.
+    fn flaky(failures: u32) -> MockSnakeRepository {
+        let calls = AtomicU32::new(0);
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().returning(move |_| {
+            if calls.fetch_add(1, Ordering::Relaxed) < failures {
+                Err(Error::unavailable("database busy"))
+            } else {
+                Ok(snake())
+            }
+        });
+        inner
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let repository = Retry::new(flaky(2), policy(3));
+        assert_eq!(repository.get_snake(snake().id).await.unwrap(), snake());
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let repository = Retry::new(flaky(3), policy(3));
+        let err = repository.get_snake(snake().id).await.unwrap_err();
+        assert_eq!(err.to_string(), "gave up after 3 attempts");
+        assert!(matches!(err.root(), Error::Unavailable(_)));
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_is_not_retried() {
+        let mut inner = MockSnakeRepository::new();
+        inner
+            .expect_get_snake()
+            .times(1)
+            .returning(|_| Err(Error::unavailable("database busy")));
+        let breaker = CircuitBreaker::new(
+            "database",
+            BreakerConfig {
+                minimum_calls: 1,
+                ..BreakerConfig::default()
+            },
+        );
+        let repository = Retry::new(Breaker::new(inner, Arc::new(breaker)), policy(3));
+        // The first attempt opens the circuit and the second is turned away.
+        let err = repository.get_snake(snake().id).await.unwrap_err();
+        assert!(matches!(err, Error::Unavailable(source) if source.is::<CircuitOpen>()));
+        let err = repository.get_snake(snake().id).await.unwrap_err();
+        assert!(matches!(err, Error::Unavailable(source) if source.is::<CircuitOpen>()));
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_are_not_retried() {
+        let mut inner = MockSnakeRepository::new();
+        inner
+            .expect_insert_snake()
+            .times(1)
+            .returning(|snake| Err(Error::Conflict(snake.id.into())));
+        let repository = Retry::new(inner, policy(3));
+        assert!(matches!(
+            repository.insert_snake(snake()).await,
+            Err(Error::Conflict(EntityID::Snake(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let mut inner = MockSnakeRepository::new();
+        inner
+            .expect_get_snake()
+            .returning(|_| Err(Error::unavailable("database busy")));
+        let policy = RetryPolicy {
+            max_attempts: u32::MAX,
+            deadline: Some(Duration::from_millis(20)),
+            ..(*policy(0)).clone()
+        };
+        let repository = Retry::new(inner, Arc::new(policy));
+        let start = Instant::now();
+        assert!(repository
+            .get_snake(snake().id)
+            .await
+            .unwrap_err()
+            .is_transient());
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_abandons_slow_attempt() {
+        let policy = RetryPolicy {
+            deadline: Some(Duration::from_millis(10)),
+            ..RetryPolicy::default()
+        };
+        let result = policy
+            .run(|| async {
+                time::sleep(Duration::from_secs(1)).await;
+                Ok(())
+            })
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "deadline exceeded on attempt 1"
+        );
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy {
+            jitter: 1.0,
+            ..RetryPolicy::default()
+        };
+        let backoff = Duration::from_millis(100);
+        let delays: Vec<_> = (0..16).map(|_| policy.jittered(backoff)).collect();
+        assert!(delays.iter().all(|delay| *delay <= backoff));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[tokio::test]
+    async fn test_use_case() {
+        let calls = AtomicU32::new(0);
+        let mut inner = MockFrogUseCase::new();
+        inner.expect_get_frog_eating_slug().returning(move |_| {
+            match calls.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(Error::unavailable("database busy").context("getting slug 1")),
+                _ => Ok(Frog {
+                    id: FrogID::new(3).unwrap(),
+                    eaten_by: snake().id,
+                }),
+            }
+        });
+        let use_case = Retry::new(inner, policy(2));
+        let frog = use_case
+            .get_frog_eating_slug(SlugID::new(1).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(frog.id, FrogID::new(3).unwrap());
+    }
+}
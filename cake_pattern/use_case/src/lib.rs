@@ -1,113 +1,299 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use domain::{
-    Frog, FrogID, FrogRepository, FrogUseCase, RepositoryProvider, Slug, SlugID, SlugRepository,
-    SlugUseCase, Snake, SnakeID, SnakeRepository, SnakeUseCase, UseCaseProvider,
+    FoodChainUseCase, Frog, FrogID, FrogRepository, FrogUseCase, Result, Slug, SlugID,
+    SlugRepository, SlugUseCase, Snake, SnakeID, SnakeRepository, SnakeUseCase, Transaction,
+    TransactionProvider, UseCaseProvider,
 };
+use tracing::instrument;
 
-pub struct UseCaseProviderImpl<'rp, RP: RepositoryProvider> {
+pub struct UseCaseProviderImpl<'rp, RP: TransactionProvider> {
     repository: &'rp RP,
 }
 
-impl<'rp, RP: RepositoryProvider> UseCaseProviderImpl<'rp, RP> {
+impl<'rp, RP: TransactionProvider> UseCaseProviderImpl<'rp, RP> {
     pub fn new(repository: &'rp RP) -> Self {
         Self { repository }
     }
 }
 
-impl<'rp, RP: RepositoryProvider> UseCaseProvider for UseCaseProviderImpl<'rp, RP> {
-    type SnakeUseCase<'a> = SnakeUseCaseImpl<RP::SnakeRepository<'rp>, RP::FrogRepository<'rp>> where 'rp : 'a;
-    type SlugUseCase<'a> = SlugUseCaseImpl<RP::SlugRepository<'rp>, RP::SnakeRepository<'rp>> where 'rp: 'a;
-    type FrogUseCase<'a> = FrogUseCaseImpl<RP::FrogRepository<'rp>, RP::SlugRepository<'rp>> where 'rp: 'a;
+impl<'rp, RP: TransactionProvider> UseCaseProvider for UseCaseProviderImpl<'rp, RP> {
+    type SnakeUseCase<'a> = SnakeUseCaseImpl<'rp, RP, RP::SnakeRepository<'rp>, RP::FrogRepository<'rp>> where 'rp : 'a;
+    type SlugUseCase<'a> = SlugUseCaseImpl<'rp, RP, RP::SlugRepository<'rp>, RP::SnakeRepository<'rp>> where 'rp: 'a;
+    type FrogUseCase<'a> = FrogUseCaseImpl<'rp, RP, RP::FrogRepository<'rp>, RP::SlugRepository<'rp>> where 'rp: 'a;
+    type FoodChainUseCase<'a> = FoodChainUseCaseImpl<'rp, RP> where 'rp: 'a;
 
     fn snake_use_case(&self) -> Self::SnakeUseCase<'_> {
         Self::SnakeUseCase {
+            transaction_provider: self.repository,
             snake_repository: self.repository.snake_repository(),
             frog_repository: self.repository.frog_repository(),
         }
     }
     fn slug_use_case(&self) -> Self::SlugUseCase<'_> {
         Self::SlugUseCase {
+            transaction_provider: self.repository,
             slug_repository: self.repository.slug_repository(),
             snake_repository: self.repository.snake_repository(),
         }
     }
     fn frog_use_case(&self) -> Self::FrogUseCase<'_> {
         Self::FrogUseCase {
+            transaction_provider: self.repository,
             frog_repository: self.repository.frog_repository(),
             slug_repository: self.repository.slug_repository(),
         }
     }
+    fn food_chain_use_case(&self) -> Self::FoodChainUseCase<'_> {
+        Self::FoodChainUseCase {
+            transaction_provider: self.repository,
+        }
+    }
 }
 
-pub struct SnakeUseCaseImpl<SnakeR: SnakeRepository, FrogR: FrogRepository> {
+pub struct SnakeUseCaseImpl<
+    'rp,
+    TP: TransactionProvider,
+    SnakeR: SnakeRepository,
+    FrogR: FrogRepository,
+> {
+    transaction_provider: &'rp TP,
     snake_repository: SnakeR,
     frog_repository: FrogR,
 }
 
 #[async_trait]
-impl<SnakeR: SnakeRepository, FrogR: FrogRepository> SnakeUseCase
-    for SnakeUseCaseImpl<SnakeR, FrogR>
+impl<'rp, TP: TransactionProvider, SnakeR: SnakeRepository, FrogR: FrogRepository> SnakeUseCase
+    for SnakeUseCaseImpl<'rp, TP, SnakeR, FrogR>
 {
+    #[instrument(skip_all, fields(snake = %id))]
     async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
         self.snake_repository.get_snake(id).await
     }
+    #[instrument(skip_all, fields(frog = %frog_id))]
     async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake> {
         let frog = self.frog_repository.get_frog(frog_id).await?;
         self.snake_repository.get_snake(frog.eaten_by).await
     }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn register_snake(&self, snake: Snake) -> Result<Snake> {
+        let transaction = self.transaction_provider.begin().await?;
+        transaction
+            .slug_repository()
+            .get_slug(snake.eaten_by)
+            .await?;
+        transaction
+            .snake_repository()
+            .insert_snake(snake.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(snake)
+    }
+    #[instrument(skip_all, fields(frog = %frog_id, snake = %snake_id))]
+    async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog> {
+        let transaction = self.transaction_provider.begin().await?;
+        let snake = transaction.snake_repository().get_snake(snake_id).await?;
+        let frog_repository = transaction.frog_repository();
+        let mut frog = frog_repository.get_frog(frog_id).await?;
+        frog.eaten_by = snake.id;
+        frog_repository.update_frog(frog.clone()).await?;
+        transaction.commit().await?;
+        Ok(frog)
+    }
 }
 
-pub struct SlugUseCaseImpl<SlugR: SlugRepository, SnakeR: SnakeRepository> {
+pub struct SlugUseCaseImpl<
+    'rp,
+    TP: TransactionProvider,
+    SlugR: SlugRepository,
+    SnakeR: SnakeRepository,
+> {
+    transaction_provider: &'rp TP,
     slug_repository: SlugR,
     snake_repository: SnakeR,
 }
 
 #[async_trait]
-impl<SlugR: SlugRepository, SnakeR: SnakeRepository> SlugUseCase
-    for SlugUseCaseImpl<SlugR, SnakeR>
+impl<'rp, TP: TransactionProvider, SlugR: SlugRepository, SnakeR: SnakeRepository> SlugUseCase
+    for SlugUseCaseImpl<'rp, TP, SlugR, SnakeR>
 {
+    #[instrument(skip_all, fields(slug = %id))]
     async fn get_slug(&self, id: SlugID) -> Result<Slug> {
         self.slug_repository.get_slug(id).await
     }
+    #[instrument(skip_all, fields(snake = %snake_id))]
     async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug> {
         let snake = self.snake_repository.get_snake(snake_id).await?;
         self.slug_repository.get_slug(snake.eaten_by).await
     }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn register_slug(&self, slug: Slug) -> Result<Slug> {
+        let transaction = self.transaction_provider.begin().await?;
+        transaction
+            .frog_repository()
+            .get_frog(slug.eaten_by)
+            .await?;
+        transaction
+            .slug_repository()
+            .insert_slug(slug.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(slug)
+    }
+    #[instrument(skip_all, fields(snake = %snake_id, slug = %slug_id))]
+    async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake> {
+        let transaction = self.transaction_provider.begin().await?;
+        let slug = transaction.slug_repository().get_slug(slug_id).await?;
+        let snake_repository = transaction.snake_repository();
+        let mut snake = snake_repository.get_snake(snake_id).await?;
+        snake.eaten_by = slug.id;
+        snake_repository.update_snake(snake.clone()).await?;
+        transaction.commit().await?;
+        Ok(snake)
+    }
 }
 
-pub struct FrogUseCaseImpl<FrogR: FrogRepository, SlugR: SlugRepository> {
+pub struct FrogUseCaseImpl<
+    'rp,
+    TP: TransactionProvider,
+    FrogR: FrogRepository,
+    SlugR: SlugRepository,
+> {
+    transaction_provider: &'rp TP,
     frog_repository: FrogR,
     slug_repository: SlugR,
 }
 
 #[async_trait]
-impl<FrogR: FrogRepository, SlugR: SlugRepository> FrogUseCase for FrogUseCaseImpl<FrogR, SlugR> {
+impl<'rp, TP: TransactionProvider, FrogR: FrogRepository, SlugR: SlugRepository> FrogUseCase
+    for FrogUseCaseImpl<'rp, TP, FrogR, SlugR>
+{
+    #[instrument(skip_all, fields(frog = %id))]
     async fn get_frog(&self, id: FrogID) -> Result<Frog> {
         self.frog_repository.get_frog(id).await
     }
+    #[instrument(skip_all, fields(slug = %slug_id))]
     async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog> {
         let slug = self.slug_repository.get_slug(slug_id).await?;
         self.frog_repository.get_frog(slug.eaten_by).await
     }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn register_frog(&self, frog: Frog) -> Result<Frog> {
+        let transaction = self.transaction_provider.begin().await?;
+        transaction
+            .snake_repository()
+            .get_snake(frog.eaten_by)
+            .await?;
+        transaction
+            .frog_repository()
+            .insert_frog(frog.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(frog)
+    }
+    #[instrument(skip_all, fields(slug = %slug_id, frog = %frog_id))]
+    async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug> {
+        let transaction = self.transaction_provider.begin().await?;
+        let frog = transaction.frog_repository().get_frog(frog_id).await?;
+        let slug_repository = transaction.slug_repository();
+        let mut slug = slug_repository.get_slug(slug_id).await?;
+        slug.eaten_by = frog.id;
+        slug_repository.update_slug(slug.clone()).await?;
+        transaction.commit().await?;
+        Ok(slug)
+    }
+}
+
+pub struct FoodChainUseCaseImpl<'rp, TP: TransactionProvider> {
+    transaction_provider: &'rp TP,
+}
+
+#[async_trait]
+impl<'rp, TP: TransactionProvider> FoodChainUseCase for FoodChainUseCaseImpl<'rp, TP> {
+    #[instrument(skip_all, fields(snakes = snakes.len(), slugs = slugs.len(), frogs = frogs.len()))]
+    async fn register_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        let transaction = self.transaction_provider.begin().await?;
+        let snake_repository = transaction.snake_repository();
+        let slug_repository = transaction.slug_repository();
+        let frog_repository = transaction.frog_repository();
+        for snake in &snakes {
+            snake_repository.insert_snake(snake.clone()).await?;
+        }
+        for slug in &slugs {
+            slug_repository.insert_slug(slug.clone()).await?;
+        }
+        for frog in &frogs {
+            frog_repository.insert_frog(frog.clone()).await?;
+        }
+        // Every predator is now either one of the animals or stored before.
+        for snake in &snakes {
+            slug_repository.get_slug(snake.eaten_by).await?;
+        }
+        for slug in &slugs {
+            frog_repository.get_frog(slug.eaten_by).await?;
+        }
+        for frog in &frogs {
+            snake_repository.get_snake(frog.eaten_by).await?;
+        }
+        transaction.commit().await
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use domain::{MockFrogRepository, MockSlugRepository, MockSnakeRepository};
+    use domain::{
+        EntityID, Error, MockFrogRepository, MockSlugRepository, MockSnakeRepository,
+        MockTransaction, MockTransactionProvider,
+    };
+    use domain_fakes::{fixtures, FakeRepositoryProvider};
+    use mockall::predicate::eq;
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
+
+    fn transaction_provider(transaction: MockTransaction) -> MockTransactionProvider {
+        let mut transaction_provider = MockTransactionProvider::new();
+        transaction_provider
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        transaction_provider
+    }
 
     #[tokio::test]
     async fn test_get_snake() {
         let mut snake_repository = MockSnakeRepository::new();
         snake_repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let snake_use_case = SnakeUseCaseImpl {
+            transaction_provider: &MockTransactionProvider::new(),
             snake_repository,
             frog_repository: MockFrogRepository::new(),
         };
-        _ = snake_use_case.get_snake(SnakeID::default()).await;
+        assert_eq!(snake_use_case.get_snake(snake().id).await.unwrap(), snake());
     }
 
     #[tokio::test]
@@ -115,79 +301,597 @@ mod test {
         let mut snake_repository = MockSnakeRepository::new();
         snake_repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let mut frog_repository = MockFrogRepository::new();
-        frog_repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
         let snake_use_case = SnakeUseCaseImpl {
+            transaction_provider: &MockTransactionProvider::new(),
             snake_repository,
             frog_repository,
         };
-        _ = snake_use_case
-            .get_snake_eating_frog(FrogID::default())
-            .await;
+        assert_eq!(
+            snake_use_case
+                .get_snake_eating_frog(frog().id)
+                .await
+                .unwrap(),
+            snake()
+        );
     }
 
     #[tokio::test]
-    async fn test_get_slug() {
+    async fn test_register_snake() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_insert_snake()
+            .with(eq(snake()))
+            .returning(|_| Ok(()));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let snake_use_case = SnakeUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            snake_repository: MockSnakeRepository::new(),
+            frog_repository: MockFrogRepository::new(),
+        };
+        assert_eq!(
+            snake_use_case.register_snake(snake()).await.unwrap(),
+            snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_unknown_slug() {
         let mut slug_repository = MockSlugRepository::new();
         slug_repository
             .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction.expect_snake_repository().never();
+        transaction.expect_commit().never();
+        let snake_use_case = SnakeUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            snake_repository: MockSnakeRepository::new(),
+            frog_repository: MockFrogRepository::new(),
+        };
+        let err = snake_use_case.register_snake(snake()).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == slug().id));
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|_| Ok(snake()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        frog_repository
+            .expect_update_frog()
+            .withf(|frog| frog.eaten_by == snake().id)
+            .returning(|_| Ok(()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let snake_use_case = SnakeUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            snake_repository: MockSnakeRepository::new(),
+            frog_repository: MockFrogRepository::new(),
+        };
+        let frog = snake_use_case
+            .set_snake_eating_frog(frog().id, snake().id)
+            .await
+            .unwrap();
+        assert_eq!(frog.eaten_by, snake().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_unknown_snake() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_update_frog().never();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction.expect_commit().never();
+        let snake_use_case = SnakeUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            snake_repository: MockSnakeRepository::new(),
+            frog_repository: MockFrogRepository::new(),
+        };
+        let err = snake_use_case
+            .set_snake_eating_frog(frog().id, snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(id)) if id == snake().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_slug() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
         let slug_use_case = SlugUseCaseImpl {
+            transaction_provider: &MockTransactionProvider::new(),
             slug_repository,
             snake_repository: MockSnakeRepository::new(),
         };
-        _ = slug_use_case.get_slug(SlugID::default()).await;
+        assert_eq!(slug_use_case.get_slug(slug().id).await.unwrap(), slug());
     }
 
     #[tokio::test]
     async fn test_get_slug_eating_snake() {
         let mut slug_repository = MockSlugRepository::new();
-        slug_repository
-            .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
         let mut snake_repository = MockSnakeRepository::new();
         snake_repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let slug_use_case = SlugUseCaseImpl {
+            transaction_provider: &MockTransactionProvider::new(),
             slug_repository,
             snake_repository,
         };
-        _ = slug_use_case
-            .get_slug_eating_snake(SnakeID::default())
-            .await;
+        assert_eq!(
+            slug_use_case
+                .get_slug_eating_snake(snake().id)
+                .await
+                .unwrap(),
+            slug()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_slug() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository
+            .expect_insert_slug()
+            .with(eq(slug()))
+            .returning(|_| Ok(()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let slug_use_case = SlugUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            slug_repository: MockSlugRepository::new(),
+            snake_repository: MockSnakeRepository::new(),
+        };
+        assert_eq!(slug_use_case.register_slug(slug()).await.unwrap(), slug());
+    }
+
+    #[tokio::test]
+    async fn test_set_slug_eating_snake() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|_| Ok(snake()));
+        snake_repository
+            .expect_update_snake()
+            .withf(|snake| snake.eaten_by == slug().id)
+            .returning(|_| Ok(()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let slug_use_case = SlugUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            slug_repository: MockSlugRepository::new(),
+            snake_repository: MockSnakeRepository::new(),
+        };
+        let snake = slug_use_case
+            .set_slug_eating_snake(snake().id, slug().id)
+            .await
+            .unwrap();
+        assert_eq!(snake.eaten_by, slug().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_slug_eating_snake_unknown_slug() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository
+            .expect_get_slug()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository.expect_update_snake().never();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction.expect_commit().never();
+        let slug_use_case = SlugUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            slug_repository: MockSlugRepository::new(),
+            snake_repository: MockSnakeRepository::new(),
+        };
+        let err = slug_use_case
+            .set_slug_eating_snake(snake().id, slug().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == slug().id));
     }
 
     #[tokio::test]
     async fn test_get_frog() {
         let mut frog_repository = MockFrogRepository::new();
-        frog_repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
         let slug_use_case = FrogUseCaseImpl {
+            transaction_provider: &MockTransactionProvider::new(),
             frog_repository,
             slug_repository: MockSlugRepository::new(),
         };
-        _ = slug_use_case.get_frog(FrogID::default()).await;
+        assert_eq!(slug_use_case.get_frog(frog().id).await.unwrap(), frog());
     }
 
     #[tokio::test]
     async fn test_get_frog_eating_slug() {
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let slug_use_case = FrogUseCaseImpl {
+            transaction_provider: &MockTransactionProvider::new(),
+            frog_repository,
+            slug_repository,
+        };
+        assert_eq!(
+            slug_use_case.get_frog_eating_slug(slug().id).await.unwrap(),
+            frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_frog() {
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository
+            .expect_insert_frog()
+            .with(eq(frog()))
+            .returning(|_| Ok(()));
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|_| Ok(snake()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let frog_use_case = FrogUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            frog_repository: MockFrogRepository::new(),
+            slug_repository: MockSlugRepository::new(),
+        };
+        assert_eq!(frog_use_case.register_frog(frog()).await.unwrap(), frog());
+    }
+
+    #[tokio::test]
+    async fn test_set_frog_eating_slug() {
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        slug_repository
+            .expect_update_slug()
+            .withf(|slug| slug.eaten_by == frog().id)
+            .returning(|_| Ok(()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let frog_use_case = FrogUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            frog_repository: MockFrogRepository::new(),
+            slug_repository: MockSlugRepository::new(),
+        };
+        let slug = frog_use_case
+            .set_frog_eating_slug(slug().id, frog().id)
+            .await
+            .unwrap();
+        assert_eq!(slug.eaten_by, frog().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_frog_eating_slug_unknown_frog() {
         let mut frog_repository = MockFrogRepository::new();
         frog_repository
             .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_update_slug().never();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction.expect_commit().never();
+        let frog_use_case = FrogUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
+            frog_repository: MockFrogRepository::new(),
+            slug_repository: MockSlugRepository::new(),
+        };
+        let err = frog_use_case
+            .set_frog_eating_slug(slug().id, frog().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Frog(id)) if id == frog().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_eating_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCaseProviderImpl::new(&repository);
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        assert_eq!(
+            use_case
+                .slug_use_case()
+                .get_slug_eating_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::slug()
+        );
+        assert_eq!(
+            use_case
+                .frog_use_case()
+                .get_frog_eating_slug(fixtures::slug().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCaseProviderImpl::new(&repository);
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..snake()
+        };
+        use_case
+            .snake_use_case()
+            .register_snake(snake.clone())
+            .await
+            .unwrap();
+        use_case
+            .snake_use_case()
+            .set_snake_eating_frog(fixtures::frog().id, snake.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            snake
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_unknown_snake_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCaseProviderImpl::new(&repository);
+        let err = use_case
+            .snake_use_case()
+            .set_snake_eating_frog(fixtures::frog().id, SnakeID::new(4).unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+        assert_eq!(
+            use_case
+                .frog_use_case()
+                .get_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_taken_stored() {
+        let repository = FakeRepositoryProvider::seeded()
+            .with_slug(Slug {
+                id: SlugID::new(4).unwrap(),
+                eaten_by: FrogID::new(4).unwrap(),
+            })
+            .with_frog(Frog {
+                id: FrogID::new(4).unwrap(),
+                eaten_by: SnakeID::new(4).unwrap(),
+            });
+        let use_case = UseCaseProviderImpl::new(&repository);
+        let err = use_case
+            .snake_use_case()
+            .register_snake(Snake {
+                eaten_by: SlugID::new(4).unwrap(),
+                ..snake()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Conflict(EntityID::Snake(id)) if id == fixtures::snake().id));
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_unknown_slug_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCaseProviderImpl::new(&repository);
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            eaten_by: SlugID::new(4).unwrap(),
+        };
+        let err = use_case
+            .snake_use_case()
+            .register_snake(snake.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == snake.eaten_by));
+        let err = use_case
+            .snake_use_case()
+            .get_snake(snake.id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_insert_snake()
+            .with(eq(snake()))
+            .return_once(|_| Ok(()));
+        snake_repository
+            .expect_get_snake()
+            .with(eq(snake().id))
+            .return_once(|_| Ok(snake()));
         let mut slug_repository = MockSlugRepository::new();
+        slug_repository
+            .expect_insert_slug()
+            .with(eq(slug()))
+            .return_once(|_| Ok(()));
         slug_repository
             .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
-        let slug_use_case = FrogUseCaseImpl {
-            frog_repository,
-            slug_repository,
+            .with(eq(slug().id))
+            .return_once(|_| Ok(slug()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository
+            .expect_insert_frog()
+            .with(eq(frog()))
+            .return_once(|_| Ok(()));
+        frog_repository
+            .expect_get_frog()
+            .with(eq(frog().id))
+            .return_once(|_| Ok(frog()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_once(move || snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_once(move || slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_once(move || frog_repository);
+        transaction.expect_commit().return_once(|| Ok(()));
+        let food_chain_use_case = FoodChainUseCaseImpl {
+            transaction_provider: &transaction_provider(transaction),
         };
-        _ = slug_use_case.get_frog_eating_slug(SlugID::default()).await;
+        food_chain_use_case
+            .register_food_chain(vec![snake()], vec![slug()], vec![frog()])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_stored() {
+        let repository = FakeRepositoryProvider::new();
+        let use_case = UseCaseProviderImpl::new(&repository);
+        use_case
+            .food_chain_use_case()
+            .register_food_chain(
+                vec![fixtures::snake()],
+                vec![fixtures::slug()],
+                vec![fixtures::frog()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        // Later animals may be eaten by ones already registered.
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..fixtures::snake()
+        };
+        use_case
+            .food_chain_use_case()
+            .register_food_chain(vec![snake.clone()], vec![], vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case.snake_use_case().get_snake(snake.id).await.unwrap(),
+            snake
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_unknown_predator_stored() {
+        let repository = FakeRepositoryProvider::new();
+        let use_case = UseCaseProviderImpl::new(&repository);
+        let err = use_case
+            .food_chain_use_case()
+            .register_food_chain(vec![fixtures::snake()], vec![fixtures::slug()], vec![])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Frog(id)) if id == fixtures::frog().id));
+        let err = use_case
+            .snake_use_case()
+            .get_snake(fixtures::snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
     }
 }
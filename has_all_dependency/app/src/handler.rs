@@ -1,7 +1,8 @@
 use domain::{
-    Frog, FrogID, FrogService, ServiceProvider, Slug, SlugID, SlugService, Snake, SnakeID,
-    SnakeService,
+    Error, Frog, FrogID, FrogService, Result, ServiceProvider, Slug, SlugID, SlugService, Snake,
+    SnakeID, SnakeService,
 };
+use tracing::instrument;
 
 pub(crate) struct Handler<'sp, SP: ServiceProvider> {
     snake_service: &'sp SP::SnakeService,
@@ -18,51 +19,113 @@ impl<'sp, SP: ServiceProvider> Handler<'sp, SP> {
         }
     }
 
-    pub(crate) async fn run(&self) -> (Snake, Slug, Frog) {
+    #[instrument(skip_all, fields(slug = %slug_id, frog = %frog_id, snake = %snake_id))]
+    pub(crate) async fn run(
+        &self,
+        slug_id: SlugID,
+        frog_id: FrogID,
+        snake_id: SnakeID,
+    ) -> Result<(Snake, Slug, Frog)> {
         let snake = self
             .snake_service
-            .get_snake_eating_frog_eating_slug(SlugID::default())
-            .await
-            .unwrap();
+            .get_snake_eating_frog_eating_slug(slug_id)
+            .await?;
         let slug = self
             .slug_service
-            .get_slug_eating_snake_eating_frog(FrogID::default())
-            .await
-            .unwrap();
+            .get_slug_eating_snake_eating_frog(frog_id)
+            .await?;
         let frog = self
             .frog_service
-            .get_frog_eating_slug_eating_snake(SnakeID::default())
-            .await
-            .unwrap();
-        (snake, slug, frog)
+            .get_frog_eating_slug_eating_snake(snake_id)
+            .await?;
+        Ok((snake, slug, frog))
+    }
+}
+
+pub(crate) fn status(err: &Error) -> u16 {
+    match err.root() {
+        Error::Invalid(_) => 400,
+        Error::NotFound(_) => 404,
+        Error::Conflict(_) => 409,
+        Error::Internal(_) | Error::Context { .. } => 500,
+        Error::Unavailable(_) => 503,
     }
 }
 
 #[cfg(test)]
 mod test {
-    use domain::{MockFrogService, MockServiceProvider, MockSlugService, MockSnakeService};
+    use domain::{
+        EntityID, MockFrogService, MockServiceProvider, MockSlugService, MockSnakeService,
+    };
 
     use super::*;
 
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
+
     #[tokio::test]
     async fn test_run() {
         let mut snake_service = MockSnakeService::new();
         snake_service
             .expect_get_snake_eating_frog_eating_slug()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let mut slug_service = MockSlugService::new();
         slug_service
             .expect_get_slug_eating_snake_eating_frog()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|_| Ok(slug()));
         let mut frog_service = MockFrogService::new();
         frog_service
             .expect_get_frog_eating_slug_eating_snake()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|_| Ok(frog()));
         let mut service = MockServiceProvider::new();
         service.expect_snake_service().return_const(snake_service);
         service.expect_slug_service().return_const(slug_service);
         service.expect_frog_service().return_const(frog_service);
         let handler = Handler::new(&service);
-        _ = handler.run().await;
+        assert_eq!(
+            handler.run(slug().id, frog().id, snake().id).await.unwrap(),
+            (snake(), slug(), frog())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_not_found() {
+        let mut snake_service = MockSnakeService::new();
+        snake_service
+            .expect_get_snake_eating_frog_eating_slug()
+            .returning(|slug_id| Err(Error::NotFound(slug_id.into())));
+        let mut service = MockServiceProvider::new();
+        service.expect_snake_service().return_const(snake_service);
+        service
+            .expect_slug_service()
+            .return_const(MockSlugService::new());
+        service
+            .expect_frog_service()
+            .return_const(MockFrogService::new());
+        let handler = Handler::new(&service);
+        let err = handler
+            .run(slug().id, frog().id, snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == slug().id));
+        assert_eq!(status(&err), 404);
     }
 }
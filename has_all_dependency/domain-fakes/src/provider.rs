@@ -0,0 +1,280 @@
+use async_trait::async_trait;
+use domain::{Frog, RepositoryProvider, Result, Slug, Snake, Transaction, TransactionProvider};
+
+use crate::{
+    store::{State, Store},
+    FakeFrogRepository, FakeSlugRepository, FakeSnakeRepository,
+};
+
+/// An in-memory [`TransactionProvider`] whose repositories share their
+/// animals, so what one use case writes the next reads.
+#[derive(Debug, Clone)]
+pub struct FakeRepositoryProvider {
+    store: Store,
+    snake_repository: FakeSnakeRepository,
+    slug_repository: FakeSlugRepository,
+    frog_repository: FakeFrogRepository,
+}
+
+impl FakeRepositoryProvider {
+    pub fn new() -> Self {
+        Self::from_store(Store::default())
+    }
+
+    /// A provider holding the [`fixtures`](crate::fixtures).
+    pub fn seeded() -> Self {
+        Self::from_store(Store::new(State::seeded()))
+    }
+
+    fn from_store(store: Store) -> Self {
+        Self {
+            snake_repository: FakeSnakeRepository {
+                store: store.clone(),
+            },
+            slug_repository: FakeSlugRepository {
+                store: store.clone(),
+            },
+            frog_repository: FakeFrogRepository {
+                store: store.clone(),
+            },
+            store,
+        }
+    }
+
+    pub fn with_snake(self, snake: Snake) -> Self {
+        self.store.lock().put(snake);
+        self
+    }
+
+    pub fn with_slug(self, slug: Slug) -> Self {
+        self.store.lock().put(slug);
+        self
+    }
+
+    pub fn with_frog(self, frog: Frog) -> Self {
+        self.store.lock().put(frog);
+        self
+    }
+}
+
+impl Default for FakeRepositoryProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RepositoryProvider for FakeRepositoryProvider {
+    type SnakeRepository = FakeSnakeRepository;
+    type SlugRepository = FakeSlugRepository;
+    type FrogRepository = FakeFrogRepository;
+
+    fn snake_repository(&self) -> &Self::SnakeRepository {
+        &self.snake_repository
+    }
+    fn slug_repository(&self) -> &Self::SlugRepository {
+        &self.slug_repository
+    }
+    fn frog_repository(&self) -> &Self::FrogRepository {
+        &self.frog_repository
+    }
+}
+
+#[async_trait]
+impl TransactionProvider for FakeRepositoryProvider {
+    type Transaction = FakeTransaction;
+
+    async fn begin(&self) -> Result<Self::Transaction> {
+        let snapshot = self.store.lock().clone();
+        Ok(FakeTransaction {
+            provider: self.store.clone(),
+            staged: Self::from_store(Store::staged(snapshot.clone())),
+            snapshot,
+        })
+    }
+}
+
+/// Writes to a copy of the provider's animals taken when it began, and on
+/// commit applies to the provider only the animals it changed. Foreign keys
+/// are checked on commit, so animals may be written in any order.
+#[derive(Debug)]
+pub struct FakeTransaction {
+    provider: Store,
+    snapshot: State,
+    staged: FakeRepositoryProvider,
+}
+
+impl RepositoryProvider for FakeTransaction {
+    type SnakeRepository = FakeSnakeRepository;
+    type SlugRepository = FakeSlugRepository;
+    type FrogRepository = FakeFrogRepository;
+
+    fn snake_repository(&self) -> &Self::SnakeRepository {
+        self.staged.snake_repository()
+    }
+    fn slug_repository(&self) -> &Self::SlugRepository {
+        self.staged.slug_repository()
+    }
+    fn frog_repository(&self) -> &Self::FrogRepository {
+        self.staged.frog_repository()
+    }
+}
+
+#[async_trait]
+impl Transaction for FakeTransaction {
+    async fn commit(self) -> Result<()> {
+        let staged = self.staged.store.lock();
+        let mut provider = self.provider.lock();
+        let mut next = provider.clone();
+        next.apply(&self.snapshot, &staged);
+        next.check()?;
+        *provider = next;
+        Ok(())
+    }
+    async fn rollback(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use domain::{
+        EntityID, Error, FrogID, FrogRepository, SlugID, SlugRepository, SnakeID, SnakeRepository,
+    };
+
+    use super::*;
+    use crate::fixtures;
+
+    #[tokio::test]
+    async fn test_repositories_share_animals() {
+        let provider = FakeRepositoryProvider::seeded();
+        // The snake is checked against the slug stored through another
+        // repository of the provider.
+        let slug = Slug {
+            id: SlugID::new(5).unwrap(),
+            ..fixtures::slug()
+        };
+        provider
+            .slug_repository()
+            .insert_slug(slug.clone())
+            .await
+            .unwrap();
+        let other = Snake {
+            id: SnakeID::new(4).unwrap(),
+            eaten_by: slug.id,
+        };
+        provider
+            .snake_repository()
+            .insert_snake(other.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            provider
+                .snake_repository()
+                .get_snake(other.id)
+                .await
+                .unwrap(),
+            other
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit() {
+        let provider = FakeRepositoryProvider::seeded();
+        let transaction = provider.begin().await.unwrap();
+        // The slug is deleted before its prey moves away, which only the
+        // check on commit allows.
+        transaction
+            .slug_repository()
+            .delete_slug(fixtures::slug().id)
+            .await
+            .unwrap();
+        let slug = Slug {
+            id: SlugID::new(5).unwrap(),
+            ..fixtures::slug()
+        };
+        transaction
+            .slug_repository()
+            .insert_slug(slug.clone())
+            .await
+            .unwrap();
+        let moved = Snake {
+            eaten_by: slug.id,
+            ..fixtures::snake()
+        };
+        transaction
+            .snake_repository()
+            .update_snake(moved.clone())
+            .await
+            .unwrap();
+        // Unseen outside the transaction until it commits.
+        let snakes = provider.snake_repository();
+        assert_eq!(snakes.get_snake(moved.id).await.unwrap(), fixtures::snake());
+        transaction.commit().await.unwrap();
+        assert_eq!(snakes.get_snake(moved.id).await.unwrap(), moved);
+        let err = provider
+            .slug_repository()
+            .get_slug(fixtures::slug().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        let provider = FakeRepositoryProvider::seeded();
+        let transaction = provider.begin().await.unwrap();
+        transaction
+            .snake_repository()
+            .delete_snake(fixtures::snake().id)
+            .await
+            .unwrap();
+        transaction.rollback().await.unwrap();
+        let dropped = provider.begin().await.unwrap();
+        dropped
+            .snake_repository()
+            .delete_snake(fixtures::snake().id)
+            .await
+            .unwrap();
+        drop(dropped);
+        assert_eq!(
+            provider
+                .snake_repository()
+                .get_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_keeps_concurrent_writes() {
+        let provider = FakeRepositoryProvider::seeded();
+        let transaction = provider.begin().await.unwrap();
+        transaction
+            .frog_repository()
+            .insert_frog(Frog {
+                id: FrogID::new(5).unwrap(),
+                ..fixtures::frog()
+            })
+            .await
+            .unwrap();
+        let other = Snake {
+            id: SnakeID::new(4).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        };
+        provider
+            .snake_repository()
+            .insert_snake(other.clone())
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+        assert_eq!(
+            provider
+                .snake_repository()
+                .get_snake(other.id)
+                .await
+                .unwrap(),
+            other
+        );
+    }
+}
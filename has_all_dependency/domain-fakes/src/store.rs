@@ -0,0 +1,246 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use domain::{EntityID, Error, Frog, FrogID, Result, Slug, SlugID, Snake, SnakeID};
+
+use crate::fixtures;
+
+/// The animals the fakes made from the same store share.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct State {
+    snakes: BTreeMap<SnakeID, Snake>,
+    slugs: BTreeMap<SlugID, Slug>,
+    frogs: BTreeMap<FrogID, Frog>,
+}
+
+impl State {
+    pub(crate) fn seeded() -> Self {
+        let mut state = Self::default();
+        state.put(fixtures::snake());
+        state.put(fixtures::slug());
+        state.put(fixtures::frog());
+        state
+    }
+
+    /// A snake, a slug and a frog with each of the IDs, each eaten by the
+    /// next in the food chain with the same ID.
+    #[cfg(test)]
+    pub(crate) fn food_chains(ids: impl IntoIterator<Item = u64>) -> Self {
+        let mut state = Self::default();
+        for id in ids {
+            state.put(Snake {
+                id: SnakeID::new(id).unwrap(),
+                eaten_by: SlugID::new(id).unwrap(),
+            });
+            state.put(Slug {
+                id: SlugID::new(id).unwrap(),
+                eaten_by: FrogID::new(id).unwrap(),
+            });
+            state.put(Frog {
+                id: FrogID::new(id).unwrap(),
+                eaten_by: SnakeID::new(id).unwrap(),
+            });
+        }
+        state
+    }
+
+    pub(crate) fn put<A: Animal>(&mut self, animal: A) {
+        A::table(self).insert(animal.id(), animal);
+    }
+
+    /// Fails with [`Error::Invalid`] if an animal is eaten by one that does
+    /// not exist.
+    pub(crate) fn check(&self) -> Result<()> {
+        let valid = self
+            .snakes
+            .values()
+            .all(|snake| snake.predator_exists(self))
+            && self.slugs.values().all(|slug| slug.predator_exists(self))
+            && self.frogs.values().all(|frog| frog.predator_exists(self));
+        if !valid {
+            return Err(foreign_key_violated());
+        }
+        Ok(())
+    }
+
+    /// Applies to `self` the changes that turned `before` into `after`, so
+    /// writes made to `self` meanwhile to other animals are kept.
+    pub(crate) fn apply(&mut self, before: &Self, after: &Self) {
+        apply(&mut self.snakes, &before.snakes, &after.snakes);
+        apply(&mut self.slugs, &before.slugs, &after.slugs);
+        apply(&mut self.frogs, &before.frogs, &after.frogs);
+    }
+}
+
+fn apply<K: Ord + Copy, V: Clone + PartialEq>(
+    table: &mut BTreeMap<K, V>,
+    before: &BTreeMap<K, V>,
+    after: &BTreeMap<K, V>,
+) {
+    for id in before.keys().filter(|id| !after.contains_key(id)) {
+        table.remove(id);
+    }
+    for (id, animal) in after {
+        if before.get(id) != Some(animal) {
+            table.insert(*id, animal.clone());
+        }
+    }
+}
+
+/// The error the real repositories fail with on a broken foreign key.
+fn foreign_key_violated() -> Error {
+    Error::Invalid("foreign key violated: a row refers to one that does not exist".to_owned())
+}
+
+/// Maps an animal onto its table in the [`State`].
+pub(crate) trait Animal: Clone + Sized {
+    type ID: Copy + Ord + Into<EntityID>;
+
+    fn id(&self) -> Self::ID;
+    fn table(state: &mut State) -> &mut BTreeMap<Self::ID, Self>;
+    /// Whether the animal this one is eaten by exists.
+    fn predator_exists(&self, state: &State) -> bool;
+    /// Whether an animal eaten by the one with the ID exists.
+    fn prey_exists(state: &State, id: Self::ID) -> bool;
+}
+
+impl Animal for Snake {
+    type ID = SnakeID;
+
+    fn id(&self) -> Self::ID {
+        self.id
+    }
+    fn table(state: &mut State) -> &mut BTreeMap<Self::ID, Self> {
+        &mut state.snakes
+    }
+    fn predator_exists(&self, state: &State) -> bool {
+        state.slugs.contains_key(&self.eaten_by)
+    }
+    fn prey_exists(state: &State, id: Self::ID) -> bool {
+        state.frogs.values().any(|frog| frog.eaten_by == id)
+    }
+}
+
+impl Animal for Slug {
+    type ID = SlugID;
+
+    fn id(&self) -> Self::ID {
+        self.id
+    }
+    fn table(state: &mut State) -> &mut BTreeMap<Self::ID, Self> {
+        &mut state.slugs
+    }
+    fn predator_exists(&self, state: &State) -> bool {
+        state.frogs.contains_key(&self.eaten_by)
+    }
+    fn prey_exists(state: &State, id: Self::ID) -> bool {
+        state.snakes.values().any(|snake| snake.eaten_by == id)
+    }
+}
+
+impl Animal for Frog {
+    type ID = FrogID;
+
+    fn id(&self) -> Self::ID {
+        self.id
+    }
+    fn table(state: &mut State) -> &mut BTreeMap<Self::ID, Self> {
+        &mut state.frogs
+    }
+    fn predator_exists(&self, state: &State) -> bool {
+        state.snakes.contains_key(&self.eaten_by)
+    }
+    fn prey_exists(state: &State, id: Self::ID) -> bool {
+        state.slugs.values().any(|slug| slug.eaten_by == id)
+    }
+}
+
+/// A [`State`] shared by handle, with the repository semantics of the
+/// domain's traits on top, foreign keys included.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Store {
+    state: Arc<Mutex<State>>,
+    /// Set for a transaction's copy, whose foreign keys are checked on
+    /// commit instead, as the real repositories' are.
+    deferred: bool,
+}
+
+impl Store {
+    pub(crate) fn new(state: State) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            deferred: false,
+        }
+    }
+
+    pub(crate) fn staged(state: State) -> Self {
+        Self {
+            deferred: true,
+            ..Self::new(state)
+        }
+    }
+
+    pub(crate) fn get<A: Animal>(&self, id: A::ID) -> Result<A> {
+        A::table(&mut self.lock())
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(id.into()))
+    }
+
+    pub(crate) fn insert<A: Animal>(&self, animal: A) -> Result<()> {
+        let mut state = self.lock();
+        let table = A::table(&mut state);
+        if table.contains_key(&animal.id()) {
+            return Err(Error::Conflict(animal.id().into()));
+        }
+        self.check_predator(&state, &animal)?;
+        state.put(animal);
+        Ok(())
+    }
+
+    pub(crate) fn update<A: Animal>(&self, animal: A) -> Result<()> {
+        let mut state = self.lock();
+        if !A::table(&mut state).contains_key(&animal.id()) {
+            return Err(Error::NotFound(animal.id().into()));
+        }
+        self.check_predator(&state, &animal)?;
+        state.put(animal);
+        Ok(())
+    }
+
+    pub(crate) fn upsert<A: Animal>(&self, animal: A) -> Result<()> {
+        let mut state = self.lock();
+        self.check_predator(&state, &animal)?;
+        state.put(animal);
+        Ok(())
+    }
+
+    pub(crate) fn delete<A: Animal>(&self, id: A::ID) -> Result<()> {
+        let mut state = self.lock();
+        if !A::table(&mut state).contains_key(&id) {
+            return Err(Error::NotFound(id.into()));
+        }
+        if !self.deferred && A::prey_exists(&state, id) {
+            return Err(foreign_key_violated());
+        }
+        A::table(&mut state).remove(&id);
+        Ok(())
+    }
+
+    /// A panic while holding the lock cannot leave an animal half written, so
+    /// poisoning is ignored.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn check_predator<A: Animal>(&self, state: &State, animal: &A) -> Result<()> {
+        if !self.deferred && !animal.predator_exists(state) {
+            return Err(foreign_key_violated());
+        }
+        Ok(())
+    }
+}
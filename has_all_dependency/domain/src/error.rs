@@ -0,0 +1,157 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::{FrogID, IdError, SlugID, SnakeID};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityID {
+    Snake(SnakeID),
+    Slug(SlugID),
+    Frog(FrogID),
+}
+
+impl fmt::Display for EntityID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Snake(id) => write!(f, "snake {id}"),
+            Self::Slug(id) => write!(f, "slug {id}"),
+            Self::Frog(id) => write!(f, "frog {id}"),
+        }
+    }
+}
+
+impl From<SnakeID> for EntityID {
+    fn from(id: SnakeID) -> Self {
+        Self::Snake(id)
+    }
+}
+
+impl From<SlugID> for EntityID {
+    fn from(id: SlugID) -> Self {
+        Self::Slug(id)
+    }
+}
+
+impl From<FrogID> for EntityID {
+    fn from(id: FrogID) -> Self {
+        Self::Frog(id)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} not found")]
+    NotFound(EntityID),
+    #[error("{0} conflicts with the stored state")]
+    Conflict(EntityID),
+    #[error("backend unavailable")]
+    Unavailable(#[source] BoxError),
+    #[error("invalid input: {0}")]
+    Invalid(String),
+    #[error("internal error")]
+    Internal(#[source] BoxError),
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    pub fn unavailable(source: impl Into<BoxError>) -> Self {
+        Self::Unavailable(source.into())
+    }
+
+    pub fn internal(source: impl Into<BoxError>) -> Self {
+        Self::Internal(source.into())
+    }
+
+    pub fn context(self, context: impl Into<String>) -> Self {
+        Self::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the innermost error underneath any added context.
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// Whether the operation may succeed if retried later.
+    pub fn is_transient(&self) -> bool {
+        matches!(self.root(), Self::Unavailable(_))
+    }
+}
+
+pub trait ResultExt<T> {
+    fn context(self, context: impl Into<String>) -> Result<T>;
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|err| err.context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|err| err.context(f()))
+    }
+}
+
+impl From<IdError> for Error {
+    fn from(err: IdError) -> Self {
+        Self::Invalid(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error as _, io};
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let id = SnakeID::new(1).unwrap();
+        assert_eq!(Error::NotFound(id.into()).to_string(), "snake 1 not found");
+        assert_eq!(
+            Error::from("0".parse::<FrogID>().unwrap_err()).to_string(),
+            "invalid input: id must not be zero"
+        );
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let err = Error::unavailable(io::Error::new(io::ErrorKind::Other, "refused"));
+        assert_eq!(err.to_string(), "backend unavailable");
+        assert_eq!(err.source().unwrap().to_string(), "refused");
+    }
+
+    #[test]
+    fn test_context() {
+        let id = FrogID::new(3).unwrap();
+        let err = Err::<(), _>(Error::NotFound(id.into()))
+            .context("getting frog 3")
+            .with_context(|| "getting snake eating frog 3")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting snake eating frog 3");
+        assert_eq!(err.source().unwrap().to_string(), "getting frog 3");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Frog(root)) if *root == id));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let err = Error::unavailable("pool exhausted").context("getting snake 1");
+        assert!(err.is_transient());
+        assert!(!Error::NotFound(SnakeID::new(1).unwrap().into()).is_transient());
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use thiserror::Error;
+
+use crate::{EntityID, FrogID, SlugID, SnakeID};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid event {0:?}")]
+pub struct EventError(String);
+
+/// A change to the food chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    SnakeRegistered {
+        snake: SnakeID,
+        eaten_by: SlugID,
+    },
+    SlugRegistered {
+        slug: SlugID,
+        eaten_by: FrogID,
+    },
+    FrogRegistered {
+        frog: FrogID,
+        eaten_by: SnakeID,
+    },
+    /// The predator of `prey` changed from `from` to `to`.
+    EatenByChanged {
+        prey: EntityID,
+        from: EntityID,
+        to: EntityID,
+    },
+    SnakeEaten {
+        snake: SnakeID,
+    },
+    SlugEaten {
+        slug: SlugID,
+    },
+    FrogEaten {
+        frog: FrogID,
+    },
+}
+
+/// A change to the food chain and when it happened, as published to the
+/// topic of the animal it is about.
+///
+/// Events travel as one line of text, e.g.
+/// `SnakeRegistered snake=1 eaten_by=2 at=1700000000.000000000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub occurred_at: SystemTime,
+}
+
+impl Event {
+    pub fn now(kind: EventKind) -> Self {
+        Self {
+            kind,
+            occurred_at: SystemTime::now(),
+        }
+    }
+
+    /// The animal the event is about.
+    pub fn subject(&self) -> EntityID {
+        match self.kind {
+            EventKind::SnakeRegistered { snake, .. } | EventKind::SnakeEaten { snake } => {
+                snake.into()
+            }
+            EventKind::SlugRegistered { slug, .. } | EventKind::SlugEaten { slug } => slug.into(),
+            EventKind::FrogRegistered { frog, .. } | EventKind::FrogEaten { frog } => frog.into(),
+            EventKind::EatenByChanged { prey, .. } => prey,
+        }
+    }
+
+    /// `snake`, `slug` or `frog`, after the subject.
+    pub fn topic(&self) -> &'static str {
+        match self.subject() {
+            EntityID::Snake(_) => "snake",
+            EntityID::Slug(_) => "slug",
+            EntityID::Frog(_) => "frog",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            EventKind::SnakeRegistered { snake, eaten_by } => {
+                write!(f, "SnakeRegistered snake={snake} eaten_by={eaten_by}")?
+            }
+            EventKind::SlugRegistered { slug, eaten_by } => {
+                write!(f, "SlugRegistered slug={slug} eaten_by={eaten_by}")?
+            }
+            EventKind::FrogRegistered { frog, eaten_by } => {
+                write!(f, "FrogRegistered frog={frog} eaten_by={eaten_by}")?
+            }
+            EventKind::EatenByChanged { prey, from, to } => write!(
+                f,
+                "EatenByChanged prey={} from={} to={}",
+                Tagged(prey),
+                Tagged(from),
+                Tagged(to)
+            )?,
+            EventKind::SnakeEaten { snake } => write!(f, "SnakeEaten snake={snake}")?,
+            EventKind::SlugEaten { slug } => write!(f, "SlugEaten slug={slug}")?,
+            EventKind::FrogEaten { frog } => write!(f, "FrogEaten frog={frog}")?,
+        }
+        // Times before the epoch do not occur; they are written as the epoch.
+        let at = self
+            .occurred_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(f, " at={}.{:09}", at.as_secs(), at.subsec_nanos())
+    }
+}
+
+impl FromStr for Event {
+    type Err = EventError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s).ok_or_else(|| EventError(s.to_owned()))
+    }
+}
+
+fn parse(s: &str) -> Option<Event> {
+    let mut words = s.split_whitespace();
+    let name = words.next()?;
+    let fields = words
+        .map(|word| word.split_once('='))
+        .collect::<Option<Vec<_>>>()?;
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| *value)
+    };
+    fn id<T: FromStr>(value: Option<&str>) -> Option<T> {
+        value?.parse().ok()
+    }
+    let kind = match name {
+        "SnakeRegistered" => EventKind::SnakeRegistered {
+            snake: id(field("snake"))?,
+            eaten_by: id(field("eaten_by"))?,
+        },
+        "SlugRegistered" => EventKind::SlugRegistered {
+            slug: id(field("slug"))?,
+            eaten_by: id(field("eaten_by"))?,
+        },
+        "FrogRegistered" => EventKind::FrogRegistered {
+            frog: id(field("frog"))?,
+            eaten_by: id(field("eaten_by"))?,
+        },
+        "EatenByChanged" => EventKind::EatenByChanged {
+            prey: tagged(field("prey")?)?,
+            from: tagged(field("from")?)?,
+            to: tagged(field("to")?)?,
+        },
+        "SnakeEaten" => EventKind::SnakeEaten {
+            snake: id(field("snake"))?,
+        },
+        "SlugEaten" => EventKind::SlugEaten {
+            slug: id(field("slug"))?,
+        },
+        "FrogEaten" => EventKind::FrogEaten {
+            frog: id(field("frog"))?,
+        },
+        _ => return None,
+    };
+    let (secs, nanos) = field("at")?.split_once('.')?;
+    if nanos.len() != 9 {
+        return None;
+    }
+    let at = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+    Some(Event {
+        kind,
+        occurred_at: UNIX_EPOCH + at,
+    })
+}
+
+/// An entity ID written as `<kind>:<id>`, e.g. `snake:1`.
+struct Tagged(EntityID);
+
+impl fmt::Display for Tagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            EntityID::Snake(id) => write!(f, "snake:{id}"),
+            EntityID::Slug(id) => write!(f, "slug:{id}"),
+            EntityID::Frog(id) => write!(f, "frog:{id}"),
+        }
+    }
+}
+
+fn tagged(s: &str) -> Option<EntityID> {
+    match s.split_once(':')? {
+        ("snake", id) => id.parse::<SnakeID>().ok().map(Into::into),
+        ("slug", id) => id.parse::<SlugID>().ok().map(Into::into),
+        ("frog", id) => id.parse::<FrogID>().ok().map(Into::into),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(secs: u64, nanos: u32) -> SystemTime {
+        UNIX_EPOCH + Duration::new(secs, nanos)
+    }
+
+    #[test]
+    fn test_display() {
+        let event = Event {
+            kind: EventKind::SnakeRegistered {
+                snake: SnakeID::new(1).unwrap(),
+                eaten_by: SlugID::new(2).unwrap(),
+            },
+            occurred_at: at(1_700_000_000, 5),
+        };
+        assert_eq!(
+            event.to_string(),
+            "SnakeRegistered snake=1 eaten_by=2 at=1700000000.000000005"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snake = SnakeID::new(1).unwrap();
+        let slug = SlugID::new(2).unwrap();
+        let frog = FrogID::new(u64::MAX).unwrap();
+        let kinds = [
+            EventKind::SnakeRegistered {
+                snake,
+                eaten_by: slug,
+            },
+            EventKind::SlugRegistered {
+                slug,
+                eaten_by: frog,
+            },
+            EventKind::FrogRegistered {
+                frog,
+                eaten_by: snake,
+            },
+            EventKind::EatenByChanged {
+                prey: frog.into(),
+                from: snake.into(),
+                to: SnakeID::new(3).unwrap().into(),
+            },
+            EventKind::SnakeEaten { snake },
+            EventKind::SlugEaten { slug },
+            EventKind::FrogEaten { frog },
+        ];
+        for kind in kinds {
+            let event = Event {
+                kind,
+                occurred_at: at(1_700_000_000, 123_456_789),
+            };
+            assert_eq!(event.to_string().parse::<Event>().unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn test_topic() {
+        let event = Event::now(EventKind::EatenByChanged {
+            prey: SlugID::new(2).unwrap().into(),
+            from: FrogID::new(1).unwrap().into(),
+            to: FrogID::new(3).unwrap().into(),
+        });
+        assert_eq!(event.subject(), SlugID::new(2).unwrap().into());
+        assert_eq!(event.topic(), "slug");
+        let event = Event::now(EventKind::FrogEaten {
+            frog: FrogID::new(1).unwrap(),
+        });
+        assert_eq!(event.topic(), "frog");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for s in [
+            "",
+            "SnakeRegistered snake=1 eaten_by=2",
+            "SnakeRegistered snake=0 eaten_by=2 at=0.0",
+            "SnakeMolted snake=1 at=0.0",
+            "EatenByChanged prey=snake:1 from=slug:2 to=toad:3 at=0.0",
+            "SlugEaten slug=1 at=yesterday",
+            "SlugEaten slug=1 at=1.5",
+        ] {
+            assert!(s.parse::<Event>().is_err(), "{s:?}");
+        }
+    }
+}
@@ -1,27 +1,27 @@
-use anyhow::Result;
 use async_trait::async_trait;
-use mockall::automock;
+use mockall::{automock, mock};
 
-#[derive(Default)]
-pub struct SnakeID;
-#[derive(Default)]
-pub struct SlugID;
-#[derive(Default)]
-pub struct FrogID;
+mod error;
+mod event;
+mod id;
 
-#[derive(Default)]
+pub use error::{BoxError, EntityID, Error, Result, ResultExt};
+pub use event::{Event, EventError, EventKind};
+pub use id::{FrogID, IdError, SlugID, SnakeID};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Snake {
     pub id: SnakeID,
     pub eaten_by: SlugID,
 }
 
-#[derive(Default)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Slug {
     pub id: SlugID,
     pub eaten_by: FrogID,
 }
 
-#[derive(Default)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Frog {
     pub id: FrogID,
     pub eaten_by: SnakeID,
@@ -41,34 +41,108 @@ pub trait RepositoryProvider {
     fn frog_repository(&self) -> &Self::FrogRepository;
 }
 
+/// A [`RepositoryProvider`] that can start transactions.
+#[async_trait]
+pub trait TransactionProvider: RepositoryProvider + Send + Sync {
+    type Transaction: Transaction;
+    async fn begin(&self) -> Result<Self::Transaction>;
+}
+
+/// A [`RepositoryProvider`] scoped to a transaction. Its repositories see the
+/// transaction's own writes, which take effect together on commit; dropping it
+/// without committing rolls back.
+#[async_trait]
+pub trait Transaction: RepositoryProvider + Send + Sync {
+    async fn commit(self) -> Result<()>;
+    async fn rollback(self) -> Result<()>;
+}
+
+mock! {
+    pub Transaction {}
+    impl RepositoryProvider for Transaction {
+        type SnakeRepository = MockSnakeRepository;
+        type SlugRepository = MockSlugRepository;
+        type FrogRepository = MockFrogRepository;
+        fn snake_repository(&self) -> &MockSnakeRepository;
+        fn slug_repository(&self) -> &MockSlugRepository;
+        fn frog_repository(&self) -> &MockFrogRepository;
+    }
+    #[async_trait]
+    impl Transaction for Transaction {
+        async fn commit(self) -> Result<()>;
+        async fn rollback(self) -> Result<()>;
+    }
+}
+
+mock! {
+    pub TransactionProvider {}
+    impl RepositoryProvider for TransactionProvider {
+        type SnakeRepository = MockSnakeRepository;
+        type SlugRepository = MockSlugRepository;
+        type FrogRepository = MockFrogRepository;
+        fn snake_repository(&self) -> &MockSnakeRepository;
+        fn slug_repository(&self) -> &MockSlugRepository;
+        fn frog_repository(&self) -> &MockFrogRepository;
+    }
+    #[async_trait]
+    impl TransactionProvider for TransactionProvider {
+        type Transaction = MockTransaction;
+        async fn begin(&self) -> Result<MockTransaction>;
+    }
+}
+
 #[automock]
 #[async_trait]
 pub trait SnakeRepository: Send + Sync {
     async fn get_snake(&self, id: SnakeID) -> Result<Snake>;
+    /// Fails with [`Error::Conflict`] if a snake with the same ID is already stored.
+    async fn insert_snake(&self, snake: Snake) -> Result<()>;
+    /// Fails with [`Error::NotFound`] if no snake with the same ID is stored.
+    async fn update_snake(&self, snake: Snake) -> Result<()>;
+    async fn upsert_snake(&self, snake: Snake) -> Result<()>;
+    /// Fails with [`Error::NotFound`] if no snake with the ID is stored.
+    async fn delete_snake(&self, id: SnakeID) -> Result<()>;
 }
 #[automock]
 #[async_trait]
 pub trait SlugRepository: Send + Sync {
     async fn get_slug(&self, id: SlugID) -> Result<Slug>;
+    /// Fails with [`Error::Conflict`] if a slug with the same ID is already stored.
+    async fn insert_slug(&self, slug: Slug) -> Result<()>;
+    /// Fails with [`Error::NotFound`] if no slug with the same ID is stored.
+    async fn update_slug(&self, slug: Slug) -> Result<()>;
+    async fn upsert_slug(&self, slug: Slug) -> Result<()>;
+    /// Fails with [`Error::NotFound`] if no slug with the ID is stored.
+    async fn delete_slug(&self, id: SlugID) -> Result<()>;
 }
 #[automock]
 #[async_trait]
 pub trait FrogRepository: Send + Sync {
     async fn get_frog(&self, id: FrogID) -> Result<Frog>;
+    /// Fails with [`Error::Conflict`] if a frog with the same ID is already stored.
+    async fn insert_frog(&self, frog: Frog) -> Result<()>;
+    /// Fails with [`Error::NotFound`] if no frog with the same ID is stored.
+    async fn update_frog(&self, frog: Frog) -> Result<()>;
+    async fn upsert_frog(&self, frog: Frog) -> Result<()>;
+    /// Fails with [`Error::NotFound`] if no frog with the ID is stored.
+    async fn delete_frog(&self, id: FrogID) -> Result<()>;
 }
 
 #[automock(
     type SnakeUseCase=MockSnakeUseCase;
     type SlugUseCase=MockSlugUseCase;
     type FrogUseCase=MockFrogUseCase;
+    type FoodChainUseCase=MockFoodChainUseCase;
 )]
 pub trait UseCaseProvider {
     type SnakeUseCase: SnakeUseCase;
     type SlugUseCase: SlugUseCase;
     type FrogUseCase: FrogUseCase;
+    type FoodChainUseCase: FoodChainUseCase;
     fn snake_use_case(&self) -> &Self::SnakeUseCase;
     fn slug_use_case(&self) -> &Self::SlugUseCase;
     fn frog_use_case(&self) -> &Self::FrogUseCase;
+    fn food_chain_use_case(&self) -> &Self::FoodChainUseCase;
 }
 
 #[automock]
@@ -76,18 +150,51 @@ pub trait UseCaseProvider {
 pub trait SnakeUseCase: Send + Sync {
     async fn get_snake(&self, id: SnakeID) -> Result<Snake>;
     async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake>;
+    /// Stores a new snake, failing with [`Error::NotFound`] unless the slug it
+    /// is eaten by is registered, or with [`Error::Conflict`] if its ID is
+    /// taken.
+    async fn register_snake(&self, snake: Snake) -> Result<Snake>;
+    /// Makes the frog prey of the snake; both must already be registered.
+    async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog>;
 }
 #[automock]
 #[async_trait]
 pub trait SlugUseCase: Send + Sync {
     async fn get_slug(&self, id: SlugID) -> Result<Slug>;
     async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug>;
+    /// Stores a new slug, failing with [`Error::NotFound`] unless the frog it
+    /// is eaten by is registered, or with [`Error::Conflict`] if its ID is
+    /// taken.
+    async fn register_slug(&self, slug: Slug) -> Result<Slug>;
+    /// Makes the snake prey of the slug; both must already be registered.
+    async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake>;
 }
 #[automock]
 #[async_trait]
 pub trait FrogUseCase: Send + Sync {
     async fn get_frog(&self, id: FrogID) -> Result<Frog>;
     async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog>;
+    /// Stores a new frog, failing with [`Error::NotFound`] unless the snake it
+    /// is eaten by is registered, or with [`Error::Conflict`] if its ID is
+    /// taken.
+    async fn register_frog(&self, frog: Frog) -> Result<Frog>;
+    /// Makes the slug prey of the frog; both must already be registered.
+    async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug>;
+}
+#[automock]
+#[async_trait]
+pub trait FoodChainUseCase: Send + Sync {
+    /// Stores new animals together or none of them, failing with
+    /// [`Error::NotFound`] unless each is eaten by one of them or by one
+    /// already registered, or with [`Error::Conflict`] if an ID is taken.
+    /// Food chains are cycles, so the first animals of one are registered
+    /// this way: none of them can be registered before its predator.
+    async fn register_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()>;
 }
 
 #[automock(
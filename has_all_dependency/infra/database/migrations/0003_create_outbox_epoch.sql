@@ -0,0 +1,7 @@
+-- Identifies the outbox of this database: the relay publishes as a producer
+-- named after it, so the message queue never takes the outbox IDs of another
+-- database, which start over at 1, for ones it has already published.
+CREATE TABLE outbox_epoch (
+    epoch INTEGER NOT NULL
+);
+INSERT INTO outbox_epoch (epoch) VALUES (random());
@@ -0,0 +1,609 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::Deref,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    blocking, memory::MemoryStore, sqlite::SqliteStore, Backend, DatabaseConfig,
+    DatabaseConnection, Error, Result,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Connections opened up front and kept through idle reaping.
+    pub min_size: usize,
+    /// Upper bound on connections checked out at the same time.
+    pub max_size: usize,
+    /// How long [`Pool::get`] waits for a free connection.
+    pub checkout_timeout: Duration,
+    /// How long a connection may sit unused before it is closed.
+    pub idle_timeout: Duration,
+    /// How long a SQLite file connection waits for another one's lock before
+    /// failing as busy.
+    pub busy_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 1,
+            max_size: 8,
+            checkout_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(300),
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A point-in-time view of the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    pub max_size: usize,
+    pub idle: usize,
+    pub in_use: usize,
+    pub waiting: usize,
+    pub checkouts: u64,
+    pub timeouts: u64,
+    pub reaped: u64,
+    pub broken: u64,
+}
+
+impl PoolStats {
+    pub fn size(&self) -> usize {
+        self.idle + self.in_use
+    }
+
+    /// Fraction of the maximum size currently checked out.
+    pub fn saturation(&self) -> f64 {
+        self.in_use as f64 / self.max_size as f64
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} in use, {} idle, {} waiting, {} checkouts, {} timeouts, {} reaped, {} broken",
+            self.in_use,
+            self.max_size,
+            self.idle,
+            self.waiting,
+            self.checkouts,
+            self.timeouts,
+            self.reaped,
+            self.broken
+        )
+    }
+}
+
+/// Opens new connections for the pool. Every connection of an in-memory
+/// backend has to see the same data, so those share one store.
+#[derive(Debug)]
+enum Connector {
+    Memory(Arc<MemoryStore>),
+    Sqlite {
+        path: PathBuf,
+        busy_timeout: Duration,
+    },
+    // Shared-cache in-memory databases live as long as one connection is open,
+    // so the connector keeps one of its own.
+    SqliteMemory {
+        uri: String,
+        _keep: SqliteStore,
+    },
+}
+
+impl Connector {
+    fn new(config: &DatabaseConfig, busy_timeout: Duration) -> Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        match config {
+            DatabaseConfig::Memory => Ok(Self::Memory(Arc::default())),
+            DatabaseConfig::Sqlite(path) if path.as_os_str() == ":memory:" => {
+                let uri = format!(
+                    "file:pool-{}-{}?mode=memory&cache=shared",
+                    process::id(),
+                    NEXT.fetch_add(1, Ordering::Relaxed)
+                );
+                let keep = SqliteStore::open(&uri)?;
+                Ok(Self::SqliteMemory { uri, _keep: keep })
+            }
+            DatabaseConfig::Sqlite(path) => Ok(Self::Sqlite {
+                path: path.clone(),
+                busy_timeout,
+            }),
+        }
+    }
+
+    fn connect(&self) -> Result<DatabaseConnection> {
+        let backend = match self {
+            Self::Memory(store) => Backend::Memory(Arc::clone(store)),
+            Self::Sqlite { path, busy_timeout } => {
+                let store = SqliteStore::open(path)?;
+                store.set_busy_timeout(*busy_timeout)?;
+                Backend::Sqlite(store)
+            }
+            Self::SqliteMemory { uri, .. } => Backend::Sqlite(SqliteStore::open(uri)?),
+        };
+        Ok(DatabaseConnection { backend })
+    }
+}
+
+#[derive(Debug)]
+struct Idle {
+    conn: DatabaseConnection,
+    since: Instant,
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: PoolConfig,
+    connector: Connector,
+    semaphore: Arc<Semaphore>,
+    idle: Mutex<VecDeque<Idle>>,
+    in_use: AtomicUsize,
+    waiting: AtomicUsize,
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    reaped: AtomicU64,
+    broken: AtomicU64,
+}
+
+impl Shared {
+    fn idle(&self) -> std::sync::MutexGuard<'_, VecDeque<Idle>> {
+        // The queue is consistent after every push and pop, so a panic while
+        // it was locked leaves nothing to repair.
+        self.idle.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Takes the most recently used idle connection that still answers.
+    fn take(&self) -> Result<DatabaseConnection> {
+        loop {
+            let Some(idle) = self.idle().pop_back() else {
+                return self.connector.connect();
+            };
+            match idle.conn.ping() {
+                Ok(()) => return Ok(idle.conn),
+                Err(_) => _ = self.broken.fetch_add(1, Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Drops idle connections that no longer answer and those idle for
+    /// longer than the idle timeout, then opens new ones until the pool is
+    /// back to `min_size`.
+    fn reap(&self) -> usize {
+        let reaped = {
+            let mut idle = self.idle();
+            let before = idle.len();
+            idle.retain(|idle| idle.conn.ping().is_ok());
+            self.broken
+                .fetch_add((before - idle.len()) as u64, Ordering::Relaxed);
+            let in_use = self.in_use.load(Ordering::Relaxed);
+            let mut reaped = 0;
+            while let Some(oldest) = idle.front() {
+                if idle.len() + in_use <= self.config.min_size
+                    || oldest.since.elapsed() < self.config.idle_timeout
+                {
+                    break;
+                }
+                idle.pop_front();
+                reaped += 1;
+            }
+            reaped
+        };
+        self.reaped.fetch_add(reaped as u64, Ordering::Relaxed);
+        self.replenish();
+        reaped
+    }
+
+    /// Opens connections, outside the lock, until `min_size` are open. One
+    /// that fails to open is left for the next reap to retry.
+    fn replenish(&self) {
+        let missing = {
+            let idle = self.idle();
+            let in_use = self.in_use.load(Ordering::Relaxed);
+            self.config.min_size.saturating_sub(idle.len() + in_use)
+        };
+        for _ in 0..missing {
+            let Ok(conn) = self.connector.connect() else {
+                return;
+            };
+            self.idle().push_front(Idle {
+                conn,
+                since: Instant::now(),
+            });
+        }
+    }
+}
+
+/// Counts a caller waiting for a connection for as long as it is alive, so
+/// one that gives up waiting by dropping [`Pool::get`] is still uncounted.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A bounded pool of [`DatabaseConnection`]s.
+///
+/// SQLite connections block while they open, probe and query, so the pool
+/// does that on Tokio's blocking threads, as does
+/// [`PooledConnection::run`].
+#[derive(Debug, Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl Pool {
+    /// Opens `min_size` connections up front, so a bad configuration fails here.
+    pub fn open(database: &DatabaseConfig, config: PoolConfig) -> Result<Self> {
+        if config.max_size == 0 || config.min_size > config.max_size {
+            return Err(Error::InvalidPool(format!(
+                "min size {} and max size {} are out of range",
+                config.min_size, config.max_size
+            )));
+        }
+        let connector = Connector::new(database, config.busy_timeout)?;
+        let idle = (0..config.min_size)
+            .map(|_| {
+                Ok(Idle {
+                    conn: connector.connect()?,
+                    since: Instant::now(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                semaphore: Arc::new(Semaphore::new(config.max_size)),
+                config,
+                connector,
+                idle: Mutex::new(idle),
+                in_use: AtomicUsize::new(0),
+                waiting: AtomicUsize::new(0),
+                checkouts: AtomicU64::new(0),
+                timeouts: AtomicU64::new(0),
+                reaped: AtomicU64::new(0),
+                broken: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// Checks out a connection that passed its liveness probe, waiting up to
+    /// the checkout timeout when all of them are in use.
+    pub async fn get(&self) -> Result<PooledConnection> {
+        let shared = &self.shared;
+        let waiting = Waiting::new(&shared.waiting);
+        let permit = time::timeout(
+            shared.config.checkout_timeout,
+            Arc::clone(&shared.semaphore).acquire_owned(),
+        )
+        .await;
+        drop(waiting);
+        let Ok(permit) = permit else {
+            shared.timeouts.fetch_add(1, Ordering::Relaxed);
+            return Err(Error::Timeout(shared.config.checkout_timeout));
+        };
+        let permit = permit.expect("pool semaphore is never closed");
+        let conn = match shared.connector {
+            Connector::Memory(_) => shared.take()?,
+            _ => {
+                let shared = Arc::clone(shared);
+                blocking(move || shared.take()).await?
+            }
+        };
+        shared.in_use.fetch_add(1, Ordering::Relaxed);
+        shared.checkouts.fetch_add(1, Ordering::Relaxed);
+        Ok(PooledConnection {
+            checkout: Arc::new(Checkout {
+                conn: Some(conn),
+                shared: Arc::clone(shared),
+                _permit: permit,
+            }),
+        })
+    }
+
+    /// Closes connections idle for longer than the idle timeout, keeping at
+    /// least `min_size` open, and replaces broken ones. Returns how many were
+    /// closed for being idle.
+    pub fn reap(&self) -> usize {
+        self.shared.reap()
+    }
+
+    /// Reaps idle connections in the background until the pool is dropped.
+    pub fn spawn_reaper(&self) -> JoinHandle<()> {
+        let shared = Arc::downgrade(&self.shared);
+        let period = (self.shared.config.idle_timeout / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            let mut interval = time::interval(period);
+            loop {
+                interval.tick().await;
+                match shared.upgrade() {
+                    Some(shared) => _ = blocking(move || shared.reap()).await,
+                    None => break,
+                }
+            }
+        })
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let shared = &self.shared;
+        PoolStats {
+            max_size: shared.config.max_size,
+            idle: shared.idle().len(),
+            in_use: shared.in_use.load(Ordering::Relaxed),
+            waiting: shared.waiting.load(Ordering::Relaxed),
+            checkouts: shared.checkouts.load(Ordering::Relaxed),
+            timeouts: shared.timeouts.load(Ordering::Relaxed),
+            reaped: shared.reaped.load(Ordering::Relaxed),
+            broken: shared.broken.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A checked-out connection, returned to the pool when dropped and done with
+/// whatever [`run`](Self::run) started on it.
+#[derive(Debug)]
+pub struct PooledConnection {
+    checkout: Arc<Checkout>,
+}
+
+impl PooledConnection {
+    /// Runs `f` on the connection. A SQLite connection blocks while it
+    /// queries, and while it waits out another one's lock, so `f` runs on
+    /// Tokio's blocking threads and goes on to the end even if the returned
+    /// future is dropped; an in-memory one runs it right away.
+    pub async fn run<T: Send + 'static, E: Send + 'static>(
+        &self,
+        f: impl FnOnce(&DatabaseConnection) -> Result<T, E> + Send + 'static,
+    ) -> Result<T, E> {
+        match &self.backend {
+            Backend::Memory(_) => f(self),
+            Backend::Sqlite(_) => {
+                let checkout = Arc::clone(&self.checkout);
+                blocking(move || f(&checkout)).await
+            }
+        }
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = DatabaseConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.checkout
+    }
+}
+
+#[derive(Debug)]
+struct Checkout {
+    conn: Option<DatabaseConnection>,
+    shared: Arc<Shared>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Deref for Checkout {
+    type Target = DatabaseConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn
+            .as_ref()
+            .expect("connection is taken only on drop")
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.shared.idle().push_back(Idle {
+                conn,
+                since: Instant::now(),
+            });
+        }
+        self.shared.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, sync::mpsc};
+
+    use super::*;
+    use crate::{test::seed, Migrator, SnakeRow, Transaction};
+
+    fn pool(database: &str, min_size: usize, max_size: usize) -> Pool {
+        let config = PoolConfig {
+            min_size,
+            max_size,
+            checkout_timeout: Duration::from_millis(20),
+            idle_timeout: Duration::ZERO,
+            busy_timeout: Duration::ZERO,
+        };
+        Pool::open(&database.parse().unwrap(), config).unwrap()
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        for (min_size, max_size) in [(0, 0), (3, 2)] {
+            let config = PoolConfig {
+                min_size,
+                max_size,
+                ..PoolConfig::default()
+            };
+            assert!(matches!(
+                Pool::open(&DatabaseConfig::Memory, config),
+                Err(Error::InvalidPool(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connections_share_data() {
+        for database in ["memory", "sqlite::memory:"] {
+            let pool = pool(database, 0, 2);
+            let first = pool.get().await.unwrap();
+            let second = pool.get().await.unwrap();
+            Migrator::default().migrate(&first).unwrap();
+            seed(&first, [2]);
+            first.insert(SnakeRow { id: 1, eaten_by: 2 }).unwrap();
+            assert_eq!(
+                second.get::<SnakeRow>(1).unwrap(),
+                Some(SnakeRow { id: 1, eaten_by: 2 })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkout_reuses_connections() {
+        let pool = pool("memory", 1, 2);
+        assert_eq!(pool.stats().idle, 1);
+        drop(pool.get().await.unwrap());
+        drop(pool.get().await.unwrap());
+        let stats = pool.stats();
+        assert_eq!((stats.size(), stats.idle, stats.in_use), (1, 1, 0));
+        assert_eq!(stats.checkouts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_checkout_timeout() {
+        let pool = pool("memory", 0, 1);
+        let _conn = pool.get().await.unwrap();
+        assert!(matches!(pool.get().await, Err(Error::Timeout(_))));
+        let stats = pool.stats();
+        assert_eq!((stats.in_use, stats.timeouts), (1, 1));
+        assert_eq!(stats.saturation(), 1.0);
+        assert!(Error::Timeout(Duration::ZERO).is_transient());
+    }
+
+    #[tokio::test]
+    async fn test_waiter_gets_released_connection() {
+        let pool = pool("memory", 0, 1);
+        let conn = pool.get().await.unwrap();
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.map(drop) }
+        });
+        tokio::task::yield_now().await;
+        drop(conn);
+        waiter.await.unwrap().unwrap();
+        assert_eq!(pool.stats().timeouts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reap_keeps_min_size() {
+        let pool = pool("memory", 1, 3);
+        let conns = [
+            pool.get().await.unwrap(),
+            pool.get().await.unwrap(),
+            pool.get().await.unwrap(),
+        ];
+        assert_eq!(pool.reap(), 0);
+        drop(conns);
+        assert_eq!(pool.stats().idle, 3);
+        assert_eq!(pool.reap(), 2);
+        let stats = pool.stats();
+        assert_eq!((stats.idle, stats.reaped), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_checkout_stops_waiting() {
+        let pool = pool("memory", 0, 1);
+        let _conn = pool.get().await.unwrap();
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.map(drop) }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(pool.stats().waiting, 1);
+        waiter.abort();
+        assert!(waiter.await.unwrap_err().is_cancelled());
+        assert_eq!(pool.stats().waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reap_replenishes_min_size() {
+        let pool = pool("memory", 2, 3);
+        pool.shared.idle().clear();
+        assert_eq!(pool.reap(), 0);
+        assert_eq!(pool.stats().idle, 2);
+        let _conn = pool.get().await.unwrap();
+        pool.shared.idle().clear();
+        pool.reap();
+        let stats = pool.stats();
+        assert_eq!((stats.idle, stats.in_use), (1, 1));
+    }
+
+    /// A caller that stops waiting for work on a SQLite connection gets its
+    /// worker back at once, while the work keeps the connection checked out
+    /// until it is done.
+    #[tokio::test]
+    async fn test_run_abandoned() {
+        let pool = pool("sqlite::memory:", 1, 1);
+        let conn = pool.get().await.unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let run = conn.run(move |_| -> Result<()> {
+            _ = released.recv();
+            Ok(())
+        });
+        assert!(time::timeout(Duration::from_millis(10), run).await.is_err());
+        drop(conn);
+        assert_eq!(pool.stats().in_use, 1);
+        release.send(()).unwrap();
+        time::timeout(Duration::from_secs(1), async {
+            while pool.stats().in_use > 0 {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    /// The second transaction waits out the first one's lock on a blocking
+    /// thread, so the only worker of the runtime is free to release it.
+    #[tokio::test]
+    async fn test_busy_timeout() {
+        let path = env::temp_dir().join(format!("pool-busy-{}.db", process::id()));
+        _ = fs::remove_file(&path);
+        let config = PoolConfig {
+            busy_timeout: Duration::from_secs(1),
+            ..PoolConfig::default()
+        };
+        let pool = Pool::open(&DatabaseConfig::Sqlite(path.clone()), config).unwrap();
+        let writer = Transaction::begin(pool.get().await.unwrap()).await.unwrap();
+        let release = tokio::spawn(async move {
+            time::sleep(Duration::from_millis(50)).await;
+            writer.rollback().await.unwrap();
+        });
+        Transaction::begin(pool.get().await.unwrap())
+            .await
+            .unwrap()
+            .rollback()
+            .await
+            .unwrap();
+        release.await.unwrap();
+        drop(pool);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    lock,
+    memory::{self, Check, Tables, Write},
+    sqlite::SqliteStore,
+    Backend, Error, PooledConnection, Result, Row,
+};
+
+/// In-memory transactions write to a private copy of the tables and record
+/// each write, so commit can replay them onto whatever is current by then,
+/// and check the foreign keys of the rows written. They also record each row
+/// read as it was at begin, so commit fails rather than replay writes made on
+/// the strength of a row another transaction has changed since.
+#[derive(Default)]
+struct Pending {
+    /// The tables as of begin.
+    base: Arc<Tables>,
+    tables: Arc<Tables>,
+    /// By table and ID, so a row read twice is checked once.
+    reads: BTreeMap<(&'static str, u64), Check>,
+    writes: Vec<Write>,
+    checks: Vec<Check>,
+}
+
+struct Inner {
+    conn: PooledConnection,
+    // `None` once committed or rolled back.
+    pending: Mutex<Option<Pending>>,
+}
+
+/// A unit of work on one pooled connection. Reads see the transaction's own
+/// writes, which take effect together on [`commit`](Self::commit) or not at
+/// all.
+///
+/// Clones are handles to the same transaction. Once any of them commits or
+/// rolls back, the others fail with [`Error::Finished`]; dropping the last
+/// one uncommitted rolls back.
+#[derive(Clone)]
+pub struct Transaction {
+    inner: Arc<Inner>,
+}
+
+impl Transaction {
+    /// Begins on the connection from within [`run`](Self::run), so a caller
+    /// that stops waiting leaves the transaction to be rolled back once it
+    /// has begun.
+    pub(crate) async fn begin(conn: PooledConnection) -> Result<Self> {
+        let transaction = Self {
+            inner: Arc::new(Inner {
+                conn,
+                pending: Mutex::new(None),
+            }),
+        };
+        transaction
+            .run(|transaction| -> Result<()> {
+                let pending = match &transaction.inner.conn.backend {
+                    Backend::Memory(store) => {
+                        let tables = store.tables()?;
+                        Pending {
+                            base: Arc::clone(&tables),
+                            tables,
+                            ..Pending::default()
+                        }
+                    }
+                    Backend::Sqlite(store) => {
+                        store.begin()?;
+                        Pending::default()
+                    }
+                };
+                *transaction.inner.pending() = Some(pending);
+                Ok(())
+            })
+            .await?;
+        Ok(transaction)
+    }
+
+    /// Runs `f` on the transaction, on Tokio's blocking threads if it is on
+    /// a SQLite connection; see [`PooledConnection::run`].
+    pub async fn run<T: Send + 'static, E: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Self) -> Result<T, E> + Send + 'static,
+    ) -> Result<T, E> {
+        let transaction = self.clone();
+        self.inner.conn.run(move |_| f(&transaction)).await
+    }
+
+    pub fn get<R: Row>(&self, id: u64) -> Result<Option<R>> {
+        let mut pending = self.inner.pending();
+        let pending = pending.as_mut().ok_or(Error::Finished)?;
+        match &self.inner.conn.backend {
+            Backend::Memory(_) => {
+                pending
+                    .reads
+                    .entry((R::TABLE, id))
+                    .or_insert_with(|| unchanged::<R>(&pending.base, id));
+                Ok(R::table(&pending.tables).get(&id).cloned())
+            }
+            Backend::Sqlite(store) => store.get(id),
+        }
+    }
+
+    /// Fails with [`Error::Conflict`] if a row with the same ID exists.
+    pub fn insert<R: Row>(&self, row: R) -> Result<()> {
+        let check = checked::<R>(row.id());
+        self.write(row, memory::insert, SqliteStore::insert, check)
+    }
+
+    /// Fails with [`Error::NotFound`] if no row with the same ID exists.
+    pub fn update<R: Row>(&self, row: R) -> Result<()> {
+        let check = checked::<R>(row.id());
+        self.write(row, memory::update, SqliteStore::update, check)
+    }
+
+    pub fn upsert<R: Row>(&self, row: R) -> Result<()> {
+        let check = checked::<R>(row.id());
+        self.write(row, memory::upsert, SqliteStore::upsert, check)
+    }
+
+    /// Fails with [`Error::NotFound`] if no row with the ID exists.
+    pub fn delete<R: Row>(&self, id: u64) -> Result<()> {
+        let check = checked::<R>(id);
+        self.write(id, memory::delete::<R>, SqliteStore::delete::<R>, check)
+    }
+
+    /// Adds a message to the outbox, so it is published only if the
+    /// transaction commits.
+    pub fn enqueue(&self, topic: &str, payload: impl Into<Vec<u8>>) -> Result<()> {
+        let message = (topic.to_owned(), payload.into());
+        self.write(message, memory::enqueue, SqliteStore::enqueue, None)
+    }
+
+    /// Applies every write of the transaction. Foreign keys are checked
+    /// here rather than on each write, so the rows written may refer to each
+    /// other in any order; a row left referring to one that does not exist
+    /// fails the commit with [`Error::ForeignKey`]. In memory, a row read
+    /// that another transaction has changed since fails the commit with
+    /// [`Error::Conflict`], as does a write that no longer holds against the
+    /// current tables (say, an insert racing another one), and nothing is
+    /// applied.
+    pub async fn commit(self) -> Result<()> {
+        self.run(|transaction| {
+            let pending = transaction.inner.finish()?;
+            match &transaction.inner.conn.backend {
+                Backend::Memory(store) => {
+                    store.apply(pending.reads.values(), &pending.writes, &pending.checks)
+                }
+                Backend::Sqlite(store) => store.commit().map_err(|err| {
+                    _ = store.rollback();
+                    err
+                }),
+            }
+        })
+        .await
+    }
+
+    pub async fn rollback(self) -> Result<()> {
+        self.run(|transaction| {
+            transaction.inner.finish()?;
+            match &transaction.inner.conn.backend {
+                Backend::Memory(_) => Ok(()),
+                Backend::Sqlite(store) => store.rollback(),
+            }
+        })
+        .await
+    }
+
+    fn write<T: Clone + Send + Sync + 'static>(
+        &self,
+        arg: T,
+        memory: fn(&mut Tables, T) -> Result<()>,
+        sqlite: fn(&SqliteStore, T) -> Result<()>,
+        check: Option<Check>,
+    ) -> Result<()> {
+        let mut pending = self.inner.pending();
+        let pending = pending.as_mut().ok_or(Error::Finished)?;
+        match &self.inner.conn.backend {
+            Backend::Memory(_) => {
+                memory(Arc::make_mut(&mut pending.tables), arg.clone())?;
+                pending
+                    .writes
+                    .push(Box::new(move |tables| memory(tables, arg.clone())));
+                pending.checks.extend(check);
+                Ok(())
+            }
+            Backend::Sqlite(store) => sqlite(store, arg),
+        }
+    }
+}
+
+/// The commit check of the foreign keys of the row with the ID.
+fn checked<R: Row>(id: u64) -> Option<Check> {
+    Some(Box::new(move |tables| memory::check::<R>(tables, id)))
+}
+
+/// The commit check that the row with the ID is still as it is in `tables`,
+/// or still missing.
+fn unchanged<R: Row>(tables: &Tables, id: u64) -> Check {
+    let read = R::table(tables).get(&id).cloned();
+    Box::new(move |tables| {
+        if R::table(tables).get(&id) != read.as_ref() {
+            return Err(Error::Conflict {
+                table: R::TABLE,
+                id,
+            });
+        }
+        Ok(())
+    })
+}
+
+impl Inner {
+    fn pending(&self) -> MutexGuard<'_, Option<Pending>> {
+        lock(&self.pending)
+    }
+
+    fn finish(&self) -> Result<Pending> {
+        self.pending().take().ok_or(Error::Finished)
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let active = lock(&self.pending).is_some();
+        if let (true, Backend::Sqlite(store)) = (active, &self.conn.backend) {
+            _ = store.rollback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test::seed, Database, DatabaseConfig, FrogRow, Migrator, Pool, PoolConfig, SlugRow,
+        SnakeRow,
+    };
+
+    /// Databases holding a snake, a slug and a frog with each of the IDs 7 to
+    /// 9, for the rows under test to be eaten by.
+    async fn databases() -> Vec<Database> {
+        let mut databases = Vec::new();
+        for url in ["memory", "sqlite::memory:"] {
+            let pool = Pool::open(&url.parse().unwrap(), PoolConfig::default()).unwrap();
+            let conn = pool.get().await.unwrap();
+            Migrator::default().migrate(&conn).unwrap();
+            seed(&conn, 7..=9);
+            drop(conn);
+            databases.push(Database::new(pool));
+        }
+        databases
+    }
+
+    fn snake(id: u64, eaten_by: u64) -> SnakeRow {
+        SnakeRow { id, eaten_by }
+    }
+
+    #[tokio::test]
+    async fn test_commit() {
+        for database in databases().await {
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(1, 7)).unwrap();
+            tx.update(snake(1, 8)).unwrap();
+            tx.insert(SlugRow { id: 3, eaten_by: 9 }).unwrap();
+            assert_eq!(tx.get::<SnakeRow>(1).unwrap(), Some(snake(1, 8)));
+            tx.commit().await.unwrap();
+            let conn = database.conn().await.unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 8)));
+            assert_eq!(
+                conn.get::<SlugRow>(3).unwrap(),
+                Some(SlugRow { id: 3, eaten_by: 9 })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        for database in databases().await {
+            database.conn().await.unwrap().insert(snake(1, 7)).unwrap();
+            let tx = database.begin().await.unwrap();
+            tx.delete::<SnakeRow>(1).unwrap();
+            tx.insert(snake(2, 7)).unwrap();
+            assert_eq!(tx.get::<SnakeRow>(1).unwrap(), None);
+            tx.rollback().await.unwrap();
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(3, 7)).unwrap();
+            drop(tx);
+            let conn = database.conn().await.unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 7)));
+            assert_eq!(conn.get::<SnakeRow>(2).unwrap(), None);
+            assert_eq!(conn.get::<SnakeRow>(3).unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outbox_follows_transaction() {
+        for database in databases().await {
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(1, 7)).unwrap();
+            tx.enqueue("snake", "1").unwrap();
+            tx.rollback().await.unwrap();
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(2, 7)).unwrap();
+            tx.enqueue("snake", "2").unwrap();
+            tx.commit().await.unwrap();
+            let unsent = database.conn().await.unwrap().unsent(10).unwrap();
+            assert_eq!(unsent.len(), 1);
+            assert_eq!(unsent[0].payload, b"2");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_keeps_transaction() {
+        for database in databases().await {
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(1, 7)).unwrap();
+            assert!(matches!(
+                tx.insert(snake(1, 8)),
+                Err(Error::Conflict { id: 1, .. })
+            ));
+            assert!(matches!(
+                tx.update(snake(2, 8)),
+                Err(Error::NotFound { id: 2, .. })
+            ));
+            tx.commit().await.unwrap();
+            let conn = database.conn().await.unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 7)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_commit_applies_nothing() {
+        let database =
+            Database::new(Pool::open(&DatabaseConfig::Memory, PoolConfig::default()).unwrap());
+        seed(&database.conn().await.unwrap(), [7, 9]);
+        let tx = database.begin().await.unwrap();
+        tx.insert(snake(1, 7)).unwrap();
+        tx.insert(snake(2, 7)).unwrap();
+        database.conn().await.unwrap().insert(snake(2, 9)).unwrap();
+        assert!(matches!(
+            tx.commit().await,
+            Err(Error::Conflict { id: 2, .. })
+        ));
+        let conn = database.conn().await.unwrap();
+        assert_eq!(conn.get::<SnakeRow>(1).unwrap(), None);
+        assert_eq!(conn.get::<SnakeRow>(2).unwrap(), Some(snake(2, 9)));
+    }
+
+    #[tokio::test]
+    async fn test_changed_read_fails_commit() {
+        let database =
+            Database::new(Pool::open(&DatabaseConfig::Memory, PoolConfig::default()).unwrap());
+        seed(&database.conn().await.unwrap(), 7..=9);
+        database.conn().await.unwrap().insert(snake(1, 7)).unwrap();
+        let tx = database.begin().await.unwrap();
+        let other = database.begin().await.unwrap();
+        assert_eq!(tx.get::<SnakeRow>(1).unwrap(), Some(snake(1, 7)));
+        assert_eq!(tx.get::<SnakeRow>(2).unwrap(), None);
+        assert_eq!(other.get::<SnakeRow>(1).unwrap(), Some(snake(1, 7)));
+        other.update(snake(1, 8)).unwrap();
+        other.commit().await.unwrap();
+        tx.update(snake(1, 9)).unwrap();
+        assert!(matches!(
+            tx.commit().await,
+            Err(Error::Conflict { id: 1, .. })
+        ));
+        let conn = database.conn().await.unwrap();
+        assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 8)));
+        drop(conn);
+
+        // Rows written by others but not read are no conflict.
+        let tx = database.begin().await.unwrap();
+        assert_eq!(tx.get::<SnakeRow>(1).unwrap(), Some(snake(1, 8)));
+        database.conn().await.unwrap().insert(snake(2, 7)).unwrap();
+        tx.update(snake(1, 9)).unwrap();
+        tx.commit().await.unwrap();
+        let conn = database.conn().await.unwrap();
+        assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 9)));
+        assert_eq!(conn.get::<SnakeRow>(2).unwrap(), Some(snake(2, 7)));
+    }
+
+    #[tokio::test]
+    async fn test_foreign_keys_checked_on_commit() {
+        for database in databases().await {
+            // The rows refer to each other in a cycle, written in any order.
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(1, 2)).unwrap();
+            tx.insert(SlugRow { id: 2, eaten_by: 3 }).unwrap();
+            tx.insert(FrogRow { id: 3, eaten_by: 1 }).unwrap();
+            tx.commit().await.unwrap();
+
+            let tx = database.begin().await.unwrap();
+            tx.insert(snake(4, 5)).unwrap();
+            tx.enqueue("snake", "4").unwrap();
+            assert!(matches!(tx.commit().await, Err(Error::ForeignKey)));
+            let conn = database.conn().await.unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 2)));
+            assert_eq!(conn.get::<SnakeRow>(4).unwrap(), None);
+            assert!(conn.unsent(10).unwrap().is_empty());
+            drop(conn);
+
+            // Slug 2 is still eaten by snake 1.
+            let tx = database.begin().await.unwrap();
+            tx.delete::<SnakeRow>(1).unwrap();
+            assert!(matches!(tx.commit().await, Err(Error::ForeignKey)));
+            let conn = database.conn().await.unwrap();
+            assert_eq!(conn.get::<SnakeRow>(1).unwrap(), Some(snake(1, 2)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finished_handles() {
+        for database in databases().await {
+            let tx = database.begin().await.unwrap();
+            let handle = tx.clone();
+            tx.insert(snake(1, 7)).unwrap();
+            assert_eq!(handle.get::<SnakeRow>(1).unwrap(), Some(snake(1, 7)));
+            tx.commit().await.unwrap();
+            assert!(matches!(handle.insert(snake(2, 7)), Err(Error::Finished)));
+            assert!(matches!(handle.commit().await, Err(Error::Finished)));
+            let conn = database.conn().await.unwrap();
+            assert_eq!(conn.get::<SnakeRow>(2).unwrap(), None);
+        }
+    }
+}
@@ -0,0 +1,753 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use domain::{
+    EntityID, Error, Frog, FrogID, FrogRepository, RepositoryProvider, Result, Slug, SlugID,
+    SlugRepository, Snake, SnakeID, SnakeRepository, Transaction, TransactionProvider,
+};
+use message_queue::Subscription;
+
+use crate::outbox::decode_event;
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Entries kept before the least recently used is evicted.
+    pub capacity: usize,
+    /// How long a fetched animal is served from the cache.
+    pub ttl: Duration,
+    /// How long an animal found missing is reported missing without asking
+    /// the inner repository again.
+    pub negative_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A point-in-time view of the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub invalidations: u64,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entries, {} hits, {} misses, {} evictions, {} invalidations",
+            self.entries, self.hits, self.misses, self.evictions, self.invalidations
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Snake(Snake),
+    Slug(Slug),
+    Frog(Frog),
+}
+
+/// An animal the cache can hold.
+trait Animal: Clone + Send + Sized {
+    fn wrap(self) -> Value;
+    fn unwrap(value: &Value) -> Option<Self>;
+}
+
+macro_rules! animal {
+    ($animal:ident) => {
+        impl Animal for $animal {
+            fn wrap(self) -> Value {
+                Value::$animal(self)
+            }
+            fn unwrap(value: &Value) -> Option<Self> {
+                match value {
+                    Value::$animal(animal) => Some(animal.clone()),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+animal!(Snake);
+animal!(Slug);
+animal!(Frog);
+
+#[derive(Debug)]
+struct Entry {
+    /// `None` if the animal was not found.
+    value: Option<Value>,
+    expires_at: Instant,
+    /// Key into [`State::recency`].
+    used: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<EntityID, Entry>,
+    /// Entries by last use, least recent first.
+    recency: BTreeMap<u64, EntityID>,
+    clock: u64,
+    /// Bumped on every invalidation, so a fetch that raced one is not cached.
+    generation: u64,
+    stats: CacheStats,
+}
+
+impl State {
+    fn touch(&mut self, id: EntityID) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&id) {
+            self.recency.remove(&entry.used);
+            entry.used = self.clock;
+            self.recency.insert(self.clock, id);
+        }
+    }
+
+    fn remove(&mut self, id: &EntityID) -> bool {
+        let Some(entry) = self.entries.remove(id) else {
+            return false;
+        };
+        self.recency.remove(&entry.used);
+        true
+    }
+}
+
+/// A least recently used cache of animals by ID, shared by the
+/// [`CachedRepository`]s in front of one backend.
+#[derive(Debug)]
+pub struct Cache {
+    config: CacheConfig,
+    state: Mutex<State>,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn invalidate(&self, id: impl Into<EntityID>) {
+        let mut state = self.lock();
+        state.generation += 1;
+        if state.remove(&id.into()) {
+            state.stats.invalidations += 1;
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.generation += 1;
+        state.stats.invalidations += state.entries.len() as u64;
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.lock();
+        CacheStats {
+            entries: state.entries.len(),
+            ..state.stats
+        }
+    }
+
+    /// Invalidates the animal each change event received is about, until
+    /// the subscription ends. Subscribe to the topics events are published
+    /// to, so writes that bypass the cache are seen too.
+    pub async fn invalidate_from(&self, mut subscription: Subscription) {
+        while let Some(message) = subscription.recv().await {
+            // Messages that are not events concern no animal.
+            if let Some(event) = decode_event(&message.payload) {
+                self.invalidate(event.subject());
+            }
+        }
+    }
+
+    async fn get<A: Animal>(
+        &self,
+        id: EntityID,
+        fetch: impl Future<Output = Result<A>> + Send,
+    ) -> Result<A> {
+        let generation = {
+            let mut state = self.lock();
+            let now = Instant::now();
+            match state.entries.get(&id) {
+                Some(entry) if entry.expires_at > now => {
+                    let value = entry.value.as_ref().map(A::unwrap);
+                    state.stats.hits += 1;
+                    state.touch(id);
+                    return match value {
+                        Some(Some(animal)) => Ok(animal),
+                        Some(None) => Err(Error::internal(format!("{id} cached as another kind"))),
+                        None => Err(Error::NotFound(id)),
+                    };
+                }
+                Some(_) => _ = state.remove(&id),
+                None => {}
+            }
+            state.stats.misses += 1;
+            state.generation
+        };
+        let result = fetch.await;
+        let value = match &result {
+            Ok(animal) => Some(animal.clone().wrap()),
+            Err(Error::NotFound(_)) => None,
+            // Failures are left to the next call to retry.
+            Err(_) => return result,
+        };
+        let ttl = match value {
+            Some(_) => self.config.ttl,
+            None => self.config.negative_ttl,
+        };
+        self.put(id, value, ttl, generation);
+        result
+    }
+
+    fn put(&self, id: EntityID, value: Option<Value>, ttl: Duration, generation: u64) {
+        let mut state = self.lock();
+        if state.generation != generation || self.config.capacity == 0 {
+            return;
+        }
+        state.remove(&id);
+        state.clock += 1;
+        let used = state.clock;
+        state.entries.insert(
+            id,
+            Entry {
+                value,
+                expires_at: Instant::now() + ttl,
+                used,
+            },
+        );
+        state.recency.insert(used, id);
+        while state.entries.len() > self.config.capacity {
+            let Some((_, oldest)) = state.recency.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+            state.stats.evictions += 1;
+        }
+    }
+
+    /// `entries` and `recency` are updated in separate steps, so a panic
+    /// while holding the lock can leave them out of step. A poisoned cache is
+    /// emptied on every lock from then on: reads all go to the inner
+    /// repository, as if each animal had just been invalidated.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| {
+            let mut state = poisoned.into_inner();
+            state.generation += 1;
+            state.entries.clear();
+            state.recency.clear();
+            state
+        })
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+/// Serves reads of the inner repository from a [`Cache`]. Writes go to the
+/// inner repository and invalidate the animal written.
+pub struct CachedRepository<R> {
+    inner: R,
+    cache: Arc<Cache>,
+}
+
+impl<R> CachedRepository<R> {
+    pub fn new(inner: R, cache: Arc<Cache>) -> Self {
+        Self { inner, cache }
+    }
+
+    pub fn cache(&self) -> &Arc<Cache> {
+        &self.cache
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+/// Runs the write, then invalidates the animal whether it succeeded or not.
+macro_rules! invalidating {
+    ($self:ident, $id:expr, $call:expr) => {{
+        let id = $id;
+        let result = $call.await;
+        $self.cache.invalidate(id);
+        result
+    }};
+}
+
+#[async_trait]
+impl<R: SnakeRepository> SnakeRepository for CachedRepository<R> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.cache.get(id.into(), self.inner.get_snake(id)).await
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        invalidating!(self, snake.id, self.inner.insert_snake(snake))
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        invalidating!(self, snake.id, self.inner.update_snake(snake))
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        invalidating!(self, snake.id, self.inner.upsert_snake(snake))
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        invalidating!(self, id, self.inner.delete_snake(id))
+    }
+}
+
+#[async_trait]
+impl<R: SlugRepository> SlugRepository for CachedRepository<R> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.cache.get(id.into(), self.inner.get_slug(id)).await
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        invalidating!(self, slug.id, self.inner.insert_slug(slug))
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        invalidating!(self, slug.id, self.inner.update_slug(slug))
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        invalidating!(self, slug.id, self.inner.upsert_slug(slug))
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        invalidating!(self, id, self.inner.delete_slug(id))
+    }
+}
+
+#[async_trait]
+impl<R: FrogRepository> FrogRepository for CachedRepository<R> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.cache.get(id.into(), self.inner.get_frog(id)).await
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        invalidating!(self, frog.id, self.inner.insert_frog(frog))
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        invalidating!(self, frog.id, self.inner.update_frog(frog))
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        invalidating!(self, frog.id, self.inner.upsert_frog(frog))
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        invalidating!(self, id, self.inner.delete_frog(id))
+    }
+}
+
+impl<R> RepositoryProvider for CachedRepository<R>
+where
+    R: SnakeRepository + SlugRepository + FrogRepository,
+{
+    type SnakeRepository = Self;
+    type SlugRepository = Self;
+    type FrogRepository = Self;
+
+    fn snake_repository(&self) -> &Self::SnakeRepository {
+        self
+    }
+    fn slug_repository(&self) -> &Self::SlugRepository {
+        self
+    }
+    fn frog_repository(&self) -> &Self::FrogRepository {
+        self
+    }
+}
+
+/// Starts transactions that read past the cache and invalidate the animals
+/// they wrote once they commit.
+#[async_trait]
+impl<R> TransactionProvider for CachedRepository<R>
+where
+    R: TransactionProvider + SnakeRepository + SlugRepository + FrogRepository,
+{
+    type Transaction = CachedTransaction<R::Transaction>;
+
+    async fn begin(&self) -> Result<Self::Transaction> {
+        Ok(CachedTransaction {
+            inner: self.inner.begin().await?,
+            cache: Arc::clone(&self.cache),
+            written: Mutex::default(),
+        })
+    }
+}
+
+/// A transaction started through a [`CachedRepository`]. Its reads go to the
+/// inner transaction, which alone sees its writes; the animals written are
+/// invalidated on commit.
+pub struct CachedTransaction<T> {
+    inner: T,
+    cache: Arc<Cache>,
+    written: Mutex<Vec<EntityID>>,
+}
+
+impl<T> CachedTransaction<T> {
+    fn record(&self, id: impl Into<EntityID>) {
+        let mut written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+        written.push(id.into());
+    }
+}
+
+/// Records the animal, then runs the write.
+macro_rules! recording {
+    ($self:ident, $id:expr, $call:expr) => {{
+        $self.record($id);
+        $call.await
+    }};
+}
+
+#[async_trait]
+impl<T: Transaction> SnakeRepository for CachedTransaction<T> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.inner.snake_repository().get_snake(id).await
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        recording!(
+            self,
+            snake.id,
+            self.inner.snake_repository().insert_snake(snake)
+        )
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        recording!(
+            self,
+            snake.id,
+            self.inner.snake_repository().update_snake(snake)
+        )
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        recording!(
+            self,
+            snake.id,
+            self.inner.snake_repository().upsert_snake(snake)
+        )
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        recording!(self, id, self.inner.snake_repository().delete_snake(id))
+    }
+}
+
+#[async_trait]
+impl<T: Transaction> SlugRepository for CachedTransaction<T> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.inner.slug_repository().get_slug(id).await
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        recording!(
+            self,
+            slug.id,
+            self.inner.slug_repository().insert_slug(slug)
+        )
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        recording!(
+            self,
+            slug.id,
+            self.inner.slug_repository().update_slug(slug)
+        )
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        recording!(
+            self,
+            slug.id,
+            self.inner.slug_repository().upsert_slug(slug)
+        )
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        recording!(self, id, self.inner.slug_repository().delete_slug(id))
+    }
+}
+
+#[async_trait]
+impl<T: Transaction> FrogRepository for CachedTransaction<T> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.inner.frog_repository().get_frog(id).await
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        recording!(
+            self,
+            frog.id,
+            self.inner.frog_repository().insert_frog(frog)
+        )
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        recording!(
+            self,
+            frog.id,
+            self.inner.frog_repository().update_frog(frog)
+        )
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        recording!(
+            self,
+            frog.id,
+            self.inner.frog_repository().upsert_frog(frog)
+        )
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        recording!(self, id, self.inner.frog_repository().delete_frog(id))
+    }
+}
+
+impl<T: Transaction> RepositoryProvider for CachedTransaction<T> {
+    type SnakeRepository = Self;
+    type SlugRepository = Self;
+    type FrogRepository = Self;
+
+    fn snake_repository(&self) -> &Self::SnakeRepository {
+        self
+    }
+    fn slug_repository(&self) -> &Self::SlugRepository {
+        self
+    }
+    fn frog_repository(&self) -> &Self::FrogRepository {
+        self
+    }
+}
+
+#[async_trait]
+impl<T: Transaction> Transaction for CachedTransaction<T> {
+    /// Commits, then invalidates what was written whether the commit
+    /// succeeded or not.
+    async fn commit(self) -> Result<()> {
+        let result = self.inner.commit().await;
+        let written = self
+            .written
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        for id in written {
+            self.cache.invalidate(id);
+        }
+        result
+    }
+    async fn rollback(self) -> Result<()> {
+        self.inner.rollback().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use database::{Database, DatabaseConfig, Pool, PoolConfig};
+    use domain::{Event, EventKind, MockFrogRepository, MockSnakeRepository};
+    use domain_fakes::fixtures;
+    use message_queue::{MessageQueue, MessageQueueConnection};
+
+    use super::*;
+    use crate::{outbox::encode_event, Repository};
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn frog(n: u64) -> Frog {
+        Frog {
+            id: FrogID::new(n).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
+
+    fn cached<R>(inner: R, config: CacheConfig) -> CachedRepository<R> {
+        CachedRepository::new(inner, Arc::new(Cache::new(config)))
+    }
+
+    #[tokio::test]
+    async fn test_hit() {
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().times(1).returning(|_| Ok(snake()));
+        let repository = cached(inner, CacheConfig::default());
+        for _ in 0..3 {
+            assert_eq!(repository.get_snake(snake().id).await.unwrap(), snake());
+        }
+        let stats = repository.cache().stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_write_invalidates() {
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().times(2).returning(|_| Ok(snake()));
+        inner.expect_update_snake().times(1).returning(|_| Ok(()));
+        let repository = cached(inner, CacheConfig::default());
+        repository.get_snake(snake().id).await.unwrap();
+        repository.update_snake(snake()).await.unwrap();
+        repository.get_snake(snake().id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_poisoned_cache_passes_reads_through() {
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().times(3).returning(|_| Ok(snake()));
+        let repository = cached(inner, CacheConfig::default());
+        repository.get_snake(snake().id).await.unwrap();
+        let cache = Arc::clone(repository.cache());
+        std::thread::spawn(move || {
+            let _state = cache.lock();
+            panic!("poisoning the cache lock");
+        })
+        .join()
+        .unwrap_err();
+        for _ in 0..2 {
+            assert_eq!(repository.get_snake(snake().id).await.unwrap(), snake());
+        }
+        assert_eq!(repository.cache().stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().times(2).returning(|_| Ok(snake()));
+        let repository = cached(
+            inner,
+            CacheConfig {
+                ttl: Duration::from_millis(10),
+                ..CacheConfig::default()
+            },
+        );
+        repository.get_snake(snake().id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        repository.get_snake(snake().id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching() {
+        let mut inner = MockSnakeRepository::new();
+        inner
+            .expect_get_snake()
+            .times(1)
+            .returning(|id| Err(Error::NotFound(id.into())));
+        inner.expect_insert_snake().times(1).returning(|_| Ok(()));
+        let repository = cached(inner, CacheConfig::default());
+        for _ in 0..2 {
+            assert!(matches!(
+                repository.get_snake(snake().id).await,
+                Err(Error::NotFound(EntityID::Snake(id))) if id == snake().id
+            ));
+        }
+        repository.insert_snake(snake()).await.unwrap();
+        assert_eq!(repository.cache().stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_not_cached() {
+        let mut inner = MockSnakeRepository::new();
+        inner
+            .expect_get_snake()
+            .times(2)
+            .returning(|_| Err(Error::unavailable("database down")));
+        let repository = cached(inner, CacheConfig::default());
+        for _ in 0..2 {
+            assert!(matches!(
+                repository.get_snake(snake().id).await,
+                Err(Error::Unavailable(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let mut inner = MockFrogRepository::new();
+        inner.expect_get_frog().returning(|id| Ok(frog(id.get())));
+        let repository = cached(
+            inner,
+            CacheConfig {
+                capacity: 2,
+                ..CacheConfig::default()
+            },
+        );
+        for n in [1, 2, 1, 3] {
+            repository.get_frog(FrogID::new(n).unwrap()).await.unwrap();
+        }
+        // Frog 2 was the least recently used when frog 3 came in.
+        let stats = repository.cache().stats();
+        assert_eq!((stats.entries, stats.evictions, stats.misses), (2, 1, 3));
+        repository.get_frog(FrogID::new(1).unwrap()).await.unwrap();
+        repository.get_frog(FrogID::new(2).unwrap()).await.unwrap();
+        assert_eq!(repository.cache().stats().misses, 4);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_from_events() {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().times(2).returning(|_| Ok(snake()));
+        let repository = cached(inner, CacheConfig::default());
+        repository.get_snake(snake().id).await.unwrap();
+
+        let subscription = message_queue.subscribe("snake").await.unwrap();
+        let cache = Arc::clone(repository.cache());
+        let invalidator = tokio::spawn(async move { cache.invalidate_from(subscription).await });
+        let event = Event::now(EventKind::SnakeEaten { snake: snake().id });
+        message_queue
+            .publish("snake", encode_event(&event).unwrap())
+            .await
+            .unwrap();
+        drop(message_queue);
+        invalidator.await.unwrap();
+        assert_eq!(repository.cache().stats().invalidations, 1);
+        repository.get_snake(snake().id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transaction_reads_its_own_write() {
+        let database =
+            Database::new(Pool::open(&DatabaseConfig::Memory, PoolConfig::default()).unwrap());
+        let repository = cached(Repository::new(&database), CacheConfig::default());
+        let seed = repository.inner().begin().await.unwrap();
+        seed.snake_repository()
+            .insert_snake(fixtures::snake())
+            .await
+            .unwrap();
+        seed.slug_repository()
+            .insert_slug(fixtures::slug())
+            .await
+            .unwrap();
+        seed.frog_repository()
+            .insert_frog(fixtures::frog())
+            .await
+            .unwrap();
+        seed.commit().await.unwrap();
+        let stored = fixtures::snake();
+        assert_eq!(repository.get_snake(stored.id).await.unwrap(), stored);
+
+        let slug = Slug {
+            id: SlugID::new(9).unwrap(),
+            ..fixtures::slug()
+        };
+        let eaten = Snake {
+            eaten_by: slug.id,
+            ..stored.clone()
+        };
+        let tx = repository.begin().await.unwrap();
+        tx.insert_slug(slug).await.unwrap();
+        tx.update_snake(eaten.clone()).await.unwrap();
+        assert_eq!(tx.get_snake(stored.id).await.unwrap(), eaten);
+        // Uncommitted writes are not seen outside the transaction.
+        assert_eq!(repository.get_snake(stored.id).await.unwrap(), stored);
+        tx.commit().await.unwrap();
+        assert_eq!(repository.get_snake(stored.id).await.unwrap(), eaten);
+        assert_eq!(repository.cache().stats().invalidations, 1);
+    }
+}
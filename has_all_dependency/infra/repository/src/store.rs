@@ -0,0 +1,520 @@
+use database::{Database, FrogRow, Row, SlugRow, SnakeRow, Transaction};
+use domain::{
+    EntityID, Error, Event, EventKind, Frog, FrogID, Result, Slug, SlugID, Snake, SnakeID,
+};
+
+use message_queue::{correlate_sync, correlation_id};
+
+use crate::outbox;
+
+/// Maps a domain entity onto its database row and its writes onto events.
+pub(crate) trait Entity: Clone + Send + 'static {
+    type ID: Copy + Into<EntityID> + Into<u64> + Send + 'static;
+    type Row: Row;
+
+    fn id(&self) -> Self::ID;
+    fn eaten_by(&self) -> EntityID;
+    fn to_row(&self) -> Self::Row;
+    fn from_row(row: Self::Row) -> Result<Self>;
+    fn registered(&self) -> EventKind;
+    fn eaten(id: Self::ID) -> EventKind;
+
+    /// The event for `before` becoming `self`, if the predator changed.
+    fn changed_from(&self, before: &Self) -> Option<EventKind> {
+        (self.eaten_by() != before.eaten_by()).then(|| EventKind::EatenByChanged {
+            prey: self.id().into(),
+            from: before.eaten_by(),
+            to: self.eaten_by(),
+        })
+    }
+}
+
+impl Entity for Snake {
+    type ID = SnakeID;
+    type Row = SnakeRow;
+
+    fn id(&self) -> Self::ID {
+        self.id
+    }
+    fn eaten_by(&self) -> EntityID {
+        self.eaten_by.into()
+    }
+    fn to_row(&self) -> Self::Row {
+        SnakeRow {
+            id: self.id.get(),
+            eaten_by: self.eaten_by.get(),
+        }
+    }
+    fn from_row(row: Self::Row) -> Result<Self> {
+        Ok(Self {
+            id: SnakeID::new(row.id).map_err(Error::internal)?,
+            eaten_by: SlugID::new(row.eaten_by).map_err(Error::internal)?,
+        })
+    }
+    fn registered(&self) -> EventKind {
+        EventKind::SnakeRegistered {
+            snake: self.id,
+            eaten_by: self.eaten_by,
+        }
+    }
+    fn eaten(id: Self::ID) -> EventKind {
+        EventKind::SnakeEaten { snake: id }
+    }
+}
+
+impl Entity for Slug {
+    type ID = SlugID;
+    type Row = SlugRow;
+
+    fn id(&self) -> Self::ID {
+        self.id
+    }
+    fn eaten_by(&self) -> EntityID {
+        self.eaten_by.into()
+    }
+    fn to_row(&self) -> Self::Row {
+        SlugRow {
+            id: self.id.get(),
+            eaten_by: self.eaten_by.get(),
+        }
+    }
+    fn from_row(row: Self::Row) -> Result<Self> {
+        Ok(Self {
+            id: SlugID::new(row.id).map_err(Error::internal)?,
+            eaten_by: FrogID::new(row.eaten_by).map_err(Error::internal)?,
+        })
+    }
+    fn registered(&self) -> EventKind {
+        EventKind::SlugRegistered {
+            slug: self.id,
+            eaten_by: self.eaten_by,
+        }
+    }
+    fn eaten(id: Self::ID) -> EventKind {
+        EventKind::SlugEaten { slug: id }
+    }
+}
+
+impl Entity for Frog {
+    type ID = FrogID;
+    type Row = FrogRow;
+
+    fn id(&self) -> Self::ID {
+        self.id
+    }
+    fn eaten_by(&self) -> EntityID {
+        self.eaten_by.into()
+    }
+    fn to_row(&self) -> Self::Row {
+        FrogRow {
+            id: self.id.get(),
+            eaten_by: self.eaten_by.get(),
+        }
+    }
+    fn from_row(row: Self::Row) -> Result<Self> {
+        Ok(Self {
+            id: FrogID::new(row.id).map_err(Error::internal)?,
+            eaten_by: SnakeID::new(row.eaten_by).map_err(Error::internal)?,
+        })
+    }
+    fn registered(&self) -> EventKind {
+        EventKind::FrogRegistered {
+            frog: self.id,
+            eaten_by: self.eaten_by,
+        }
+    }
+    fn eaten(id: Self::ID) -> EventKind {
+        EventKind::FrogEaten { frog: id }
+    }
+}
+
+/// Where repository calls go: through a pooled connection each, or into a
+/// transaction.
+#[derive(Clone)]
+pub(crate) enum Scope<'a> {
+    Database(&'a Database),
+    Transaction(Transaction),
+}
+
+macro_rules! run {
+    ($scope:expr, $id:expr, $conn:ident => $call:expr) => {
+        match $scope {
+            Scope::Database(database) => {
+                let conn = database.conn().await.map_err(|err| error(err, $id))?;
+                conn.run(move |$conn| $call).await
+            }
+            Scope::Transaction(transaction) => transaction.run(move |$conn| $call).await,
+        }
+        .map_err(|err| error(err, $id))
+    };
+}
+
+pub(crate) async fn get<E: Entity>(scope: &Scope<'_>, id: E::ID) -> Result<E> {
+    match run!(scope, id, conn => conn.get::<E::Row>(id.into()))? {
+        Some(row) => E::from_row(row),
+        None => Err(Error::NotFound(id.into())),
+    }
+}
+
+pub(crate) async fn insert<E: Entity>(scope: &Scope<'_>, entity: &E) -> Result<()> {
+    let entity = entity.clone();
+    write(scope, move |transaction| {
+        transaction
+            .insert(entity.to_row())
+            .map_err(|err| error(err, entity.id()))?;
+        Ok(Some(entity.registered()))
+    })
+    .await
+}
+
+pub(crate) async fn update<E: Entity>(scope: &Scope<'_>, entity: &E) -> Result<()> {
+    let entity = entity.clone();
+    write(scope, move |transaction| {
+        let before = read::<E>(transaction, entity.id())?;
+        transaction
+            .update(entity.to_row())
+            .map_err(|err| error(err, entity.id()))?;
+        Ok(before.and_then(|before| entity.changed_from(&before)))
+    })
+    .await
+}
+
+pub(crate) async fn upsert<E: Entity>(scope: &Scope<'_>, entity: &E) -> Result<()> {
+    let entity = entity.clone();
+    write(scope, move |transaction| {
+        let before = read::<E>(transaction, entity.id())?;
+        transaction
+            .upsert(entity.to_row())
+            .map_err(|err| error(err, entity.id()))?;
+        Ok(match before {
+            Some(before) => entity.changed_from(&before),
+            None => Some(entity.registered()),
+        })
+    })
+    .await
+}
+
+pub(crate) async fn delete<E: Entity>(scope: &Scope<'_>, id: E::ID) -> Result<()> {
+    write(scope, move |transaction| {
+        transaction
+            .delete::<E::Row>(id.into())
+            .map_err(|err| error(err, id))?;
+        Ok(Some(E::eaten(id)))
+    })
+    .await
+}
+
+/// Runs the write in the scope's transaction, or else in one of its own, and
+/// adds the event it returns to the outbox of the same transaction.
+async fn write(
+    scope: &Scope<'_>,
+    f: impl FnOnce(&Transaction) -> Result<Option<EventKind>> + Send + 'static,
+) -> Result<()> {
+    match scope {
+        Scope::Transaction(transaction) => {
+            run(transaction, |transaction| record(transaction, f)).await
+        }
+        Scope::Database(database) => {
+            let transaction = begin(database).await?;
+            run(&transaction, |transaction| record(transaction, f)).await?;
+            commit(transaction).await
+        }
+    }
+}
+
+/// Runs `f` in the transaction, see [`Transaction::run`], under the
+/// correlation ID of the caller, for the events it enqueues.
+async fn run<T: Send + 'static>(
+    transaction: &Transaction,
+    f: impl FnOnce(&Transaction) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let correlation = correlation_id();
+    transaction
+        .run(move |transaction| match correlation {
+            Some(id) => correlate_sync(id, || f(transaction)),
+            None => f(transaction),
+        })
+        .await
+}
+
+fn record(
+    transaction: &Transaction,
+    f: impl FnOnce(&Transaction) -> Result<Option<EventKind>>,
+) -> Result<()> {
+    if let Some(kind) = f(transaction)? {
+        outbox::enqueue(transaction, &Event::now(kind))?;
+    }
+    Ok(())
+}
+
+fn read<E: Entity>(transaction: &Transaction, id: E::ID) -> Result<Option<E>> {
+    transaction
+        .get::<E::Row>(id.into())
+        .map_err(|err| error(err, id))?
+        .map(E::from_row)
+        .transpose()
+}
+
+pub(crate) async fn begin(database: &Database) -> Result<Transaction> {
+    database.begin().await.map_err(unclassified)
+}
+
+pub(crate) async fn commit(transaction: Transaction) -> Result<()> {
+    transaction.commit().await.map_err(|err| {
+        let id = match &err {
+            database::Error::Conflict { table, id } | database::Error::NotFound { table, id } => {
+                entity_id(table, *id)
+            }
+            _ => None,
+        };
+        match id {
+            Some(id) => error(err, id),
+            None => unclassified(err),
+        }
+    })
+}
+
+pub(crate) async fn rollback(transaction: Transaction) -> Result<()> {
+    transaction.rollback().await.map_err(unclassified)
+}
+
+fn entity_id(table: &str, id: u64) -> Option<EntityID> {
+    match table {
+        SnakeRow::TABLE => SnakeID::new(id).ok().map(Into::into),
+        SlugRow::TABLE => SlugID::new(id).ok().map(Into::into),
+        FrogRow::TABLE => FrogID::new(id).ok().map(Into::into),
+        _ => None,
+    }
+}
+
+fn error(err: database::Error, id: impl Into<EntityID>) -> Error {
+    match err {
+        database::Error::Conflict { .. } => Error::Conflict(id.into()),
+        database::Error::NotFound { .. } => Error::NotFound(id.into()),
+        err => unclassified(err),
+    }
+}
+
+pub(crate) fn unclassified(err: database::Error) -> Error {
+    if let database::Error::ForeignKey = err {
+        // An animal eaten by one that does not exist, or eating a deleted one.
+        Error::Invalid(err.to_string())
+    } else if err.is_transient() {
+        Error::unavailable(err)
+    } else {
+        Error::internal(err)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{
+        env, fs,
+        path::PathBuf,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use database::{DatabaseConfig, Migrator, Pool, PoolConfig};
+    use message_queue::{correlate, Codec, Envelope, MessageID, Text};
+
+    use super::*;
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn database() -> Database {
+        Database::new(Pool::open(&DatabaseConfig::Memory, PoolConfig::default()).unwrap())
+    }
+
+    /// A SQLite file, removed again on drop.
+    pub(crate) struct TempFile(PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// A migrated database in a SQLite file of its own, so tests running in
+    /// parallel do not see each other's rows. The file lives as long as the
+    /// returned [`TempFile`].
+    pub(crate) async fn sqlite_database() -> (Database, TempFile) {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "repository-{}-{}.db",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        _ = fs::remove_file(&path);
+        let pool =
+            Pool::open(&DatabaseConfig::Sqlite(path.clone()), PoolConfig::default()).unwrap();
+        let database = Database::new(pool);
+        Migrator::default()
+            .migrate(&database.conn().await.unwrap())
+            .unwrap();
+        (database, TempFile(path))
+    }
+
+    /// Stores a snake, a slug and a frog with each of the IDs, each eaten by
+    /// the next in the food chain with the same ID, for the animals under test
+    /// to be eaten by. No events are recorded for them.
+    pub(crate) async fn seed(database: &Database, ids: impl IntoIterator<Item = u64>) {
+        let transaction = database.begin().await.unwrap();
+        for id in ids {
+            transaction.insert(SnakeRow { id, eaten_by: id }).unwrap();
+            transaction.insert(SlugRow { id, eaten_by: id }).unwrap();
+            transaction.insert(FrogRow { id, eaten_by: id }).unwrap();
+        }
+        transaction.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let database = database();
+        seed(&database, [2, 3]).await;
+        insert(&Scope::Database(&database), &snake()).await.unwrap();
+        assert_eq!(
+            get::<Snake>(&Scope::Database(&database), snake().id)
+                .await
+                .unwrap(),
+            snake()
+        );
+        assert!(matches!(
+            insert(&Scope::Database(&database), &snake()).await,
+            Err(Error::Conflict(EntityID::Snake(id))) if id == snake().id
+        ));
+        delete::<Snake>(&Scope::Database(&database), snake().id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            get::<Snake>(&Scope::Database(&database), snake().id).await,
+            Err(Error::NotFound(EntityID::Snake(id))) if id == snake().id
+        ));
+    }
+
+    /// The events waiting in the outbox, checking each is on its topic.
+    async fn events(database: &Database) -> Vec<EventKind> {
+        let records = database.conn().await.unwrap().unsent(100).unwrap();
+        records
+            .into_iter()
+            .map(|record| {
+                let envelope = Envelope::from_bytes(&record.payload).unwrap();
+                assert_eq!(envelope.content_type, Text::CONTENT_TYPE);
+                let event = envelope
+                    .decode::<String>()
+                    .unwrap()
+                    .parse::<Event>()
+                    .unwrap();
+                assert_eq!(record.topic, event.topic());
+                assert_eq!(envelope.timestamp, event.occurred_at);
+                event.kind
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_events_are_correlated() {
+        // A SQLite transaction runs on a blocking thread, out of reach of
+        // the caller's correlation.
+        let (sqlite, _file) = sqlite_database().await;
+        for database in [database(), sqlite] {
+            seed(&database, [2, 3]).await;
+            let request = MessageID::new();
+            correlate(request, insert(&Scope::Database(&database), &snake()))
+                .await
+                .unwrap();
+            let records = database.conn().await.unwrap().unsent(100).unwrap();
+            let envelope = Envelope::from_bytes(&records[0].payload).unwrap();
+            assert_eq!(envelope.correlation_id, request);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_record_events() {
+        let database = database();
+        seed(&database, [2, 3]).await;
+        let scope = Scope::Database(&database);
+        let moved = Snake {
+            eaten_by: SlugID::new(3).unwrap(),
+            ..snake()
+        };
+        insert(&scope, &snake()).await.unwrap();
+        update(&scope, &snake()).await.unwrap();
+        upsert(&scope, &moved).await.unwrap();
+        delete::<Snake>(&scope, snake().id).await.unwrap();
+        assert!(update(&scope, &snake()).await.is_err());
+        upsert(&scope, &snake()).await.unwrap();
+        assert_eq!(
+            events(&database).await,
+            [
+                EventKind::SnakeRegistered {
+                    snake: snake().id,
+                    eaten_by: snake().eaten_by,
+                },
+                EventKind::EatenByChanged {
+                    prey: snake().id.into(),
+                    from: snake().eaten_by.into(),
+                    to: moved.eaten_by.into(),
+                },
+                EventKind::SnakeEaten { snake: snake().id },
+                EventKind::SnakeRegistered {
+                    snake: snake().id,
+                    eaten_by: snake().eaten_by,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_events_follow_transaction() {
+        let database = database();
+        seed(&database, [2, 3]).await;
+        let transaction = begin(&database).await.unwrap();
+        let scope = Scope::Transaction(transaction.clone());
+        insert(&scope, &snake()).await.unwrap();
+        rollback(transaction).await.unwrap();
+        assert!(events(&database).await.is_empty());
+        let transaction = begin(&database).await.unwrap();
+        let scope = Scope::Transaction(transaction.clone());
+        insert(&scope, &snake()).await.unwrap();
+        delete::<Snake>(&scope, snake().id).await.unwrap();
+        assert!(events(&database).await.is_empty());
+        commit(transaction).await.unwrap();
+        assert_eq!(events(&database).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_row() {
+        let database = database();
+        let transaction = database.begin().await.unwrap();
+        transaction.insert(SnakeRow { id: 1, eaten_by: 0 }).unwrap();
+        transaction.insert(SlugRow { id: 0, eaten_by: 0 }).unwrap();
+        transaction.insert(FrogRow { id: 0, eaten_by: 1 }).unwrap();
+        transaction.commit().await.unwrap();
+        assert!(matches!(
+            get::<Snake>(&Scope::Database(&database), snake().id).await,
+            Err(Error::Internal(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pool_exhausted() {
+        let config = PoolConfig {
+            max_size: 1,
+            checkout_timeout: Duration::from_millis(10),
+            ..PoolConfig::default()
+        };
+        let database = Database::new(Pool::open(&DatabaseConfig::Memory, config).unwrap());
+        let _conn = database.conn().await.unwrap();
+        assert!(matches!(
+            get::<Snake>(&Scope::Database(&database), snake().id).await,
+            Err(Error::Unavailable(_))
+        ));
+    }
+}
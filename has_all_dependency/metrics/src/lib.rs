@@ -0,0 +1,5 @@
+mod decorator;
+mod registry;
+
+pub use decorator::Metrics;
+pub use registry::{Registry, BUCKETS};
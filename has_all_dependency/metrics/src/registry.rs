@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use domain::Error;
+
+/// The upper bounds, in seconds, of the latency histogram buckets. Calls to
+/// the in-process backends take well under a millisecond, so the buckets
+/// start lower than Prometheus's defaults.
+pub const BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// The layer and method a call was made to, e.g. `("repository", "get_snake")`.
+type Key = (&'static str, &'static str);
+
+#[derive(Debug, Default)]
+struct Method {
+    calls: u64,
+    /// Failed calls by the kind of error, see [`error_kind`].
+    errors: BTreeMap<&'static str, u64>,
+    /// Calls per bucket of [`BUCKETS`], not cumulative; the last counts the
+    /// calls slower than every bound.
+    buckets: [u64; BUCKETS.len() + 1],
+    seconds: f64,
+}
+
+/// Call counts, error counts and latency histograms of the instrumented
+/// methods, shared by the [`Metrics`](crate::Metrics) decorators that record
+/// them.
+#[derive(Debug, Default)]
+pub struct Registry {
+    methods: Mutex<BTreeMap<Key, Method>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(
+        &self,
+        layer: &'static str,
+        method: &'static str,
+        elapsed: Duration,
+        error: Option<&Error>,
+    ) {
+        let mut methods = self.lock();
+        let metrics = methods.entry((layer, method)).or_default();
+        metrics.calls += 1;
+        if let Some(err) = error {
+            *metrics.errors.entry(error_kind(err)).or_default() += 1;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+        metrics.buckets[bucket] += 1;
+        metrics.seconds += seconds;
+    }
+
+    /// The calls made to the method so far.
+    pub fn calls(&self, layer: &str, method: &str) -> u64 {
+        self.lock()
+            .get(&(layer, method))
+            .map_or(0, |metrics| metrics.calls)
+    }
+
+    /// The failed calls made to the method so far.
+    pub fn errors(&self, layer: &str, method: &str) -> u64 {
+        self.lock()
+            .get(&(layer, method))
+            .map_or(0, |metrics| metrics.errors.values().sum())
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let methods = self.lock();
+        let mut out = String::new();
+        let labels = |(layer, method): &Key| format!("layer=\"{layer}\",method=\"{method}\"");
+
+        out.push_str("# HELP food_chain_calls_total Calls by layer and method.\n");
+        out.push_str("# TYPE food_chain_calls_total counter\n");
+        for (key, metrics) in methods.iter() {
+            _ = writeln!(
+                out,
+                "food_chain_calls_total{{{}}} {}",
+                labels(key),
+                metrics.calls
+            );
+        }
+
+        out.push_str(
+            "# HELP food_chain_errors_total Failed calls by layer, method and kind of error.\n",
+        );
+        out.push_str("# TYPE food_chain_errors_total counter\n");
+        for (key, metrics) in methods.iter() {
+            for (kind, errors) in &metrics.errors {
+                _ = writeln!(
+                    out,
+                    "food_chain_errors_total{{{},error=\"{kind}\"}} {errors}",
+                    labels(key)
+                );
+            }
+        }
+
+        out.push_str("# HELP food_chain_call_duration_seconds Call latency by layer and method.\n");
+        out.push_str("# TYPE food_chain_call_duration_seconds histogram\n");
+        for (key, metrics) in methods.iter() {
+            let labels = labels(key);
+            let mut cumulative = 0;
+            for (bound, calls) in BUCKETS.iter().zip(metrics.buckets) {
+                cumulative += calls;
+                _ = writeln!(
+                    out,
+                    "food_chain_call_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+                );
+            }
+            _ = writeln!(
+                out,
+                "food_chain_call_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                metrics.calls
+            );
+            _ = writeln!(
+                out,
+                "food_chain_call_duration_seconds_sum{{{labels}}} {}",
+                metrics.seconds
+            );
+            _ = writeln!(
+                out,
+                "food_chain_call_duration_seconds_count{{{labels}}} {}",
+                metrics.calls
+            );
+        }
+        out
+    }
+
+    /// Writes the metrics to `path` for a textfile collector, such as node
+    /// exporter's, to scrape. The file is replaced whole, so a scrape never
+    /// sees it half written.
+    pub fn write_textfile(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.encode())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// A panic while recording leaves at most one call half counted, so the
+    /// metrics are kept, and recorded on, rather than lost with the lock.
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<Key, Method>> {
+        self.methods.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The `error` label of a failed call.
+fn error_kind(err: &Error) -> &'static str {
+    match err.root() {
+        Error::NotFound(_) => "not_found",
+        Error::Conflict(_) => "conflict",
+        Error::Unavailable(_) => "unavailable",
+        Error::Invalid(_) => "invalid",
+        Error::Internal(_) | Error::Context { .. } => "internal",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use domain::SnakeID;
+
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let registry = Registry::new();
+        let not_found = Error::NotFound(SnakeID::new(1).unwrap().into());
+        registry.record("repository", "get_snake", Duration::from_micros(50), None);
+        registry.record(
+            "repository",
+            "get_snake",
+            Duration::from_millis(3),
+            Some(&not_found.context("getting snake 1")),
+        );
+        registry.record("service", "get_frog", Duration::from_secs(2), None);
+        let encoded = registry.encode();
+        let lines: Vec<_> = encoded.lines().collect();
+        for line in [
+            r#"food_chain_calls_total{layer="repository",method="get_snake"} 2"#,
+            r#"food_chain_calls_total{layer="service",method="get_frog"} 1"#,
+            r#"food_chain_errors_total{layer="repository",method="get_snake",error="not_found"} 1"#,
+            r#"food_chain_call_duration_seconds_bucket{layer="repository",method="get_snake",le="0.0001"} 1"#,
+            r#"food_chain_call_duration_seconds_bucket{layer="repository",method="get_snake",le="0.0025"} 1"#,
+            r#"food_chain_call_duration_seconds_bucket{layer="repository",method="get_snake",le="0.005"} 2"#,
+            r#"food_chain_call_duration_seconds_bucket{layer="repository",method="get_snake",le="+Inf"} 2"#,
+            r#"food_chain_call_duration_seconds_count{layer="repository",method="get_snake"} 2"#,
+            r#"food_chain_call_duration_seconds_bucket{layer="service",method="get_frog",le="1"} 0"#,
+            r#"food_chain_call_duration_seconds_bucket{layer="service",method="get_frog",le="+Inf"} 1"#,
+            r#"food_chain_call_duration_seconds_sum{layer="service",method="get_frog"} 2"#,
+        ] {
+            assert!(lines.contains(&line), "{line} missing from\n{encoded}");
+        }
+        assert_eq!(registry.calls("repository", "get_snake"), 2);
+        assert_eq!(registry.errors("repository", "get_snake"), 1);
+        assert_eq!(registry.calls("repository", "get_slug"), 0);
+    }
+
+    #[test]
+    fn test_empty() {
+        let encoded = Registry::new().encode();
+        assert!(encoded.lines().all(|line| line.starts_with('#')));
+    }
+
+    #[test]
+    fn test_poisoned() {
+        let registry = Registry::new();
+        registry.record("use_case", "get_snake", Duration::ZERO, None);
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _methods = registry.lock();
+                    panic!("poisoning the metrics lock");
+                })
+                .join()
+                .unwrap_err();
+        });
+        registry.record("use_case", "get_snake", Duration::ZERO, None);
+        assert_eq!(registry.calls("use_case", "get_snake"), 2);
+        assert!(registry.encode().contains("food_chain_calls_total"));
+    }
+
+    #[test]
+    fn test_write_textfile() {
+        let path = env::temp_dir().join(format!("metrics-{}.prom", std::process::id()));
+        let registry = Registry::new();
+        registry.record("use_case", "get_snake", Duration::ZERO, None);
+        registry.write_textfile(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), registry.encode());
+        fs::remove_file(&path).unwrap();
+    }
+}
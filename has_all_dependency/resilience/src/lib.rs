@@ -0,0 +1,8 @@
+mod breaker;
+mod retry;
+
+pub use breaker::{
+    Breaker, BreakerConfig, BreakerStats, BreakerTransaction, CircuitBreaker, CircuitOpen,
+    CircuitState,
+};
+pub use retry::{is_retryable, Retry, RetryPolicy};
@@ -1,9 +1,9 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use domain::{
-    Frog, FrogID, FrogService, FrogUseCase, ServiceProvider, Slug, SlugID, SlugService,
+    Frog, FrogID, FrogService, FrogUseCase, Result, ServiceProvider, Slug, SlugID, SlugService,
     SlugUseCase, Snake, SnakeID, SnakeService, SnakeUseCase, UseCaseProvider,
 };
+use tracing::instrument;
 
 pub struct Service<'ucp, UCP: UseCaseProvider> {
     snake_use_case: &'ucp UCP::SnakeUseCase,
@@ -39,6 +39,7 @@ impl<'ucp, UCP: UseCaseProvider> ServiceProvider for Service<'ucp, UCP> {
 
 #[async_trait]
 impl<'ucp, UCP: UseCaseProvider> SnakeService for Service<'ucp, UCP> {
+    #[instrument(skip_all, fields(slug = %slug_id))]
     async fn get_snake_eating_frog_eating_slug(&self, slug_id: SlugID) -> Result<Snake> {
         let frog = self.frog_use_case.get_frog_eating_slug(slug_id).await?;
         self.snake_use_case.get_snake_eating_frog(frog.id).await
@@ -47,6 +48,7 @@ impl<'ucp, UCP: UseCaseProvider> SnakeService for Service<'ucp, UCP> {
 
 #[async_trait]
 impl<'ucp, UCP: UseCaseProvider> SlugService for Service<'ucp, UCP> {
+    #[instrument(skip_all, fields(frog = %frog_id))]
     async fn get_slug_eating_snake_eating_frog(&self, frog_id: FrogID) -> Result<Slug> {
         let snake = self.snake_use_case.get_snake_eating_frog(frog_id).await?;
         self.slug_use_case.get_slug_eating_snake(snake.id).await
@@ -55,6 +57,7 @@ impl<'ucp, UCP: UseCaseProvider> SlugService for Service<'ucp, UCP> {
 
 #[async_trait]
 impl<'ucp, UCP: UseCaseProvider> FrogService for Service<'ucp, UCP> {
+    #[instrument(skip_all, fields(snake = %snake_id))]
     async fn get_frog_eating_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Frog> {
         let slug = self.slug_use_case.get_slug_eating_snake(snake_id).await?;
         self.frog_use_case.get_frog_eating_slug(slug.id).await
@@ -64,19 +67,44 @@ impl<'ucp, UCP: UseCaseProvider> FrogService for Service<'ucp, UCP> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use domain::{MockFrogUseCase, MockSlugUseCase, MockSnakeUseCase, MockUseCaseProvider};
+    use domain::{
+        EntityID, Error, MockFrogUseCase, MockSlugUseCase, MockSnakeUseCase, MockUseCaseProvider,
+    };
+    use domain_fakes::{fixtures, FakeRepositoryProvider};
+    use use_case::UseCsae;
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
 
     #[tokio::test]
     async fn test_get_snake_eating_frog_eating_slug() {
         let mut snake_use_case = MockSnakeUseCase::new();
         snake_use_case
             .expect_get_snake_eating_frog()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let slug_use_case = MockSlugUseCase::new();
         let mut frog_use_case = MockFrogUseCase::new();
         frog_use_case
             .expect_get_frog_eating_slug()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|_| Ok(frog()));
         let mut use_case = MockUseCaseProvider::new();
         use_case
             .expect_snake_use_case()
@@ -84,9 +112,13 @@ mod test {
         use_case.expect_slug_use_case().return_const(slug_use_case);
         use_case.expect_frog_use_case().return_const(frog_use_case);
         let service = Service::new(&use_case);
-        _ = service
-            .get_snake_eating_frog_eating_slug(SlugID::default())
-            .await;
+        assert_eq!(
+            service
+                .get_snake_eating_frog_eating_slug(slug().id)
+                .await
+                .unwrap(),
+            snake()
+        );
     }
 
     #[tokio::test]
@@ -94,11 +126,11 @@ mod test {
         let mut snake_use_case = MockSnakeUseCase::new();
         snake_use_case
             .expect_get_snake_eating_frog()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let mut slug_use_case = MockSlugUseCase::new();
         slug_use_case
             .expect_get_slug_eating_snake()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|_| Ok(slug()));
         let frog_use_case = MockFrogUseCase::new();
         let mut use_case = MockUseCaseProvider::new();
         use_case
@@ -107,9 +139,13 @@ mod test {
         use_case.expect_slug_use_case().return_const(slug_use_case);
         use_case.expect_frog_use_case().return_const(frog_use_case);
         let service = Service::new(&use_case);
-        _ = service
-            .get_slug_eating_snake_eating_frog(FrogID::default())
-            .await;
+        assert_eq!(
+            service
+                .get_slug_eating_snake_eating_frog(frog().id)
+                .await
+                .unwrap(),
+            slug()
+        );
     }
 
     #[tokio::test]
@@ -118,11 +154,11 @@ mod test {
         let mut slug_use_case = MockSlugUseCase::new();
         slug_use_case
             .expect_get_slug_eating_snake()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|_| Ok(slug()));
         let mut frog_use_case = MockFrogUseCase::new();
         frog_use_case
             .expect_get_frog_eating_slug()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|_| Ok(frog()));
         let mut use_case = MockUseCaseProvider::new();
         use_case
             .expect_snake_use_case()
@@ -130,8 +166,59 @@ mod test {
         use_case.expect_slug_use_case().return_const(slug_use_case);
         use_case.expect_frog_use_case().return_const(frog_use_case);
         let service = Service::new(&use_case);
-        _ = service
-            .get_frog_eating_slug_eating_snake(SnakeID::default())
-            .await;
+        assert_eq!(
+            service
+                .get_frog_eating_slug_eating_snake(snake().id)
+                .await
+                .unwrap(),
+            frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_eating_eating_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCsae::new(&repository);
+        let service = Service::new(&use_case);
+        assert_eq!(
+            service
+                .snake_service()
+                .get_snake_eating_frog_eating_slug(fixtures::slug().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        assert_eq!(
+            service
+                .slug_service()
+                .get_slug_eating_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::slug()
+        );
+        assert_eq!(
+            service
+                .frog_service()
+                .get_frog_eating_slug_eating_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug_eating_snake_broken_chain() {
+        // Snake 1 is eaten by slug 2, which is not stored.
+        let repository = FakeRepositoryProvider::new()
+            .with_snake(fixtures::snake())
+            .with_frog(fixtures::frog());
+        let use_case = UseCsae::new(&repository);
+        let service = Service::new(&use_case);
+        let err = service
+            .frog_service()
+            .get_frog_eating_slug_eating_snake(fixtures::snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == fixtures::slug().id));
     }
 }
@@ -1,19 +1,22 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use domain::{
-    Frog, FrogID, FrogRepository, FrogUseCase, RepositoryProvider, Slug, SlugID, SlugRepository,
-    SlugUseCase, Snake, SnakeID, SnakeRepository, SnakeUseCase, UseCaseProvider,
+    FoodChainUseCase, Frog, FrogID, FrogRepository, FrogUseCase, RepositoryProvider, Result, Slug,
+    SlugID, SlugRepository, SlugUseCase, Snake, SnakeID, SnakeRepository, SnakeUseCase,
+    Transaction, TransactionProvider, UseCaseProvider,
 };
+use tracing::instrument;
 
-pub struct UseCsae<'r, RP: RepositoryProvider> {
+pub struct UseCsae<'r, RP: TransactionProvider> {
+    repository: &'r RP,
     snake_repository: &'r RP::SnakeRepository,
     slug_repository: &'r RP::SlugRepository,
     frog_repository: &'r RP::FrogRepository,
 }
 
-impl<'r, RP: RepositoryProvider> UseCsae<'r, RP> {
+impl<'r, RP: TransactionProvider> UseCsae<'r, RP> {
     pub fn new(repository: &'r RP) -> Self {
         Self {
+            repository,
             snake_repository: repository.snake_repository(),
             slug_repository: repository.slug_repository(),
             frog_repository: repository.frog_repository(),
@@ -21,10 +24,11 @@ impl<'r, RP: RepositoryProvider> UseCsae<'r, RP> {
     }
 }
 
-impl<'r, RP: RepositoryProvider> UseCaseProvider for UseCsae<'r, RP> {
+impl<'r, RP: TransactionProvider> UseCaseProvider for UseCsae<'r, RP> {
     type SnakeUseCase = Self;
     type SlugUseCase = Self;
     type FrogUseCase = Self;
+    type FoodChainUseCase = Self;
 
     fn snake_use_case(&self) -> &Self::SnakeUseCase {
         self
@@ -35,57 +39,222 @@ impl<'r, RP: RepositoryProvider> UseCaseProvider for UseCsae<'r, RP> {
     fn frog_use_case(&self) -> &Self::FrogUseCase {
         self
     }
+    fn food_chain_use_case(&self) -> &Self::FoodChainUseCase {
+        self
+    }
 }
 
 #[async_trait]
-impl<'r, RP: RepositoryProvider> SnakeUseCase for UseCsae<'r, RP> {
+impl<'r, RP: TransactionProvider> SnakeUseCase for UseCsae<'r, RP> {
+    #[instrument(skip_all, fields(snake = %id))]
     async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
         self.snake_repository.get_snake(id).await
     }
+    #[instrument(skip_all, fields(frog = %frog_id))]
     async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake> {
         let frog = self.frog_repository.get_frog(frog_id).await?;
         self.snake_repository.get_snake(frog.eaten_by).await
     }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn register_snake(&self, snake: Snake) -> Result<Snake> {
+        let transaction = self.repository.begin().await?;
+        transaction
+            .slug_repository()
+            .get_slug(snake.eaten_by)
+            .await?;
+        transaction
+            .snake_repository()
+            .insert_snake(snake.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(snake)
+    }
+    #[instrument(skip_all, fields(frog = %frog_id, snake = %snake_id))]
+    async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog> {
+        let transaction = self.repository.begin().await?;
+        let snake = transaction.snake_repository().get_snake(snake_id).await?;
+        let mut frog = transaction.frog_repository().get_frog(frog_id).await?;
+        frog.eaten_by = snake.id;
+        transaction
+            .frog_repository()
+            .update_frog(frog.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(frog)
+    }
 }
 
 #[async_trait]
-impl<'r, RP: RepositoryProvider> SlugUseCase for UseCsae<'r, RP> {
+impl<'r, RP: TransactionProvider> SlugUseCase for UseCsae<'r, RP> {
+    #[instrument(skip_all, fields(slug = %id))]
     async fn get_slug(&self, id: SlugID) -> Result<Slug> {
         self.slug_repository.get_slug(id).await
     }
+    #[instrument(skip_all, fields(snake = %snake_id))]
     async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug> {
         let snake = self.snake_repository.get_snake(snake_id).await?;
         self.slug_repository.get_slug(snake.eaten_by).await
     }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn register_slug(&self, slug: Slug) -> Result<Slug> {
+        let transaction = self.repository.begin().await?;
+        transaction
+            .frog_repository()
+            .get_frog(slug.eaten_by)
+            .await?;
+        transaction
+            .slug_repository()
+            .insert_slug(slug.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(slug)
+    }
+    #[instrument(skip_all, fields(snake = %snake_id, slug = %slug_id))]
+    async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake> {
+        let transaction = self.repository.begin().await?;
+        let slug = transaction.slug_repository().get_slug(slug_id).await?;
+        let mut snake = transaction.snake_repository().get_snake(snake_id).await?;
+        snake.eaten_by = slug.id;
+        transaction
+            .snake_repository()
+            .update_snake(snake.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(snake)
+    }
 }
 
 #[async_trait]
-impl<'r, RP: RepositoryProvider> FrogUseCase for UseCsae<'r, RP> {
+impl<'r, RP: TransactionProvider> FrogUseCase for UseCsae<'r, RP> {
+    #[instrument(skip_all, fields(frog = %id))]
     async fn get_frog(&self, id: FrogID) -> Result<Frog> {
         self.frog_repository.get_frog(id).await
     }
+    #[instrument(skip_all, fields(slug = %slug_id))]
     async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog> {
         let slug = self.slug_repository.get_slug(slug_id).await?;
         self.frog_repository.get_frog(slug.eaten_by).await
     }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn register_frog(&self, frog: Frog) -> Result<Frog> {
+        let transaction = self.repository.begin().await?;
+        transaction
+            .snake_repository()
+            .get_snake(frog.eaten_by)
+            .await?;
+        transaction
+            .frog_repository()
+            .insert_frog(frog.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(frog)
+    }
+    #[instrument(skip_all, fields(slug = %slug_id, frog = %frog_id))]
+    async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug> {
+        let transaction = self.repository.begin().await?;
+        let frog = transaction.frog_repository().get_frog(frog_id).await?;
+        let mut slug = transaction.slug_repository().get_slug(slug_id).await?;
+        slug.eaten_by = frog.id;
+        transaction
+            .slug_repository()
+            .update_slug(slug.clone())
+            .await?;
+        transaction.commit().await?;
+        Ok(slug)
+    }
+}
+
+#[async_trait]
+impl<'r, RP: TransactionProvider> FoodChainUseCase for UseCsae<'r, RP> {
+    #[instrument(skip_all, fields(snakes = snakes.len(), slugs = slugs.len(), frogs = frogs.len()))]
+    async fn register_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        let transaction = self.repository.begin().await?;
+        for snake in &snakes {
+            transaction
+                .snake_repository()
+                .insert_snake(snake.clone())
+                .await?;
+        }
+        for slug in &slugs {
+            transaction
+                .slug_repository()
+                .insert_slug(slug.clone())
+                .await?;
+        }
+        for frog in &frogs {
+            transaction
+                .frog_repository()
+                .insert_frog(frog.clone())
+                .await?;
+        }
+        // Every predator is now either one of the animals or stored before.
+        for snake in &snakes {
+            transaction
+                .slug_repository()
+                .get_slug(snake.eaten_by)
+                .await?;
+        }
+        for slug in &slugs {
+            transaction
+                .frog_repository()
+                .get_frog(slug.eaten_by)
+                .await?;
+        }
+        for frog in &frogs {
+            transaction
+                .snake_repository()
+                .get_snake(frog.eaten_by)
+                .await?;
+        }
+        transaction.commit().await
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use domain::{
-        MockFrogRepository, MockRepositoryProvider, MockSlugRepository, MockSnakeRepository,
+        EntityID, Error, MockFrogRepository, MockSlugRepository, MockSnakeRepository,
+        MockTransaction, MockTransactionProvider,
     };
+    use domain_fakes::{fixtures, FakeRepositoryProvider};
+    use mockall::predicate::eq;
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
 
     #[tokio::test]
     async fn test_get_snake() {
         let mut snake_repository = MockSnakeRepository::new();
         snake_repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let slug_repository = MockSlugRepository::new();
         let frog_repository = MockFrogRepository::new();
-        let mut repository = MockRepositoryProvider::new();
+        let mut repository = MockTransactionProvider::new();
         repository
             .expect_snake_repository()
             .return_const(snake_repository);
@@ -96,7 +265,7 @@ mod test {
             .expect_frog_repository()
             .return_const(frog_repository);
         let use_case = UseCsae::new(&repository);
-        _ = use_case.get_snake(SnakeID::default()).await;
+        assert_eq!(use_case.get_snake(snake().id).await.unwrap(), snake());
     }
 
     #[tokio::test]
@@ -104,13 +273,11 @@ mod test {
         let mut snake_repository = MockSnakeRepository::new();
         snake_repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let slug_repository = MockSlugRepository::new();
         let mut frog_repository = MockFrogRepository::new();
-        frog_repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
-        let mut repository = MockRepositoryProvider::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut repository = MockTransactionProvider::new();
         repository
             .expect_snake_repository()
             .return_const(snake_repository);
@@ -121,18 +288,177 @@ mod test {
             .expect_frog_repository()
             .return_const(frog_repository);
         let use_case = UseCsae::new(&repository);
-        _ = use_case.get_snake_eating_frog(FrogID::default()).await;
+        assert_eq!(
+            use_case.get_snake_eating_frog(frog().id).await.unwrap(),
+            snake()
+        );
     }
 
     #[tokio::test]
-    async fn test_get_slug() {
-        let snake_repository = MockSnakeRepository::new();
+    async fn test_register_snake() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_insert_snake()
+            .with(eq(snake()))
+            .returning(|_| Ok(()));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        assert_eq!(use_case.register_snake(snake()).await.unwrap(), snake());
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_unknown_slug() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository.expect_insert_snake().never();
         let mut slug_repository = MockSlugRepository::new();
         slug_repository
             .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
         let frog_repository = MockFrogRepository::new();
-        let mut repository = MockRepositoryProvider::new();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().never();
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let err = use_case.register_snake(snake()).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == slug().id));
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|_| Ok(snake()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        frog_repository
+            .expect_update_frog()
+            .withf(|frog| frog.eaten_by == snake().id)
+            .returning(|_| Ok(()));
+        let slug_repository = MockSlugRepository::new();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let frog = use_case
+            .set_snake_eating_frog(frog().id, snake().id)
+            .await
+            .unwrap();
+        assert_eq!(frog.eaten_by, snake().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_unknown_snake() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_update_frog().never();
+        let slug_repository = MockSlugRepository::new();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().never();
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let err = use_case
+            .set_snake_eating_frog(frog().id, snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(id)) if id == snake().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_slug() {
+        let snake_repository = MockSnakeRepository::new();
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let frog_repository = MockFrogRepository::new();
+        let mut repository = MockTransactionProvider::new();
         repository
             .expect_snake_repository()
             .return_const(snake_repository);
@@ -143,7 +469,7 @@ mod test {
             .expect_frog_repository()
             .return_const(frog_repository);
         let use_case = UseCsae::new(&repository);
-        _ = use_case.get_slug(SlugID::default()).await;
+        assert_eq!(use_case.get_slug(slug().id).await.unwrap(), slug());
     }
 
     #[tokio::test]
@@ -151,13 +477,11 @@ mod test {
         let mut snake_repository = MockSnakeRepository::new();
         snake_repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let mut slug_repository = MockSlugRepository::new();
-        slug_repository
-            .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
         let frog_repository = MockFrogRepository::new();
-        let mut repository = MockRepositoryProvider::new();
+        let mut repository = MockTransactionProvider::new();
         repository
             .expect_snake_repository()
             .return_const(snake_repository);
@@ -168,7 +492,130 @@ mod test {
             .expect_frog_repository()
             .return_const(frog_repository);
         let use_case = UseCsae::new(&repository);
-        _ = use_case.get_slug_eating_snake(SnakeID::default()).await;
+        assert_eq!(
+            use_case.get_slug_eating_snake(snake().id).await.unwrap(),
+            slug()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_slug() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository
+            .expect_insert_slug()
+            .with(eq(slug()))
+            .returning(|_| Ok(()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        assert_eq!(use_case.register_slug(slug()).await.unwrap(), slug());
+    }
+
+    #[tokio::test]
+    async fn test_set_slug_eating_snake() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|_| Ok(snake()));
+        snake_repository
+            .expect_update_snake()
+            .withf(|snake| snake.eaten_by == slug().id)
+            .returning(|_| Ok(()));
+        let frog_repository = MockFrogRepository::new();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let snake = use_case
+            .set_slug_eating_snake(snake().id, slug().id)
+            .await
+            .unwrap();
+        assert_eq!(snake.eaten_by, slug().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_slug_eating_snake_unknown_slug() {
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository
+            .expect_get_slug()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository.expect_update_snake().never();
+        let frog_repository = MockFrogRepository::new();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().never();
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let err = use_case
+            .set_slug_eating_snake(snake().id, slug().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == slug().id));
     }
 
     #[tokio::test]
@@ -176,10 +623,8 @@ mod test {
         let snake_repository = MockSnakeRepository::new();
         let slug_repository = MockSlugRepository::new();
         let mut frog_repository = MockFrogRepository::new();
-        frog_repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
-        let mut repository = MockRepositoryProvider::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut repository = MockTransactionProvider::new();
         repository
             .expect_snake_repository()
             .return_const(snake_repository);
@@ -190,31 +635,396 @@ mod test {
             .expect_frog_repository()
             .return_const(frog_repository);
         let use_case = UseCsae::new(&repository);
-        _ = use_case.get_frog(FrogID::default()).await;
+        assert_eq!(use_case.get_frog(frog().id).await.unwrap(), frog());
     }
 
     #[tokio::test]
     async fn test_get_frog_eating_slug() {
         let snake_repository = MockSnakeRepository::new();
         let mut slug_repository = MockSlugRepository::new();
-        slug_repository
-            .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        repository
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        repository
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        let use_case = UseCsae::new(&repository);
+        assert_eq!(
+            use_case.get_frog_eating_slug(slug().id).await.unwrap(),
+            frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_frog() {
         let mut frog_repository = MockFrogRepository::new();
         frog_repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
-        let mut repository = MockRepositoryProvider::new();
+            .expect_insert_frog()
+            .with(eq(frog()))
+            .returning(|_| Ok(()));
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_get_snake()
+            .returning(|_| Ok(snake()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
         repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        assert_eq!(use_case.register_frog(frog()).await.unwrap(), frog());
+    }
+
+    #[tokio::test]
+    async fn test_set_frog_eating_slug() {
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository.expect_get_frog().returning(|_| Ok(frog()));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_get_slug().returning(|_| Ok(slug()));
+        slug_repository
+            .expect_update_slug()
+            .withf(|slug| slug.eaten_by == frog().id)
+            .returning(|_| Ok(()));
+        let snake_repository = MockSnakeRepository::new();
+        let mut transaction = MockTransaction::new();
+        transaction
             .expect_snake_repository()
             .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
         repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let slug = use_case
+            .set_frog_eating_slug(slug().id, frog().id)
+            .await
+            .unwrap();
+        assert_eq!(slug.eaten_by, frog().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_frog_eating_slug_unknown_frog() {
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository
+            .expect_get_frog()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository.expect_update_slug().never();
+        let snake_repository = MockSnakeRepository::new();
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
             .expect_slug_repository()
             .return_const(slug_repository);
+        transaction
+            .expect_frog_repository()
+            .return_const(frog_repository);
+        transaction.expect_commit().never();
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
         repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        let err = use_case
+            .set_frog_eating_slug(slug().id, frog().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Frog(id)) if id == frog().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_eating_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCsae::new(&repository);
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        assert_eq!(
+            use_case
+                .slug_use_case()
+                .get_slug_eating_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::slug()
+        );
+        assert_eq!(
+            use_case
+                .frog_use_case()
+                .get_frog_eating_slug(fixtures::slug().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCsae::new(&repository);
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..snake()
+        };
+        use_case
+            .snake_use_case()
+            .register_snake(snake.clone())
+            .await
+            .unwrap();
+        use_case
+            .snake_use_case()
+            .set_snake_eating_frog(fixtures::frog().id, snake.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            snake
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_unknown_snake_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCsae::new(&repository);
+        let err = use_case
+            .snake_use_case()
+            .set_snake_eating_frog(fixtures::frog().id, SnakeID::new(4).unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+        assert_eq!(
+            use_case
+                .frog_use_case()
+                .get_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_taken_stored() {
+        let repository = FakeRepositoryProvider::seeded()
+            .with_slug(Slug {
+                id: SlugID::new(4).unwrap(),
+                eaten_by: FrogID::new(4).unwrap(),
+            })
+            .with_frog(Frog {
+                id: FrogID::new(4).unwrap(),
+                eaten_by: SnakeID::new(4).unwrap(),
+            });
+        let use_case = UseCsae::new(&repository);
+        let err = use_case
+            .snake_use_case()
+            .register_snake(Snake {
+                eaten_by: SlugID::new(4).unwrap(),
+                ..snake()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Conflict(EntityID::Snake(id)) if id == fixtures::snake().id));
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_unknown_slug_stored() {
+        let repository = FakeRepositoryProvider::seeded();
+        let use_case = UseCsae::new(&repository);
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            eaten_by: SlugID::new(4).unwrap(),
+        };
+        let err = use_case
+            .snake_use_case()
+            .register_snake(snake.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == snake.eaten_by));
+        let err = use_case
+            .snake_use_case()
+            .get_snake(snake.id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain() {
+        let mut snake_repository = MockSnakeRepository::new();
+        snake_repository
+            .expect_insert_snake()
+            .with(eq(snake()))
+            .returning(|_| Ok(()));
+        snake_repository
+            .expect_get_snake()
+            .with(eq(snake().id))
+            .returning(|_| Ok(snake()));
+        let mut slug_repository = MockSlugRepository::new();
+        slug_repository
+            .expect_insert_slug()
+            .with(eq(slug()))
+            .returning(|_| Ok(()));
+        slug_repository
+            .expect_get_slug()
+            .with(eq(slug().id))
+            .returning(|_| Ok(slug()));
+        let mut frog_repository = MockFrogRepository::new();
+        frog_repository
+            .expect_insert_frog()
+            .with(eq(frog()))
+            .returning(|_| Ok(()));
+        frog_repository
+            .expect_get_frog()
+            .with(eq(frog().id))
+            .returning(|_| Ok(frog()));
+        let mut transaction = MockTransaction::new();
+        transaction
+            .expect_snake_repository()
+            .return_const(snake_repository);
+        transaction
+            .expect_slug_repository()
+            .return_const(slug_repository);
+        transaction
             .expect_frog_repository()
             .return_const(frog_repository);
+        transaction.expect_commit().returning(|| Ok(()));
+        let mut repository = MockTransactionProvider::new();
+        repository
+            .expect_snake_repository()
+            .return_const(MockSnakeRepository::new());
+        repository
+            .expect_slug_repository()
+            .return_const(MockSlugRepository::new());
+        repository
+            .expect_frog_repository()
+            .return_const(MockFrogRepository::new());
+        repository
+            .expect_begin()
+            .return_once(move || Ok(transaction));
+        let use_case = UseCsae::new(&repository);
+        use_case
+            .register_food_chain(vec![snake()], vec![slug()], vec![frog()])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_stored() {
+        let repository = FakeRepositoryProvider::new();
+        let use_case = UseCsae::new(&repository);
+        use_case
+            .food_chain_use_case()
+            .register_food_chain(
+                vec![fixtures::snake()],
+                vec![fixtures::slug()],
+                vec![fixtures::frog()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case
+                .snake_use_case()
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        // Later animals may be eaten by ones already registered.
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..fixtures::snake()
+        };
+        use_case
+            .food_chain_use_case()
+            .register_food_chain(vec![snake.clone()], vec![], vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case.snake_use_case().get_snake(snake.id).await.unwrap(),
+            snake
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_unknown_predator_stored() {
+        let repository = FakeRepositoryProvider::new();
         let use_case = UseCsae::new(&repository);
-        _ = use_case.get_frog_eating_slug(SlugID::default()).await;
+        let err = use_case
+            .food_chain_use_case()
+            .register_food_chain(vec![fixtures::snake()], vec![fixtures::slug()], vec![])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Frog(id)) if id == fixtures::frog().id));
+        let err = use_case
+            .snake_use_case()
+            .get_snake(fixtures::snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
     }
 }
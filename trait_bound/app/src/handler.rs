@@ -1,4 +1,8 @@
-use domain::{Frog, FrogID, FrogService, Slug, SlugID, SlugService, Snake, SnakeID, SnakeService};
+use domain::{
+    Error, Frog, FrogID, FrogService, Result, Slug, SlugID, SlugService, Snake, SnakeID,
+    SnakeService,
+};
+use tracing::instrument;
 
 pub(crate) struct Handler<T>
 where
@@ -11,33 +15,67 @@ impl<T: SnakeService + SlugService + FrogService> Handler<T> {
     pub(crate) fn new(service: T) -> Self {
         Self { service }
     }
-    pub(crate) async fn run(&self) -> (Snake, Slug, Frog) {
+    #[instrument(skip_all, fields(slug = %slug_id, frog = %frog_id, snake = %snake_id))]
+    pub(crate) async fn run(
+        &self,
+        slug_id: SlugID,
+        frog_id: FrogID,
+        snake_id: SnakeID,
+    ) -> Result<(Snake, Slug, Frog)> {
         let snake = self
             .service
-            .get_snake_eating_frog_eating_slug(SlugID::default())
-            .await
-            .unwrap();
+            .get_snake_eating_frog_eating_slug(slug_id)
+            .await?;
         let slug = self
             .service
-            .get_slug_eating_snake_eating_frog(FrogID::default())
-            .await
-            .unwrap();
+            .get_slug_eating_snake_eating_frog(frog_id)
+            .await?;
         let frog = self
             .service
-            .get_frog_eating_slug_eating_snake(SnakeID::default())
-            .await
-            .unwrap();
-        (snake, slug, frog)
+            .get_frog_eating_slug_eating_snake(snake_id)
+            .await?;
+        Ok((snake, slug, frog))
+    }
+}
+
+pub(crate) fn status(err: &Error) -> u16 {
+    match err.root() {
+        Error::Invalid(_) => 400,
+        Error::NotFound(_) => 404,
+        Error::Conflict(_) => 409,
+        Error::Internal(_) | Error::Context { .. } => 500,
+        Error::Unavailable(_) => 503,
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use anyhow::Result;
     use async_trait::async_trait;
+    use domain::EntityID;
     use mockall::mock;
 
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
+
     mock! {
         Service {}
         #[async_trait]
@@ -59,13 +97,33 @@ mod test {
         let mut service = MockService::new();
         service
             .expect_get_snake_eating_frog_eating_slug()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         service
             .expect_get_slug_eating_snake_eating_frog()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|_| Ok(slug()));
         service
             .expect_get_frog_eating_slug_eating_snake()
-            .returning(|_| Ok(Frog::default()));
-        _ = Handler::new(service).run().await;
+            .returning(|_| Ok(frog()));
+        assert_eq!(
+            Handler::new(service)
+                .run(slug().id, frog().id, snake().id)
+                .await
+                .unwrap(),
+            (snake(), slug(), frog())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_not_found() {
+        let mut service = MockService::new();
+        service
+            .expect_get_snake_eating_frog_eating_slug()
+            .returning(|slug_id| Err(Error::NotFound(slug_id.into())));
+        let err = Handler::new(service)
+            .run(slug().id, frog().id, snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Slug(id)) if id == slug().id));
+        assert_eq!(status(&err), 404);
     }
 }
@@ -1,21 +1,237 @@
-use database::{Database, DatabaseConnection};
+use std::{env, error::Error as _, process::ExitCode, sync::Arc};
 
-use message_queue::{MessageQueue, MessageQueueConnection};
-use repository::Repository;
+use database::{Database, DatabaseConfig, Migration, Migrator, Pool, PoolConfig};
+use domain::{
+    Error, FoodChainUseCase, Frog, FrogID, Result, ResultExt, Slug, SlugID, Snake, SnakeID,
+};
+use message_queue::{
+    correlate, MessageID, MessageQueue, MessageQueueConfig, MessageQueueConnection, OverflowPolicy,
+    TopicConfig,
+};
+use metrics::{Metrics, Registry};
+use repository::{Cache, CacheConfig, CachedRepository, OutboxRelay, Repository};
+use resilience::{Breaker, BreakerConfig, CircuitBreaker, Retry, RetryPolicy};
 use service::Service;
+use tracing::{info_span, Instrument};
 use use_case::UseCase;
 
 mod handler;
+mod telemetry;
 
 #[tokio::main]
-async fn main() {
-    let database_connection = DatabaseConnection {};
-    let database = Database::new(database_connection);
-    let message_queue_connection = MessageQueueConnection {};
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let mut message = err.to_string();
+            let mut source = err.source();
+            while let Some(cause) = source {
+                message = format!("{message}: {cause}");
+                source = cause.source();
+            }
+            eprintln!("error {}: {message}", handler::status(&err));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<()> {
+    telemetry::init()?;
+    let mut args = env::args().skip(1).peekable();
+    // `app migrate [--dry-run]` applies, or only lists, the pending migrations
+    // and exits.
+    let migrate_only = args.next_if_eq("migrate").is_some();
+    let dry_run = migrate_only && args.next_if_eq("--dry-run").is_some();
+    if let (true, Some(arg)) = (migrate_only, args.peek()) {
+        return Err(Error::Invalid(format!("unexpected argument {arg:?}")));
+    }
+
+    let config: DatabaseConfig = env::var("DATABASE_URL")
+        .as_deref()
+        .unwrap_or("memory")
+        .parse()
+        .map_err(|err| Error::Invalid(format!("DATABASE_URL: {err}")))?;
+    let pool = Pool::open(&config, PoolConfig::default()).map_err(Error::unavailable)?;
+    let _reaper = pool.spawn_reaper();
+    let database = Database::new(pool.clone());
+    let migrations = migrate(&database, dry_run).await?;
+    if migrate_only {
+        let status = if dry_run { "pending" } else { "applied" };
+        for migration in &migrations {
+            println!(
+                "{status} migration {:04} {}",
+                migration.version, migration.name
+            );
+        }
+        return Ok(());
+    }
+    for migration in &migrations {
+        eprintln!(
+            "applied migration {:04} {}",
+            migration.version, migration.name
+        );
+    }
+    let slug_id: SlugID = args.next().as_deref().unwrap_or("1").parse()?;
+    let frog_id: FrogID = args.next().as_deref().unwrap_or("1").parse()?;
+    let snake_id: SnakeID = args.next().as_deref().unwrap_or("1").parse()?;
+
+    let message_queue_config: MessageQueueConfig = env::var("MESSAGE_QUEUE_URL")
+        .as_deref()
+        .unwrap_or("memory")
+        .parse()
+        .map_err(|err| Error::Invalid(format!("MESSAGE_QUEUE_URL: {err}")))?;
+    let message_queue_connection =
+        MessageQueueConnection::open(&message_queue_config).map_err(Error::unavailable)?;
     let message_queue = MessageQueue::new(message_queue_connection);
-    let repository = Repository::new(database, message_queue);
-    let use_case = UseCase::new(repository);
-    let service = Service::new(use_case);
+    // The repository owns its database handle, so the relay gets its own on
+    // the same pool.
+    let relay_database = Database::new(pool.clone());
+    configure_topics(&message_queue).await?;
+    let cache = Arc::new(Cache::new(CacheConfig::default()));
+    invalidate_on_change(&message_queue, &cache).await?;
+    let repository = Repository::new(database);
+    // Every layer records its calls; the repository's are those that reach
+    // the backends.
+    let registry = Arc::new(Registry::new());
+    // Each write is a transaction of its own, so retrying a repository call
+    // retries the whole write. Every retry counts towards the database
+    // circuit; cache hits are served even while it is open.
+    let database_breaker = Arc::new(CircuitBreaker::new("database", BreakerConfig::default()));
+    let message_queue_breaker = CircuitBreaker::new("message_queue", BreakerConfig::default());
+    let repository = CachedRepository::new(
+        Retry::new(
+            Breaker::new(
+                Metrics::new(repository, Arc::clone(&registry), "repository"),
+                Arc::clone(&database_breaker),
+            ),
+            Arc::new(RetryPolicy::default()),
+        ),
+        Arc::clone(&cache),
+    );
+    let use_case = Metrics::new(UseCase::new(repository), Arc::clone(&registry), "use_case");
+    seed(&use_case).await?;
+    let service = Metrics::new(Service::new(use_case), Arc::clone(&registry), "service");
     let handler = handler::Handler::new(service);
-    _ = handler.run().await;
+    // Events published on behalf of the request carry its ID as their
+    // correlation ID.
+    let request_id = MessageID::new();
+    eprintln!("request {request_id}");
+    let (snake, slug, frog) = correlate(
+        request_id,
+        handler
+            .run(slug_id, frog_id, snake_id)
+            .instrument(info_span!("request", request_id = %request_id)),
+    )
+    .await?;
+    println!("{snake:?}\n{slug:?}\n{frog:?}");
+    let relayed = message_queue_breaker
+        .call(OutboxRelay::new(&relay_database, &message_queue).relay())
+        .await?;
+    eprintln!("relayed {relayed} outbox messages");
+    for topic in EVENT_TOPICS {
+        let stats = message_queue
+            .topic_stats(topic)
+            .await
+            .map_err(Error::internal)?;
+        eprintln!("topic {topic}: {stats}");
+    }
+    eprintln!("repository cache: {}", cache.stats());
+    for breaker in [&*database_breaker, &message_queue_breaker] {
+        eprintln!("circuit {}: {}", breaker.name(), breaker.stats());
+    }
+    eprintln!("database pool: {}", pool.stats());
+    // `METRICS_FILE` names a file to export the metrics to, in Prometheus's
+    // text format, for a textfile collector to scrape.
+    if let Ok(path) = env::var("METRICS_FILE") {
+        registry
+            .write_textfile(path.as_ref())
+            .map_err(Error::unavailable)
+            .with_context(|| format!("writing metrics to {path}"))?;
+    }
+    Ok(())
+}
+
+/// The topics domain events are published to.
+const EVENT_TOPICS: [&str; 3] = ["snake", "slug", "frog"];
+
+/// Bounds what a slow subscriber can hold back. Publishing blocks rather than
+/// drops, so the backlog stays in the outbox, where it is durable.
+async fn configure_topics(message_queue: &MessageQueue) -> Result<()> {
+    let config = TopicConfig {
+        capacity: Some(1024),
+        overflow: OverflowPolicy::Block,
+    };
+    for topic in EVENT_TOPICS {
+        message_queue
+            .configure_topic(topic, config)
+            .await
+            .map_err(Error::internal)?;
+    }
+    Ok(())
+}
+
+/// Keeps the cache in step with writes made elsewhere by dropping the animals
+/// change events are about.
+async fn invalidate_on_change(message_queue: &MessageQueue, cache: &Arc<Cache>) -> Result<()> {
+    for topic in EVENT_TOPICS {
+        let subscription = message_queue
+            .subscribe(topic)
+            .await
+            .map_err(Error::internal)?;
+        let cache = Arc::clone(cache);
+        tokio::spawn(async move { cache.invalidate_from(subscription).await });
+    }
+    Ok(())
+}
+
+/// Brings the schema up to date or, on a dry run, only validates it; returns
+/// the migrations applied or pending.
+async fn migrate(database: &Database, dry_run: bool) -> Result<Vec<&'static Migration>> {
+    let conn = database.conn().await.map_err(Error::unavailable)?;
+    let migrations = conn.run(move |conn| {
+        let migrator = Migrator::default();
+        if dry_run {
+            migrator.pending(conn)
+        } else {
+            migrator.migrate(conn)
+        }
+    });
+    migrations
+        .await
+        .map_err(|err| {
+            if err.is_transient() {
+                Error::unavailable(err)
+            } else {
+                Error::internal(err)
+            }
+        })
+        .context("migrating database")
+}
+
+/// Registers three animals of each kind, eaten by each other in a cycle.
+async fn seed<U: FoodChainUseCase>(use_case: &U) -> Result<()> {
+    let mut snakes = Vec::new();
+    let mut slugs = Vec::new();
+    let mut frogs = Vec::new();
+    for n in 1..=3 {
+        snakes.push(Snake {
+            id: SnakeID::new(n)?,
+            eaten_by: SlugID::new(n)?,
+        });
+        slugs.push(Slug {
+            id: SlugID::new(n)?,
+            eaten_by: FrogID::new(n)?,
+        });
+        frogs.push(Frog {
+            id: FrogID::new(n)?,
+            eaten_by: SnakeID::new(n % 3 + 1)?,
+        });
+    }
+    match use_case.register_food_chain(snakes, slugs, frogs).await {
+        // Seeding a persistent database a second time finds the animals
+        // already there.
+        Err(err) if matches!(err.root(), Error::Conflict(_)) => Ok(()),
+        result => result,
+    }
+    .context("seeding database")
 }
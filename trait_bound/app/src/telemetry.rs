@@ -0,0 +1,243 @@
+use std::{
+    env,
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use domain::{Error, Result};
+use rand::Rng;
+use serde_json::{json, Value};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Subscriber,
+};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    fmt::format::FmtSpan,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+/// Sets up what the spans opened along a request are reported to:
+///
+/// - `LOG_FORMAT`, `text` by default or `json`, is how they are logged to
+///   stderr as they close, filtered by `RUST_LOG`, `warn` by default.
+/// - `TRACE_FILE` names a file to append every span to as OTLP JSON, one
+///   export request per line, as the OpenTelemetry Collector's file exporter
+///   writes and its file receiver reads.
+pub(crate) fn init() -> Result<()> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::WARN.into())
+        .with_env_var("RUST_LOG")
+        .from_env()
+        .map_err(|err| Error::Invalid(format!("RUST_LOG: {err}")))?;
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+    let logs = match env::var("LOG_FORMAT").as_deref() {
+        Err(_) | Ok("text") => fmt.boxed(),
+        Ok("json") => fmt.json().with_span_list(true).boxed(),
+        Ok(format) => {
+            return Err(Error::Invalid(format!(
+                "LOG_FORMAT: expected text or json, got {format:?}"
+            )))
+        }
+    };
+    let traces = match env::var("TRACE_FILE") {
+        Ok(path) => Some(
+            OtlpFile::open(&path)
+                .map_err(Error::unavailable)?
+                .with_filter(LevelFilter::INFO),
+        ),
+        Err(_) => None,
+    };
+    tracing_subscriber::registry()
+        .with(logs.with_filter(filter))
+        .with(traces)
+        .try_init()
+        .map_err(Error::internal)
+}
+
+/// What a span will be exported with once it closes.
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    start: SystemTime,
+    attributes: Vec<Value>,
+}
+
+/// Collects a span's fields as OTLP attributes.
+struct AttributeVisitor<'a>(&'a mut Vec<Value>);
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0
+            .push(attribute(field, json!({ "stringValue": value })));
+    }
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0
+            .push(attribute(field, json!({ "intValue": value.to_string() })));
+    }
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0
+            .push(attribute(field, json!({ "intValue": value.to_string() })));
+    }
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.push(attribute(field, json!({ "boolValue": value })));
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+fn attribute(field: &Field, value: Value) -> Value {
+    json!({ "key": field.name(), "value": value })
+}
+
+/// Appends each span, as it closes, to a file in the OTLP JSON encoding.
+/// Spans started under another share its trace; the others start their own.
+pub(crate) struct OtlpFile {
+    file: Mutex<File>,
+}
+
+impl OtlpFile {
+    pub(crate) fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for OtlpFile {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span.parent().and_then(|parent| {
+            let extensions = parent.extensions();
+            let data = extensions.get::<SpanData>()?;
+            Some((data.trace_id, data.span_id))
+        });
+        // Zero is not a valid OTLP trace or span ID.
+        let mut rng = rand::thread_rng();
+        let mut data = SpanData {
+            trace_id: parent.map_or_else(|| rng.gen_range(1..=u128::MAX), |(trace_id, _)| trace_id),
+            span_id: rng.gen_range(1..=u64::MAX),
+            parent_span_id: parent.map(|(_, span_id)| span_id),
+            start: SystemTime::now(),
+            attributes: Vec::new(),
+        };
+        attrs.record(&mut AttributeVisitor(&mut data.attributes));
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<SpanData>() {
+            values.record(&mut AttributeVisitor(&mut data.attributes));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        let metadata = span.metadata();
+        let mut exported = json!({
+            "traceId": format!("{:032x}", data.trace_id),
+            "spanId": format!("{:016x}", data.span_id),
+            "name": format!("{}::{}", metadata.target(), metadata.name()),
+            // Internal: neither end of a remote call.
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(data.start),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": data.attributes,
+        });
+        if let Some(parent_span_id) = data.parent_span_id {
+            exported["parentSpanId"] = json!(format!("{parent_span_id:016x}"));
+        }
+        let request = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": env!("CARGO_PKG_NAME") },
+                    }],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "tracing" },
+                    "spans": [exported],
+                }],
+            }],
+        });
+        let mut line = request.to_string();
+        line.push('\n');
+        // Tracing has no way to report a failed export; the span is lost.
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        _ = file.write_all(line.as_bytes());
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    let nanos = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    nanos.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tracing::info_span;
+
+    use super::*;
+
+    #[test]
+    fn test_otlp_file() {
+        let path = env::temp_dir().join(format!("trace-{}.jsonl", std::process::id()));
+        let layer = OtlpFile::open(path.to_str().unwrap()).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = info_span!("request", request_id = "1f").entered();
+            info_span!("get_snake", snake = %1).in_scope(|| {});
+        });
+        let exported = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let spans: Vec<Value> = exported
+            .lines()
+            .map(|line| {
+                let request: Value = serde_json::from_str(line).unwrap();
+                request["resourceSpans"][0]["scopeSpans"][0]["spans"][0].clone()
+            })
+            .collect();
+        // Spans are exported as they close, innermost first.
+        let [inner, outer] = &spans[..] else {
+            panic!("expected two spans, got {exported}");
+        };
+        assert_eq!(inner["name"], "app::telemetry::test::get_snake");
+        assert_eq!(
+            inner["attributes"][0],
+            json!({ "key": "snake", "value": { "stringValue": "1" } })
+        );
+        assert_eq!(inner["traceId"], outer["traceId"]);
+        assert_eq!(inner["parentSpanId"], outer["spanId"]);
+        assert!(outer.get("parentSpanId").is_none());
+        assert_eq!(outer["traceId"].as_str().unwrap().len(), 32);
+    }
+}
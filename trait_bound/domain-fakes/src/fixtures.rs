@@ -0,0 +1,25 @@
+//! A food chain of one animal of each kind, each eaten by the next: snake 1
+//! by slug 2, slug 2 by frog 3 and frog 3 by snake 1.
+
+use domain::{Frog, FrogID, Slug, SlugID, Snake, SnakeID};
+
+pub fn snake() -> Snake {
+    Snake {
+        id: SnakeID::new(1).unwrap(),
+        eaten_by: SlugID::new(2).unwrap(),
+    }
+}
+
+pub fn slug() -> Slug {
+    Slug {
+        id: SlugID::new(2).unwrap(),
+        eaten_by: FrogID::new(3).unwrap(),
+    }
+}
+
+pub fn frog() -> Frog {
+    Frog {
+        id: FrogID::new(3).unwrap(),
+        eaten_by: SnakeID::new(1).unwrap(),
+    }
+}
@@ -0,0 +1,336 @@
+use async_trait::async_trait;
+use domain::{
+    FoodChainRepository, Frog, FrogID, FrogRepository, Result, Slug, SlugID, SlugRepository, Snake,
+    SnakeID, SnakeRepository,
+};
+
+use crate::store::{State, Store};
+
+/// An in-memory [`SnakeRepository`], handed out by a [`FakeRepository`] whose
+/// other animals it shares, as the predators of its snakes must be stored too.
+#[derive(Debug, Clone)]
+pub struct FakeSnakeRepository {
+    pub(crate) store: Store,
+}
+
+#[async_trait]
+impl SnakeRepository for FakeSnakeRepository {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.store.get(id)
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        self.store.insert(snake)
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        self.store.update(snake)
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        self.store.upsert(snake)
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        self.store.delete::<Snake>(id)
+    }
+}
+
+/// An in-memory [`SlugRepository`], handed out by a [`FakeRepository`] whose
+/// other animals it shares, as the predators of its slugs must be stored too.
+#[derive(Debug, Clone)]
+pub struct FakeSlugRepository {
+    pub(crate) store: Store,
+}
+
+#[async_trait]
+impl SlugRepository for FakeSlugRepository {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.store.get(id)
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        self.store.insert(slug)
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        self.store.update(slug)
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        self.store.upsert(slug)
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        self.store.delete::<Slug>(id)
+    }
+}
+
+/// An in-memory [`FrogRepository`], handed out by a [`FakeRepository`] whose
+/// other animals it shares, as the predators of its frogs must be stored too.
+#[derive(Debug, Clone)]
+pub struct FakeFrogRepository {
+    pub(crate) store: Store,
+}
+
+#[async_trait]
+impl FrogRepository for FakeFrogRepository {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.store.get(id)
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        self.store.insert(frog)
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        self.store.update(frog)
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        self.store.upsert(frog)
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        self.store.delete::<Frog>(id)
+    }
+}
+
+/// An in-memory repository of every animal, for the use cases that are
+/// bound on more than one repository trait. Clones share their animals, as
+/// do the single-animal fakes it hands out.
+#[derive(Debug, Clone, Default)]
+pub struct FakeRepository {
+    store: Store,
+}
+
+impl FakeRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A repository holding the [`fixtures`](crate::fixtures).
+    pub fn seeded() -> Self {
+        Self {
+            store: Store::new(State::seeded()),
+        }
+    }
+
+    pub fn with_snake(self, snake: Snake) -> Self {
+        self.store.lock().put(snake);
+        self
+    }
+
+    pub fn with_slug(self, slug: Slug) -> Self {
+        self.store.lock().put(slug);
+        self
+    }
+
+    pub fn with_frog(self, frog: Frog) -> Self {
+        self.store.lock().put(frog);
+        self
+    }
+
+    pub fn snake_repository(&self) -> FakeSnakeRepository {
+        FakeSnakeRepository {
+            store: self.store.clone(),
+        }
+    }
+
+    pub fn slug_repository(&self) -> FakeSlugRepository {
+        FakeSlugRepository {
+            store: self.store.clone(),
+        }
+    }
+
+    pub fn frog_repository(&self) -> FakeFrogRepository {
+        FakeFrogRepository {
+            store: self.store.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl SnakeRepository for FakeRepository {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.store.get(id)
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        self.store.insert(snake)
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        self.store.update(snake)
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        self.store.upsert(snake)
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        self.store.delete::<Snake>(id)
+    }
+}
+
+#[async_trait]
+impl SlugRepository for FakeRepository {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.store.get(id)
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        self.store.insert(slug)
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        self.store.update(slug)
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        self.store.upsert(slug)
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        self.store.delete::<Slug>(id)
+    }
+}
+
+#[async_trait]
+impl FrogRepository for FakeRepository {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.store.get(id)
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        self.store.insert(frog)
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        self.store.update(frog)
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        self.store.upsert(frog)
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        self.store.delete::<Frog>(id)
+    }
+}
+
+#[async_trait]
+impl FoodChainRepository for FakeRepository {
+    async fn insert_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        self.store.insert_all(snakes, slugs, frogs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use domain::{EntityID, Error, SlugID, SnakeID};
+
+    use super::*;
+    use crate::fixtures;
+
+    #[tokio::test]
+    async fn test_repositories_share_animals() {
+        let repository = FakeRepository::seeded();
+        // The snake is checked against the slug stored through another
+        // repository handed out by the same fake.
+        let slug = Slug {
+            id: SlugID::new(5).unwrap(),
+            ..fixtures::slug()
+        };
+        repository
+            .slug_repository()
+            .insert_slug(slug.clone())
+            .await
+            .unwrap();
+        let other = Snake {
+            id: SnakeID::new(4).unwrap(),
+            eaten_by: slug.id,
+        };
+        repository
+            .snake_repository()
+            .insert_snake(other.clone())
+            .await
+            .unwrap();
+        assert_eq!(repository.get_snake(other.id).await.unwrap(), other);
+    }
+
+    #[tokio::test]
+    async fn test_seeded() {
+        let repository = FakeRepository::seeded();
+        assert_eq!(
+            repository.get_slug(fixtures::slug().id).await.unwrap(),
+            fixtures::slug()
+        );
+        assert_eq!(
+            repository
+                .frog_repository()
+                .get_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_semantics() {
+        let repository = FakeRepository::seeded();
+        let err = repository
+            .insert_snake(fixtures::snake())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Conflict(EntityID::Snake(_))));
+        let slug = Slug {
+            id: SlugID::new(5).unwrap(),
+            ..fixtures::slug()
+        };
+        repository.insert_slug(slug.clone()).await.unwrap();
+        let moved = Snake {
+            eaten_by: slug.id,
+            ..fixtures::snake()
+        };
+        repository.update_snake(moved.clone()).await.unwrap();
+        assert_eq!(repository.get_snake(moved.id).await.unwrap(), moved);
+        // The snake is still eaten by the frog, and so kept.
+        let err = repository.delete_snake(moved.id).await.unwrap_err();
+        assert!(matches!(err, Error::Invalid(_)));
+        let moved = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..moved
+        };
+        repository.insert_snake(moved.clone()).await.unwrap();
+        repository.delete_snake(moved.id).await.unwrap();
+        let err = repository.update_snake(moved.clone()).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+        let err = repository.delete_snake(moved.id).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+        repository.upsert_snake(moved.clone()).await.unwrap();
+        assert_eq!(repository.get_snake(moved.id).await.unwrap(), moved);
+    }
+
+    #[tokio::test]
+    async fn test_insert_food_chain() {
+        let repository = FakeRepository::new();
+        // The snake is eaten by a slug that is not stored.
+        let stray = Snake {
+            id: SnakeID::new(4).unwrap(),
+            eaten_by: SlugID::new(5).unwrap(),
+        };
+        let err = repository
+            .insert_food_chain(
+                vec![fixtures::snake(), stray],
+                vec![fixtures::slug()],
+                vec![fixtures::frog()],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Invalid(_)));
+        let err = repository
+            .get_snake(fixtures::snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
+        repository
+            .insert_food_chain(
+                vec![fixtures::snake()],
+                vec![fixtures::slug()],
+                vec![fixtures::frog()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            repository.get_frog(fixtures::frog().id).await.unwrap(),
+            fixtures::frog()
+        );
+        let err = repository
+            .insert_food_chain(vec![], vec![fixtures::slug()], vec![])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Conflict(EntityID::Slug(_))));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fmt,
+    num::{NonZeroU64, ParseIntError},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IdError {
+    #[error("id must not be zero")]
+    Zero,
+    #[error("invalid id {0:?}: {1}")]
+    Parse(String, ParseIntError),
+}
+
+macro_rules! id {
+    ($name:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(NonZeroU64);
+
+        impl $name {
+            pub fn new(value: u64) -> Result<Self, IdError> {
+                NonZeroU64::new(value).map(Self).ok_or(IdError::Zero)
+            }
+
+            pub fn get(self) -> u64 {
+                self.0.get()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = IdError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let value = s
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|err| IdError::Parse(s.to_owned(), err))?;
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<u64> for $name {
+            type Error = IdError;
+
+            fn try_from(value: u64) -> Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl From<NonZeroU64> for $name {
+            fn from(value: NonZeroU64) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for NonZeroU64 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.get()
+            }
+        }
+    };
+}
+
+id!(SnakeID);
+id!(SlugID);
+id!(FrogID);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(SnakeID::new(7).unwrap().get(), 7);
+        assert_eq!(SnakeID::new(0), Err(IdError::Zero));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("42".parse::<SlugID>().unwrap(), SlugID::new(42).unwrap());
+        assert_eq!(" 42 ".parse::<SlugID>().unwrap(), SlugID::new(42).unwrap());
+        assert_eq!("0".parse::<SlugID>(), Err(IdError::Zero));
+        assert!(matches!("".parse::<SlugID>(), Err(IdError::Parse(..))));
+        assert!(matches!("-1".parse::<SlugID>(), Err(IdError::Parse(..))));
+        assert!(matches!("frog".parse::<SlugID>(), Err(IdError::Parse(..))));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let id = FrogID::new(u64::MAX).unwrap();
+        assert_eq!(id.to_string().parse::<FrogID>().unwrap(), id);
+    }
+
+    #[test]
+    fn test_ord() {
+        let mut ids = vec![
+            FrogID::new(3).unwrap(),
+            FrogID::new(1).unwrap(),
+            FrogID::new(2).unwrap(),
+        ];
+        ids.sort();
+        assert_eq!(
+            ids.into_iter().map(FrogID::get).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+    }
+}
@@ -0,0 +1,17 @@
+-- The food chain is a cycle, so the first animal of a chain always references
+-- a predator that does not exist yet. The foreign keys are enforced, but
+-- deferred: they are checked when the transaction commits, so the animals of
+-- a new chain are written together in one. `IF NOT EXISTS` adopts databases
+-- created before migrations were tracked.
+CREATE TABLE IF NOT EXISTS snake (
+    id INTEGER PRIMARY KEY NOT NULL,
+    eaten_by INTEGER NOT NULL REFERENCES slug (id) DEFERRABLE INITIALLY DEFERRED
+);
+CREATE TABLE IF NOT EXISTS slug (
+    id INTEGER PRIMARY KEY NOT NULL,
+    eaten_by INTEGER NOT NULL REFERENCES frog (id) DEFERRABLE INITIALLY DEFERRED
+);
+CREATE TABLE IF NOT EXISTS frog (
+    id INTEGER PRIMARY KEY NOT NULL,
+    eaten_by INTEGER NOT NULL REFERENCES snake (id) DEFERRABLE INITIALLY DEFERRED
+);
@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use crate::{
+    AppliedMigration, Error, FrogRow, Migration, OutboxRecord, Result, Row, SlugRow, SnakeRow,
+};
+
+pub(crate) type Table<R> = BTreeMap<u64, R>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Tables {
+    pub(crate) snake: Table<SnakeRow>,
+    pub(crate) slug: Table<SlugRow>,
+    pub(crate) frog: Table<FrogRow>,
+    /// Unsent messages only: sent ones are forgotten rather than marked.
+    pub(crate) outbox: Table<OutboxRecord>,
+    pub(crate) last_outbox_id: u64,
+}
+
+/// An immutable view of every table as of the moment it was taken.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    tables: Arc<Tables>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(tables: Tables) -> Self {
+        Self {
+            tables: Arc::new(tables),
+        }
+    }
+
+    pub fn get<R: Row>(&self, id: u64) -> Option<&R> {
+        R::table(&self.tables).get(&id)
+    }
+
+    pub fn rows<R: Row>(&self) -> impl Iterator<Item = &R> {
+        R::table(&self.tables).values()
+    }
+}
+
+/// Copy-on-write storage: writers clone the tables only while a snapshot of
+/// the previous version is still alive. A write either swaps in new tables
+/// or makes a single change in place, so a poisoned lock is recovered.
+///
+/// The tables are fixed, so migrations have no schema to change; they are
+/// only recorded, which keeps checksum validation uniform across backends.
+#[derive(Debug)]
+pub(crate) struct MemoryStore {
+    tables: RwLock<Arc<Tables>>,
+    migrations: RwLock<Vec<AppliedMigration>>,
+    /// Drawn anew for every store, as its outbox IDs start over at 1.
+    outbox_epoch: u64,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            tables: RwLock::default(),
+            migrations: RwLock::default(),
+            outbox_epoch: rand::random(),
+        }
+    }
+}
+
+impl MemoryStore {
+    pub(crate) fn snapshot(&self) -> Result<Snapshot> {
+        Ok(Snapshot {
+            tables: self.tables()?,
+        })
+    }
+
+    pub(crate) fn tables(&self) -> Result<Arc<Tables>> {
+        let tables = self.tables.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(Arc::clone(&tables))
+    }
+
+    pub(crate) fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    pub(crate) fn get<R: Row>(&self, id: u64) -> Result<Option<R>> {
+        let tables = self.tables.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(R::table(&tables).get(&id).cloned())
+    }
+
+    pub(crate) fn insert<R: Row>(&self, row: R) -> Result<()> {
+        self.write_row::<R, _>(row.id(), row, insert)
+    }
+
+    pub(crate) fn update<R: Row>(&self, row: R) -> Result<()> {
+        self.write_row::<R, _>(row.id(), row, update)
+    }
+
+    pub(crate) fn upsert<R: Row>(&self, row: R) -> Result<()> {
+        self.write_row::<R, _>(row.id(), row, upsert)
+    }
+
+    pub(crate) fn delete<R: Row>(&self, id: u64) -> Result<()> {
+        self.write_row::<R, _>(id, id, delete::<R>)
+    }
+
+    pub(crate) fn enqueue(&self, message: (String, Vec<u8>)) -> Result<()> {
+        self.write(|tables| enqueue(tables, message))
+    }
+
+    pub(crate) fn unsent(&self, max: usize) -> Result<Vec<OutboxRecord>> {
+        let tables = self.tables.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(tables.outbox.values().take(max).cloned().collect())
+    }
+
+    pub(crate) fn mark_sent(&self, id: u64) -> Result<()> {
+        self.write(|tables| {
+            tables.outbox.remove(&id);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn outbox_epoch(&self) -> Result<u64> {
+        Ok(self.outbox_epoch)
+    }
+
+    pub(crate) fn applied_migrations(&self) -> Result<Vec<AppliedMigration>> {
+        let migrations = self
+            .migrations
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(migrations.clone())
+    }
+
+    pub(crate) fn apply_migration(&self, migration: &Migration) -> Result<()> {
+        let mut migrations = self
+            .migrations
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        migrations.push(AppliedMigration {
+            version: migration.version,
+            name: migration.name.to_owned(),
+            checksum: migration.checksum(),
+        });
+        Ok(())
+    }
+
+    /// Applies every write or, if one fails, none of them. The reads are
+    /// checked against the current tables before any write is applied, and
+    /// the checks run once all writes are, as deferred foreign keys do.
+    pub(crate) fn apply<'a>(
+        &self,
+        reads: impl IntoIterator<Item = &'a Check>,
+        writes: &[Write],
+        checks: &[Check],
+    ) -> Result<()> {
+        let mut tables = self.tables.write().unwrap_or_else(PoisonError::into_inner);
+        for read in reads {
+            read(&tables)?;
+        }
+        let mut next = Tables::clone(&tables);
+        for write in writes {
+            write(&mut next)?;
+        }
+        for check in checks {
+            check(&next)?;
+        }
+        *tables = Arc::new(next);
+        Ok(())
+    }
+
+    fn write<T>(&self, f: impl FnOnce(&mut Tables) -> Result<T>) -> Result<T> {
+        let mut tables = self.tables.write().unwrap_or_else(PoisonError::into_inner);
+        f(Arc::make_mut(&mut tables))
+    }
+
+    /// Runs a write of the row with the ID outside a transaction, undoing it
+    /// if it leaves a foreign key violated.
+    fn write_row<R: Row, T>(
+        &self,
+        id: u64,
+        arg: T,
+        write: fn(&mut Tables, T) -> Result<()>,
+    ) -> Result<()> {
+        self.write(|tables| {
+            let previous = R::table(tables).get(&id).cloned();
+            write(tables, arg)?;
+            check::<R>(tables, id).map_err(|err| {
+                let table = R::table_mut(tables);
+                match previous {
+                    Some(row) => table.insert(id, row),
+                    None => table.remove(&id),
+                };
+                err
+            })
+        })
+    }
+}
+
+/// A recorded write that can be replayed onto another version of the tables.
+pub(crate) type Write = Box<dyn Fn(&mut Tables) -> Result<()> + Send + Sync>;
+
+/// A recorded check of the foreign keys of a row written, or that a row read
+/// is unchanged.
+pub(crate) type Check = Box<dyn Fn(&Tables) -> Result<()> + Send + Sync>;
+
+/// Fails with [`Error::ForeignKey`] if the row the given one is eaten by does
+/// not exist.
+fn check_predator<R: Row>(tables: &Tables, row: &R) -> Result<()> {
+    if !R::predator_exists(tables, row.eaten_by()) {
+        return Err(Error::ForeignKey);
+    }
+    Ok(())
+}
+
+/// Fails with [`Error::ForeignKey`] if a row is eaten by the given one.
+fn check_prey<R: Row>(tables: &Tables, id: u64) -> Result<()> {
+    if R::prey_exists(tables, id) {
+        return Err(Error::ForeignKey);
+    }
+    Ok(())
+}
+
+/// Checks the foreign keys of the row with the ID, whether it was written or
+/// deleted.
+pub(crate) fn check<R: Row>(tables: &Tables, id: u64) -> Result<()> {
+    match R::table(tables).get(&id) {
+        Some(row) => check_predator(tables, row),
+        None => check_prey::<R>(tables, id),
+    }
+}
+
+pub(crate) fn insert<R: Row>(tables: &mut Tables, row: R) -> Result<()> {
+    let table = R::table_mut(tables);
+    if table.contains_key(&row.id()) {
+        return Err(Error::Conflict {
+            table: R::TABLE,
+            id: row.id(),
+        });
+    }
+    table.insert(row.id(), row);
+    Ok(())
+}
+
+pub(crate) fn update<R: Row>(tables: &mut Tables, row: R) -> Result<()> {
+    match R::table_mut(tables).get_mut(&row.id()) {
+        Some(stored) => {
+            *stored = row;
+            Ok(())
+        }
+        None => Err(Error::NotFound {
+            table: R::TABLE,
+            id: row.id(),
+        }),
+    }
+}
+
+pub(crate) fn upsert<R: Row>(tables: &mut Tables, row: R) -> Result<()> {
+    R::table_mut(tables).insert(row.id(), row);
+    Ok(())
+}
+
+pub(crate) fn delete<R: Row>(tables: &mut Tables, id: u64) -> Result<()> {
+    match R::table_mut(tables).remove(&id) {
+        Some(_) => Ok(()),
+        None => Err(Error::NotFound {
+            table: R::TABLE,
+            id,
+        }),
+    }
+}
+
+pub(crate) fn enqueue(tables: &mut Tables, (topic, payload): (String, Vec<u8>)) -> Result<()> {
+    tables.last_outbox_id += 1;
+    let id = tables.last_outbox_id;
+    tables
+        .outbox
+        .insert(id, OutboxRecord { id, topic, payload });
+    Ok(())
+}
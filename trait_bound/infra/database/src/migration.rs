@@ -0,0 +1,273 @@
+use crate::{DatabaseConnection, Error, Result};
+
+/// A forward-only schema change. Once released a migration must not be
+/// edited: the checksum recorded when it was applied is compared against the
+/// SQL shipped with every later build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// 64-bit FNV-1a of the SQL. Unlike `DefaultHasher` it is stable across
+    /// Rust releases, so recorded checksums stay comparable.
+    pub fn checksum(&self) -> u64 {
+        self.sql.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+}
+
+/// The snake/slug/frog schema, oldest first.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_food_chain",
+        sql: include_str!("../migrations/0001_create_food_chain.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "create_outbox",
+        sql: include_str!("../migrations/0002_create_outbox.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "create_outbox_epoch",
+        sql: include_str!("../migrations/0003_create_outbox_epoch.sql"),
+    },
+];
+
+/// A migration as recorded in the applied-migrations table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migrator {
+    migrations: &'static [Migration],
+}
+
+impl Default for Migrator {
+    fn default() -> Self {
+        Self {
+            migrations: MIGRATIONS,
+        }
+    }
+}
+
+impl Migrator {
+    /// Fails with [`Error::InvalidMigrations`] unless versions strictly
+    /// increase.
+    pub fn new(migrations: &'static [Migration]) -> Result<Self> {
+        if let Some(pair) = migrations
+            .windows(2)
+            .find(|pair| pair[0].version >= pair[1].version)
+        {
+            return Err(Error::InvalidMigrations(format!(
+                "version {} follows version {}",
+                pair[1].version, pair[0].version
+            )));
+        }
+        Ok(Self { migrations })
+    }
+
+    /// Checks the applied migrations against the known ones and returns those
+    /// still to apply, without applying them.
+    ///
+    /// Fails if an applied migration was edited or is unknown to this build,
+    /// or if a pending migration is older than the latest applied one.
+    pub fn pending(&self, conn: &DatabaseConnection) -> Result<Vec<&'static Migration>> {
+        let applied = conn.applied_migrations()?;
+        for record in &applied {
+            let migration = self
+                .migrations
+                .iter()
+                .find(|migration| migration.version == record.version)
+                .ok_or(Error::UnknownMigration(record.version))?;
+            if migration.checksum() != record.checksum {
+                return Err(Error::ChecksumMismatch {
+                    version: migration.version,
+                    name: migration.name,
+                });
+            }
+        }
+        let latest = applied.iter().map(|record| record.version).max();
+        let pending = self
+            .migrations
+            .iter()
+            .filter(|migration| {
+                !applied
+                    .iter()
+                    .any(|record| record.version == migration.version)
+            })
+            .collect::<Vec<_>>();
+        if let (Some(latest), Some(first)) = (latest, pending.first()) {
+            if first.version < latest {
+                return Err(Error::InvalidMigrations(format!(
+                    "version {} is older than applied version {latest}",
+                    first.version
+                )));
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Applies the pending migrations in order, each atomically with its
+    /// record, and returns them.
+    pub fn migrate(&self, conn: &DatabaseConnection) -> Result<Vec<&'static Migration>> {
+        let pending = self.pending(conn)?;
+        for migration in &pending {
+            conn.apply_migration(migration)?;
+        }
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test::seed, SnakeRow};
+
+    const CREATE: Migration = MIGRATIONS[0];
+
+    const OUTBOX: Migration = MIGRATIONS[1];
+
+    const OUTBOX_EPOCH: Migration = MIGRATIONS[2];
+
+    const SNAKE_INDEX: Migration = Migration {
+        version: 4,
+        name: "index_snake_eaten_by",
+        sql: "CREATE INDEX snake_eaten_by ON snake (eaten_by);",
+    };
+
+    const FROG_INDEX: Migration = Migration {
+        version: 5,
+        name: "index_frog_eaten_by",
+        sql: "CREATE INDEX frog_eaten_by ON frog (eaten_by);",
+    };
+
+    fn connections() -> Vec<DatabaseConnection> {
+        vec![
+            DatabaseConnection::in_memory(),
+            DatabaseConnection::open(&"sqlite::memory:".parse().unwrap()).unwrap(),
+        ]
+    }
+
+    fn versions(migrations: Vec<&Migration>) -> Vec<u32> {
+        migrations
+            .iter()
+            .map(|migration| migration.version)
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_order() {
+        assert!(matches!(
+            Migrator::new(&[SNAKE_INDEX, CREATE]),
+            Err(Error::InvalidMigrations(_))
+        ));
+        assert!(matches!(
+            Migrator::new(&[SNAKE_INDEX, SNAKE_INDEX]),
+            Err(Error::InvalidMigrations(_))
+        ));
+    }
+
+    #[test]
+    fn test_migrate() {
+        for conn in connections() {
+            let migrator = Migrator::default();
+            assert_eq!(versions(migrator.migrate(&conn).unwrap()), [1, 2, 3]);
+            assert!(migrator.migrate(&conn).unwrap().is_empty());
+            assert_eq!(
+                conn.applied_migrations().unwrap(),
+                [
+                    AppliedMigration {
+                        version: 1,
+                        name: "create_food_chain".to_owned(),
+                        checksum: CREATE.checksum(),
+                    },
+                    AppliedMigration {
+                        version: 2,
+                        name: "create_outbox".to_owned(),
+                        checksum: OUTBOX.checksum(),
+                    },
+                    AppliedMigration {
+                        version: 3,
+                        name: "create_outbox_epoch".to_owned(),
+                        checksum: OUTBOX_EPOCH.checksum(),
+                    }
+                ]
+            );
+            seed(&conn, [2]);
+            conn.insert(SnakeRow { id: 1, eaten_by: 2 }).unwrap();
+            let next = Migrator::new(&[CREATE, OUTBOX, OUTBOX_EPOCH, SNAKE_INDEX]).unwrap();
+            assert_eq!(versions(next.migrate(&conn).unwrap()), [4]);
+            assert_eq!(conn.applied_migrations().unwrap().len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_dry_run_applies_nothing() {
+        let conn = DatabaseConnection::open(&"sqlite::memory:".parse().unwrap()).unwrap();
+        let migrator = Migrator::default();
+        assert_eq!(versions(migrator.pending(&conn).unwrap()), [1, 2, 3]);
+        assert_eq!(versions(migrator.pending(&conn).unwrap()), [1, 2, 3]);
+        assert!(conn.applied_migrations().unwrap().is_empty());
+        assert!(conn.get::<SnakeRow>(1).is_err());
+    }
+
+    #[test]
+    fn test_edited_migration() {
+        for conn in connections() {
+            Migrator::default().migrate(&conn).unwrap();
+            let edited = Migrator::new(&[Migration {
+                sql: "CREATE TABLE snake (id INTEGER PRIMARY KEY);",
+                ..CREATE
+            }])
+            .unwrap();
+            assert!(matches!(
+                edited.migrate(&conn),
+                Err(Error::ChecksumMismatch {
+                    version: 1,
+                    name: "create_food_chain"
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unknown_migration() {
+        for conn in connections() {
+            Migrator::new(&[CREATE, OUTBOX, OUTBOX_EPOCH, SNAKE_INDEX])
+                .unwrap()
+                .migrate(&conn)
+                .unwrap();
+            assert!(matches!(
+                Migrator::default().pending(&conn),
+                Err(Error::UnknownMigration(4))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_out_of_order_migration() {
+        for conn in connections() {
+            Migrator::new(&[CREATE, OUTBOX, OUTBOX_EPOCH, FROG_INDEX])
+                .unwrap()
+                .migrate(&conn)
+                .unwrap();
+            let migrator =
+                Migrator::new(&[CREATE, OUTBOX, OUTBOX_EPOCH, SNAKE_INDEX, FROG_INDEX]).unwrap();
+            assert!(matches!(
+                migrator.migrate(&conn),
+                Err(Error::InvalidMigrations(_))
+            ));
+            assert_eq!(conn.applied_migrations().unwrap().len(), 4);
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{
+    de::{value, DeserializeOwned, IntoDeserializer},
+    ser, Deserialize, Serialize,
+};
+
+use crate::{Error, Result};
+
+/// Identifies a message, or the request a conversation of messages started
+/// with. Unique per host: made of the time, the process ID and a counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MessageID(u128);
+
+impl MessageID {
+    pub fn new() -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(u128::from(nanos) << 64 | u128::from(process::id()) << 32 | u128::from(count))
+    }
+}
+
+impl Default for MessageID {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for MessageID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for MessageID {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() != 32 || !s.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(Error::InvalidMessageID(s.to_owned()));
+        }
+        u128::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| Error::InvalidMessageID(s.to_owned()))
+    }
+}
+
+tokio::task_local! {
+    static CORRELATION_ID: MessageID;
+}
+
+/// Runs `f` with `id` as the correlation ID of the envelopes created in it,
+/// so the messages published on behalf of a request can be traced back to it.
+pub async fn correlate<F: Future>(id: MessageID, f: F) -> F::Output {
+    CORRELATION_ID.scope(id, f).await
+}
+
+/// [`correlate`] for synchronous code, such as work moved onto a blocking
+/// thread, which the enclosing async one does not reach.
+pub fn correlate_sync<R>(id: MessageID, f: impl FnOnce() -> R) -> R {
+    CORRELATION_ID.sync_scope(id, f)
+}
+
+/// The correlation ID set by the enclosing [`correlate`], if any.
+pub fn correlation_id() -> Option<MessageID> {
+    CORRELATION_ID.try_with(|id| *id).ok()
+}
+
+/// Encodes payloads of one content type.
+pub trait Codec {
+    const CONTENT_TYPE: &'static str;
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Json;
+
+impl Codec for Json {
+    const CONTENT_TYPE: &'static str = "application/json";
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(codec_error::<Self>)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(codec_error::<Self>)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    const CONTENT_TYPE: &'static str = "application/x-bincode";
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).map_err(codec_error::<Self>)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        bincode::deserialize(bytes).map_err(codec_error::<Self>)
+    }
+}
+
+/// UTF-8 text, for values that serialize as a string.
+#[derive(Debug, Clone, Copy)]
+pub struct Text;
+
+impl Codec for Text {
+    const CONTENT_TYPE: &'static str = "text/plain; charset=utf-8";
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        match serde_json::to_value(value).map_err(codec_error::<Self>)? {
+            serde_json::Value::String(text) => Ok(text.into_bytes()),
+            _ => Err(codec_error::<Self>(
+                <serde_json::Error as ser::Error>::custom("not a string"),
+            )),
+        }
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        let text = std::str::from_utf8(bytes).map_err(codec_error::<Self>)?;
+        T::deserialize(IntoDeserializer::<value::Error>::into_deserializer(text))
+            .map_err(codec_error::<Self>)
+    }
+}
+
+/// The codecs to decode a payload with, chosen by its content type: a
+/// [`Codec`] or a tuple of them.
+pub trait Codecs {
+    /// Decodes with the codec of the content type, or returns `None` if
+    /// there is none among these.
+    fn decode_as<T: DeserializeOwned>(content_type: &str, bytes: &[u8]) -> Option<Result<T>>;
+}
+
+impl<C: Codec> Codecs for C {
+    fn decode_as<T: DeserializeOwned>(content_type: &str, bytes: &[u8]) -> Option<Result<T>> {
+        (content_type == C::CONTENT_TYPE).then(|| C::decode(bytes))
+    }
+}
+
+macro_rules! tuple_codecs {
+    ($($codecs:ident),+) => {
+        impl<$($codecs: Codecs),+> Codecs for ($($codecs,)+) {
+            fn decode_as<T: DeserializeOwned>(content_type: &str, bytes: &[u8]) -> Option<Result<T>> {
+                None$(.or_else(|| $codecs::decode_as(content_type, bytes)))+
+            }
+        }
+    };
+}
+
+tuple_codecs!(A, B);
+tuple_codecs!(A, B, C);
+tuple_codecs!(A, B, C, D);
+
+/// The codecs [`Envelope::decode`] chooses from.
+pub type DefaultCodecs = (Json, Bincode, Text);
+
+fn codec_error<C: Codec>(err: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::Codec {
+        content_type: C::CONTENT_TYPE.to_owned(),
+        source: Box::new(err),
+    }
+}
+
+/// The first byte of an encoded envelope, raised whenever the encoding
+/// changes.
+const ENVELOPE_VERSION: u8 = 1;
+
+/// A payload and what consumers need to know to handle it: where it came
+/// from, how it is encoded and which version of its schema it follows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub id: MessageID,
+    /// The request or message the conversation started with; the envelope's
+    /// own ID if it started one.
+    pub correlation_id: MessageID,
+    /// The message this one was published in reaction to, if any.
+    pub causation_id: Option<MessageID>,
+    pub timestamp: SystemTime,
+    pub content_type: String,
+    pub schema_version: u32,
+    pub headers: BTreeMap<String, String>,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    /// Wraps an encoded payload, correlated with the enclosing [`correlate`]
+    /// if any.
+    pub fn new(
+        content_type: impl Into<String>,
+        schema_version: u32,
+        payload: impl Into<Vec<u8>>,
+    ) -> Self {
+        let id = MessageID::new();
+        Self {
+            id,
+            correlation_id: correlation_id().unwrap_or(id),
+            causation_id: None,
+            timestamp: SystemTime::now(),
+            content_type: content_type.into(),
+            schema_version,
+            headers: BTreeMap::new(),
+            payload: payload.into(),
+        }
+    }
+
+    /// Wraps `value` encoded with the codec.
+    pub fn encode<C: Codec, T: Serialize + ?Sized>(value: &T, schema_version: u32) -> Result<Self> {
+        Ok(Self::new(
+            C::CONTENT_TYPE,
+            schema_version,
+            C::encode(value)?,
+        ))
+    }
+
+    /// Makes the envelope a reaction to `cause`, in its conversation.
+    pub fn caused_by(mut self, cause: &Envelope) -> Self {
+        self.correlation_id = cause.correlation_id;
+        self.causation_id = Some(cause.id);
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Decodes the payload with the codec its content type names, one of
+    /// the [`DefaultCodecs`].
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T> {
+        self.decode_any::<DefaultCodecs, T>()
+    }
+
+    /// Decodes the payload with whichever of the codecs its content type
+    /// names, so codecs of other content types can be plugged in.
+    pub fn decode_any<Cs: Codecs, T: DeserializeOwned>(&self) -> Result<T> {
+        Cs::decode_as(&self.content_type, &self.payload)
+            .unwrap_or_else(|| Err(Error::UnsupportedContentType(self.content_type.clone())))
+    }
+
+    /// Decodes the payload with the codec, which must match its content type.
+    pub fn decode_with<C: Codec, T: DeserializeOwned>(&self) -> Result<T> {
+        if self.content_type != C::CONTENT_TYPE {
+            return Err(Error::UnsupportedContentType(self.content_type.clone()));
+        }
+        C::decode(&self.payload)
+    }
+
+    /// Encodes the envelope as a message payload.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![ENVELOPE_VERSION];
+        bincode::serialize_into(&mut bytes, self).map_err(Error::InvalidEnvelope)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&ENVELOPE_VERSION, rest)) => {
+                bincode::deserialize(rest).map_err(Error::InvalidEnvelope)
+            }
+            _ => Err(Error::InvalidEnvelope(Box::new(
+                bincode::ErrorKind::Custom("unknown envelope version".to_owned()),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct SnakeRegisteredV1 {
+        snake: u64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct SnakeRegisteredV2 {
+        snake: u64,
+        eaten_by: u64,
+    }
+
+    #[test]
+    fn test_message_id() {
+        let (first, second) = (MessageID::new(), MessageID::new());
+        assert_ne!(first, second);
+        assert_eq!(first.to_string().len(), 32);
+        assert_eq!(first.to_string().parse::<MessageID>().unwrap(), first);
+        for s in ["", "1", "+0000000000000000000000000000000", "snake"] {
+            assert!(matches!(
+                s.parse::<MessageID>(),
+                Err(Error::InvalidMessageID(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for envelope in [
+            Envelope::encode::<Json, _>(&SnakeRegisteredV1 { snake: 1 }, 1).unwrap(),
+            Envelope::encode::<Bincode, _>(&SnakeRegisteredV1 { snake: 1 }, 1).unwrap(),
+        ] {
+            let envelope = envelope.with_header("source", "snake-service");
+            let decoded = Envelope::from_bytes(&envelope.to_bytes().unwrap()).unwrap();
+            assert_eq!(decoded, envelope);
+            assert_eq!(decoded.header("source"), Some("snake-service"));
+            assert_eq!(
+                decoded.decode::<SnakeRegisteredV1>().unwrap(),
+                SnakeRegisteredV1 { snake: 1 }
+            );
+        }
+        assert!(matches!(
+            Envelope::from_bytes(b"{}"),
+            Err(Error::InvalidEnvelope(_))
+        ));
+    }
+
+    #[test]
+    fn test_codecs() {
+        let envelope = Envelope::encode::<Json, _>(&SnakeRegisteredV1 { snake: 1 }, 1).unwrap();
+        assert_eq!(envelope.content_type, "application/json");
+        assert_eq!(envelope.payload, br#"{"snake":1}"#);
+        assert!(matches!(
+            envelope.decode_with::<Bincode, SnakeRegisteredV1>(),
+            Err(Error::UnsupportedContentType(_))
+        ));
+        assert!(matches!(
+            envelope.decode::<SnakeRegisteredV2>(),
+            Err(Error::Codec { .. })
+        ));
+        let envelope = Envelope::new("text/plain", 1, "snake");
+        assert!(matches!(
+            envelope.decode::<String>(),
+            Err(Error::UnsupportedContentType(_))
+        ));
+        let envelope = Envelope::encode::<Text, _>("snake 1", 1).unwrap();
+        assert_eq!(envelope.payload, b"snake 1");
+        assert_eq!(envelope.decode::<String>().unwrap(), "snake 1");
+        assert!(matches!(
+            Envelope::encode::<Text, _>(&SnakeRegisteredV1 { snake: 1 }, 1),
+            Err(Error::Codec { .. })
+        ));
+    }
+
+    #[test]
+    fn test_plugged_codecs() {
+        struct Csv;
+
+        impl Codec for Csv {
+            const CONTENT_TYPE: &'static str = "text/csv";
+
+            fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+                Text::encode(value)
+            }
+            fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+                Text::decode(bytes)
+            }
+        }
+
+        let envelope = Envelope::encode::<Csv, _>("snake,1", 1).unwrap();
+        assert!(matches!(
+            envelope.decode::<String>(),
+            Err(Error::UnsupportedContentType(_))
+        ));
+        assert_eq!(
+            envelope.decode_any::<(Json, Csv), String>().unwrap(),
+            "snake,1"
+        );
+        let envelope = Envelope::encode::<Json, _>("snake", 1).unwrap();
+        assert_eq!(
+            envelope.decode_any::<(Json, Csv), String>().unwrap(),
+            "snake"
+        );
+    }
+
+    #[test]
+    fn test_schema_versions() {
+        let decode = |envelope: &Envelope| match envelope.schema_version {
+            1 => envelope
+                .decode::<SnakeRegisteredV1>()
+                .map(|v1| (v1.snake, None)),
+            _ => envelope
+                .decode::<SnakeRegisteredV2>()
+                .map(|v2| (v2.snake, Some(v2.eaten_by))),
+        };
+        let v1 = Envelope::encode::<Json, _>(&SnakeRegisteredV1 { snake: 1 }, 1).unwrap();
+        let v2 = Envelope::encode::<Bincode, _>(
+            &SnakeRegisteredV2 {
+                snake: 1,
+                eaten_by: 2,
+            },
+            2,
+        )
+        .unwrap();
+        assert_eq!(decode(&v1).unwrap(), (1, None));
+        assert_eq!(decode(&v2).unwrap(), (1, Some(2)));
+    }
+
+    #[tokio::test]
+    async fn test_correlation() {
+        let request = MessageID::new();
+        let (first, second) = correlate(request, async {
+            let first = Envelope::new("text/plain", 1, "first");
+            (
+                first.clone(),
+                Envelope::new("text/plain", 1, "second").caused_by(&first),
+            )
+        })
+        .await;
+        assert_eq!(first.correlation_id, request);
+        assert_eq!(first.causation_id, None);
+        assert_eq!(second.correlation_id, request);
+        assert_eq!(second.causation_id, Some(first.id));
+
+        let uncorrelated = Envelope::new("text/plain", 1, "");
+        assert_eq!(correlation_id(), None);
+        assert_eq!(uncorrelated.correlation_id, uncorrelated.id);
+    }
+}
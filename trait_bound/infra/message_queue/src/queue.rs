@@ -0,0 +1,350 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use tokio::sync::watch;
+
+use crate::broker::Message;
+
+/// What publishing does when a subscriber of the topic already has
+/// [`TopicConfig::capacity`] messages waiting. The drop policies only apply
+/// to [`Subscription`](crate::Subscription)s; a [`Consumer`](crate::Consumer)
+/// is owed every message, so a full one holds the publisher back as under
+/// `Block`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Holds the publisher back until every subscriber has room.
+    #[default]
+    Block,
+    /// Drops the subscriber's oldest waiting message to make room.
+    DropOldest,
+    /// Drops the new message for that subscriber.
+    DropNewest,
+    /// Fails the publish with [`Error::Full`](crate::Error::Full).
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TopicConfig {
+    /// Messages each subscriber may have waiting; unbounded if `None`.
+    /// Replays of logged messages are read no more than this many at a time.
+    pub capacity: Option<usize>,
+    pub overflow: OverflowPolicy,
+}
+
+/// A point-in-time view of a topic's subscriber queues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TopicStats {
+    pub subscribers: usize,
+    /// Messages waiting for the slowest subscriber.
+    pub depth: usize,
+    pub capacity: Option<usize>,
+    /// Messages dropped for a subscriber, summed over subscribers.
+    pub dropped: u64,
+    /// Publishes that found the topic full and failed or had to wait.
+    pub overflows: u64,
+}
+
+impl fmt::Display for TopicStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} subscribers, depth {}", self.subscribers, self.depth)?;
+        if let Some(capacity) = self.capacity {
+            write!(f, "/{capacity}")?;
+        }
+        write!(
+            f,
+            ", {} dropped, {} overflows",
+            self.dropped, self.overflows
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    messages: VecDeque<Message>,
+    waker: Option<Waker>,
+    /// Set once the broker is dropped; the queue ends when drained.
+    closed: bool,
+    /// Set once the subscriber is dropped; nothing is queued from then on.
+    detached: bool,
+}
+
+/// The messages waiting for one subscriber.
+#[derive(Debug)]
+pub(crate) struct Queue {
+    state: Mutex<State>,
+    /// The topic's signal that a subscriber made room.
+    space: Arc<watch::Sender<()>>,
+    /// Set for consumers, whose messages are never dropped.
+    lossless: bool,
+}
+
+impl Queue {
+    pub(crate) fn new(space: Arc<watch::Sender<()>>, lossless: bool) -> Self {
+        Self {
+            state: Mutex::default(),
+            space,
+            lossless,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lock().messages.len()
+    }
+
+    /// Whether the subscriber is still there to receive.
+    pub(crate) fn is_attached(&self) -> bool {
+        !self.lock().detached
+    }
+
+    /// Drops the waiting messages once the subscriber is gone, and wakes the
+    /// publishers blocked on them.
+    pub(crate) fn detach(&self) {
+        let mut state = self.lock();
+        state.detached = true;
+        state.messages.clear();
+        drop(state);
+        self.space.send_modify(|_| ());
+    }
+
+    /// Whether a publish must wait for, or fail on, this queue being full
+    /// rather than drop a message from it.
+    pub(crate) fn holds_back(&self, config: &TopicConfig) -> bool {
+        self.lossless
+            || matches!(
+                config.overflow,
+                OverflowPolicy::Block | OverflowPolicy::Error
+            )
+    }
+
+    /// Enqueues the message, making room as the topic's policy says, and
+    /// returns whether a message was dropped. Queues that hold the publish
+    /// back are left to the publisher.
+    pub(crate) fn push(&self, message: Message, config: &TopicConfig) -> bool {
+        let mut state = self.lock();
+        if state.detached {
+            return false;
+        }
+        let full = config
+            .capacity
+            .is_some_and(|capacity| state.messages.len() >= capacity);
+        let dropped = full
+            && !self.holds_back(config)
+            && match config.overflow {
+                OverflowPolicy::DropOldest => state.messages.pop_front().is_some(),
+                OverflowPolicy::DropNewest => return true,
+                OverflowPolicy::Block | OverflowPolicy::Error => false,
+            };
+        state.messages.push_back(message);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        dropped
+    }
+
+    pub(crate) fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        let mut state = self.lock();
+        if let Some(message) = state.messages.pop_front() {
+            drop(state);
+            self.space.send_modify(|_| ());
+            return Poll::Ready(Some(message));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Recovers a poisoned lock, as the broker must still close the queue
+    /// when dropped.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        crate::lock(&self.state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use super::*;
+    use crate::{ConsumerConfig, Error, MessageQueue, MessageQueueConnection};
+
+    async fn message_queue(capacity: usize, overflow: OverflowPolicy) -> MessageQueue {
+        let message_queue = MessageQueue::new(MessageQueueConnection::in_memory());
+        let config = TopicConfig {
+            capacity: Some(capacity),
+            overflow,
+        };
+        message_queue.configure_topic("frog", config).await.unwrap();
+        message_queue
+    }
+
+    async fn publish(message_queue: &MessageQueue, payloads: &[&str]) {
+        for payload in payloads {
+            message_queue.publish("frog", *payload).await.unwrap();
+        }
+    }
+
+    async fn drain(subscription: &mut crate::Subscription, n: usize) -> Vec<Vec<u8>> {
+        let mut payloads = Vec::new();
+        for _ in 0..n {
+            payloads.push(subscription.recv().await.unwrap().payload);
+        }
+        payloads
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let message_queue = message_queue(2, OverflowPolicy::DropOldest).await;
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        publish(&message_queue, &["1", "2", "3"]).await;
+        let stats = message_queue.topic_stats("frog").await.unwrap();
+        assert_eq!((stats.depth, stats.dropped), (2, 1));
+        assert_eq!(drain(&mut subscription, 2).await, [b"2", b"3"]);
+        assert_eq!(message_queue.topic_stats("frog").await.unwrap().depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest() {
+        let message_queue = message_queue(2, OverflowPolicy::DropNewest).await;
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        publish(&message_queue, &["1", "2", "3"]).await;
+        assert_eq!(message_queue.topic_stats("frog").await.unwrap().dropped, 1);
+        assert_eq!(drain(&mut subscription, 2).await, [b"1", b"2"]);
+        // Dropped for the subscriber only; the log keeps every message.
+        assert_eq!(message_queue.read("frog", 0, 10).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_consumers_lose_nothing() {
+        let message_queue = message_queue(1, OverflowPolicy::DropOldest).await;
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        let mut consumer = message_queue
+            .consumer("frog", "eater", ConsumerConfig::default())
+            .await
+            .unwrap();
+        publish(&message_queue, &["1"]).await;
+        let blocked = time::timeout(
+            Duration::from_millis(20),
+            message_queue.publish("frog", "2"),
+        );
+        assert!(blocked.await.is_err(), "the consumer has no room");
+        let consume = async {
+            let mut payloads = Vec::new();
+            for _ in 0..3 {
+                let delivery = consumer.recv().await.unwrap();
+                payloads.push(delivery.message().payload.clone());
+                delivery.ack().await.unwrap();
+            }
+            payloads
+        };
+        let (_, consumed) = tokio::join!(publish(&message_queue, &["3", "4"]), consume);
+        assert_eq!(consumed, [b"1", b"3", b"4"]);
+        assert_eq!(
+            message_queue.committed("frog", "eater").await.unwrap(),
+            Some(3)
+        );
+        // The subscription still drops down to its capacity.
+        assert_eq!(drain(&mut subscription, 1).await, [b"4"]);
+        assert_eq!(message_queue.topic_stats("frog").await.unwrap().dropped, 2);
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let message_queue = message_queue(1, OverflowPolicy::Error).await;
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        publish(&message_queue, &["1"]).await;
+        assert!(matches!(
+            message_queue.publish("frog", "2").await,
+            Err(Error::Full(topic)) if topic == "frog"
+        ));
+        assert_eq!(
+            message_queue.topic_stats("frog").await.unwrap().overflows,
+            1
+        );
+        assert_eq!(drain(&mut subscription, 1).await, [b"1"]);
+        publish(&message_queue, &["3"]).await;
+    }
+
+    #[tokio::test]
+    async fn test_block() {
+        let message_queue = message_queue(1, OverflowPolicy::Block).await;
+        let mut subscription = message_queue.subscribe("frog").await.unwrap();
+        publish(&message_queue, &["1"]).await;
+        let blocked = time::timeout(
+            Duration::from_millis(20),
+            message_queue.publish("frog", "2"),
+        );
+        assert!(blocked.await.is_err(), "the subscriber has no room");
+        let (published, received) = tokio::join!(
+            message_queue.publish("frog", "3"),
+            drain(&mut subscription, 2)
+        );
+        published.unwrap();
+        assert_eq!(received, [b"1", b"3"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_subscription_unblocks() {
+        let message_queue = message_queue(1, OverflowPolicy::Block).await;
+        let subscription = message_queue.subscribe("frog").await.unwrap();
+        publish(&message_queue, &["1"]).await;
+        let (published, ()) = tokio::join!(
+            time::timeout(Duration::from_secs(2), message_queue.publish("frog", "2")),
+            async {
+                time::sleep(Duration::from_millis(20)).await;
+                drop(subscription);
+            }
+        );
+        published.expect("the publisher is still blocked").unwrap();
+        assert_eq!(
+            message_queue.topic_stats("frog").await.unwrap().subscribers,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_subscriptions_free_room() {
+        let message_queue = message_queue(1, OverflowPolicy::Error).await;
+        let subscription = message_queue.subscribe("frog").await.unwrap();
+        publish(&message_queue, &["1"]).await;
+        drop(subscription);
+        publish(&message_queue, &["2"]).await;
+        let stats = message_queue.topic_stats("frog").await.unwrap();
+        assert_eq!((stats.subscribers, stats.depth), (0, 0));
+        assert_eq!(
+            stats.to_string(),
+            "0 subscribers, depth 0/1, 0 dropped, 0 overflows"
+        );
+    }
+
+    #[test]
+    fn test_poisoned_queue_closes() {
+        let queue = Arc::new(Queue::new(Arc::new(watch::channel(()).0), false));
+        let poisoner = Arc::clone(&queue);
+        std::thread::spawn(move || {
+            let _state = poisoner.lock();
+            panic!("poisoning the queue lock");
+        })
+        .join()
+        .unwrap_err();
+        assert!(queue.state.is_poisoned());
+        queue.close();
+        assert!(queue.lock().closed);
+        assert_eq!(queue.len(), 0);
+    }
+}
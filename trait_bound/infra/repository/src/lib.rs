@@ -1,57 +1,180 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use database::Database;
 use domain::{
-    Frog, FrogID, FrogRepository, Slug, SlugID, SlugRepository, Snake, SnakeID, SnakeRepository,
+    FoodChainRepository, Frog, FrogID, FrogRepository, Result, Slug, SlugID, SlugRepository, Snake,
+    SnakeID, SnakeRepository,
 };
-use message_queue::MessageQueue;
+use tracing::instrument;
+
+mod cache;
+mod outbox;
+mod store;
+
+pub use cache::{Cache, CacheConfig, CacheStats, CachedRepository};
+pub use outbox::OutboxRelay;
 
 pub struct Repository {
     database: Database,
-    message_queue: MessageQueue,
 }
 
 impl Repository {
-    pub fn new(database: Database, message_queue: MessageQueue) -> Self {
-        Self {
-            database,
-            message_queue,
-        }
+    pub fn new(database: Database) -> Self {
+        Self { database }
     }
 }
 
 #[async_trait]
 impl SnakeRepository for Repository {
+    #[instrument(skip_all, fields(snake = %id))]
     async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
-        _ = self.database.conn();
-        _ = self.message_queue.conn();
-        Ok(Snake {
-            id,
-            ..Default::default()
-        })
+        store::get(&self.database, id).await
+    }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        store::insert(&self.database, &snake).await
+    }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        store::update(&self.database, &snake).await
+    }
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        store::upsert(&self.database, &snake).await
+    }
+    #[instrument(skip_all, fields(snake = %id))]
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        store::delete::<Snake>(&self.database, id).await
     }
 }
 
 #[async_trait]
 impl SlugRepository for Repository {
+    #[instrument(skip_all, fields(slug = %id))]
     async fn get_slug(&self, id: SlugID) -> Result<Slug> {
-        _ = self.database.conn();
-        _ = self.message_queue.conn();
-        Ok(Slug {
-            id,
-            ..Default::default()
-        })
+        store::get(&self.database, id).await
+    }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        store::insert(&self.database, &slug).await
+    }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        store::update(&self.database, &slug).await
+    }
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        store::upsert(&self.database, &slug).await
+    }
+    #[instrument(skip_all, fields(slug = %id))]
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        store::delete::<Slug>(&self.database, id).await
     }
 }
 
 #[async_trait]
 impl FrogRepository for Repository {
+    #[instrument(skip_all, fields(frog = %id))]
     async fn get_frog(&self, id: FrogID) -> Result<Frog> {
-        _ = self.database.conn();
-        _ = self.message_queue.conn();
-        Ok(Frog {
-            id,
-            ..Default::default()
-        })
+        store::get(&self.database, id).await
+    }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        store::insert(&self.database, &frog).await
+    }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        store::update(&self.database, &frog).await
+    }
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        store::upsert(&self.database, &frog).await
+    }
+    #[instrument(skip_all, fields(frog = %id))]
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        store::delete::<Frog>(&self.database, id).await
+    }
+}
+
+#[async_trait]
+impl FoodChainRepository for Repository {
+    /// Records an event for each animal, as [`SnakeRepository::insert_snake`]
+    /// and the like do.
+    #[instrument(skip_all, fields(snakes = snakes.len(), slugs = slugs.len(), frogs = frogs.len()))]
+    async fn insert_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        store::insert_all(&self.database, &snakes, &slugs, &frogs).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use database::{DatabaseConfig, Pool, PoolConfig};
+
+    use super::*;
+    use crate::store::test::{seed, sqlite_database};
+
+    /// A repository holding the animals those of the contract are eaten by.
+    async fn repository() -> Repository {
+        let database =
+            Database::new(Pool::open(&DatabaseConfig::Memory, PoolConfig::default()).unwrap());
+        seed(&database, domain_fakes::contract::PREDATORS).await;
+        Repository::new(database)
+    }
+
+    /// The repository behaves as the in-memory fakes do, on both backends
+    /// and behind the cache.
+    mod contract {
+        use std::sync::Arc;
+
+        use super::*;
+        use crate::{Cache, CacheConfig, CachedRepository};
+
+        mod repository {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let repository = repository().await;
+                }
+                snake: &repository,
+                slug: &repository,
+                frog: &repository,
+            }
+        }
+
+        mod sqlite {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let (database, _file) = sqlite_database().await;
+                    seed(&database, domain_fakes::contract::PREDATORS).await;
+                    let repository = Repository::new(database);
+                }
+                snake: &repository,
+                slug: &repository,
+                frog: &repository,
+            }
+        }
+
+        mod cached {
+            use super::*;
+
+            domain_fakes::repository_contract! {
+                setup {
+                    let repository = CachedRepository::new(
+                        repository().await,
+                        Arc::new(Cache::new(CacheConfig::default())),
+                    );
+                }
+                snake: &repository,
+                slug: &repository,
+                frog: &repository,
+            }
+        }
     }
 }
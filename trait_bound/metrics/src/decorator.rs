@@ -0,0 +1,316 @@
+use std::{future::Future, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use domain::{
+    FoodChainRepository, FoodChainUseCase, Frog, FrogID, FrogRepository, FrogService, FrogUseCase,
+    Result, Slug, SlugID, SlugRepository, SlugService, SlugUseCase, Snake, SnakeID,
+    SnakeRepository, SnakeService, SnakeUseCase,
+};
+
+use crate::Registry;
+
+/// Records every call to the inner repository, use case or service in a
+/// [`Registry`], labelled with the layer it was made to, e.g. `repository`,
+/// `use_case` or `service`. Otherwise transparent: calls and their results
+/// pass through unchanged.
+pub struct Metrics<T> {
+    inner: T,
+    registry: Arc<Registry>,
+    layer: &'static str,
+}
+
+impl<T> Metrics<T> {
+    pub fn new(inner: T, registry: Arc<Registry>, layer: &'static str) -> Self {
+        Self {
+            inner,
+            registry,
+            layer,
+        }
+    }
+
+    pub fn registry(&self) -> &Arc<Registry> {
+        &self.registry
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    async fn observe<V>(
+        &self,
+        method: &'static str,
+        call: impl Future<Output = Result<V>>,
+    ) -> Result<V> {
+        let start = Instant::now();
+        let result = call.await;
+        self.registry
+            .record(self.layer, method, start.elapsed(), result.as_ref().err());
+        result
+    }
+}
+
+#[async_trait]
+impl<R: SnakeRepository> SnakeRepository for Metrics<R> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.observe("get_snake", self.inner.get_snake(id)).await
+    }
+    async fn insert_snake(&self, snake: Snake) -> Result<()> {
+        self.observe("insert_snake", self.inner.insert_snake(snake))
+            .await
+    }
+    async fn update_snake(&self, snake: Snake) -> Result<()> {
+        self.observe("update_snake", self.inner.update_snake(snake))
+            .await
+    }
+    async fn upsert_snake(&self, snake: Snake) -> Result<()> {
+        self.observe("upsert_snake", self.inner.upsert_snake(snake))
+            .await
+    }
+    async fn delete_snake(&self, id: SnakeID) -> Result<()> {
+        self.observe("delete_snake", self.inner.delete_snake(id))
+            .await
+    }
+}
+
+#[async_trait]
+impl<R: SlugRepository> SlugRepository for Metrics<R> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.observe("get_slug", self.inner.get_slug(id)).await
+    }
+    async fn insert_slug(&self, slug: Slug) -> Result<()> {
+        self.observe("insert_slug", self.inner.insert_slug(slug))
+            .await
+    }
+    async fn update_slug(&self, slug: Slug) -> Result<()> {
+        self.observe("update_slug", self.inner.update_slug(slug))
+            .await
+    }
+    async fn upsert_slug(&self, slug: Slug) -> Result<()> {
+        self.observe("upsert_slug", self.inner.upsert_slug(slug))
+            .await
+    }
+    async fn delete_slug(&self, id: SlugID) -> Result<()> {
+        self.observe("delete_slug", self.inner.delete_slug(id))
+            .await
+    }
+}
+
+#[async_trait]
+impl<R: FrogRepository> FrogRepository for Metrics<R> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.observe("get_frog", self.inner.get_frog(id)).await
+    }
+    async fn insert_frog(&self, frog: Frog) -> Result<()> {
+        self.observe("insert_frog", self.inner.insert_frog(frog))
+            .await
+    }
+    async fn update_frog(&self, frog: Frog) -> Result<()> {
+        self.observe("update_frog", self.inner.update_frog(frog))
+            .await
+    }
+    async fn upsert_frog(&self, frog: Frog) -> Result<()> {
+        self.observe("upsert_frog", self.inner.upsert_frog(frog))
+            .await
+    }
+    async fn delete_frog(&self, id: FrogID) -> Result<()> {
+        self.observe("delete_frog", self.inner.delete_frog(id))
+            .await
+    }
+}
+
+#[async_trait]
+impl<R: FoodChainRepository> FoodChainRepository for Metrics<R> {
+    async fn insert_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        self.observe(
+            "insert_food_chain",
+            self.inner.insert_food_chain(snakes, slugs, frogs),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<U: SnakeUseCase> SnakeUseCase for Metrics<U> {
+    async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
+        self.observe("get_snake", self.inner.get_snake(id)).await
+    }
+    async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake> {
+        self.observe(
+            "get_snake_eating_frog",
+            self.inner.get_snake_eating_frog(frog_id),
+        )
+        .await
+    }
+    async fn register_snake(&self, snake: Snake) -> Result<Snake> {
+        self.observe("register_snake", self.inner.register_snake(snake))
+            .await
+    }
+    async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog> {
+        self.observe(
+            "set_snake_eating_frog",
+            self.inner.set_snake_eating_frog(frog_id, snake_id),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<U: SlugUseCase> SlugUseCase for Metrics<U> {
+    async fn get_slug(&self, id: SlugID) -> Result<Slug> {
+        self.observe("get_slug", self.inner.get_slug(id)).await
+    }
+    async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug> {
+        self.observe(
+            "get_slug_eating_snake",
+            self.inner.get_slug_eating_snake(snake_id),
+        )
+        .await
+    }
+    async fn register_slug(&self, slug: Slug) -> Result<Slug> {
+        self.observe("register_slug", self.inner.register_slug(slug))
+            .await
+    }
+    async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake> {
+        self.observe(
+            "set_slug_eating_snake",
+            self.inner.set_slug_eating_snake(snake_id, slug_id),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<U: FrogUseCase> FrogUseCase for Metrics<U> {
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
+        self.observe("get_frog", self.inner.get_frog(id)).await
+    }
+    async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog> {
+        self.observe(
+            "get_frog_eating_slug",
+            self.inner.get_frog_eating_slug(slug_id),
+        )
+        .await
+    }
+    async fn register_frog(&self, frog: Frog) -> Result<Frog> {
+        self.observe("register_frog", self.inner.register_frog(frog))
+            .await
+    }
+    async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug> {
+        self.observe(
+            "set_frog_eating_slug",
+            self.inner.set_frog_eating_slug(slug_id, frog_id),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<U: FoodChainUseCase> FoodChainUseCase for Metrics<U> {
+    async fn register_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        self.observe(
+            "register_food_chain",
+            self.inner.register_food_chain(snakes, slugs, frogs),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<S: SnakeService> SnakeService for Metrics<S> {
+    async fn get_snake_eating_frog_eating_slug(&self, slug_id: SlugID) -> Result<Snake> {
+        self.observe(
+            "get_snake_eating_frog_eating_slug",
+            self.inner.get_snake_eating_frog_eating_slug(slug_id),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<S: SlugService> SlugService for Metrics<S> {
+    async fn get_slug_eating_snake_eating_frog(&self, frog_id: FrogID) -> Result<Slug> {
+        self.observe(
+            "get_slug_eating_snake_eating_frog",
+            self.inner.get_slug_eating_snake_eating_frog(frog_id),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<S: FrogService> FrogService for Metrics<S> {
+    async fn get_frog_eating_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Frog> {
+        self.observe(
+            "get_frog_eating_slug_eating_snake",
+            self.inner.get_frog_eating_slug_eating_snake(snake_id),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use domain::{EntityID, Error, MockSnakeRepository, MockSnakeService};
+
+    use super::*;
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repository() {
+        let mut inner = MockSnakeRepository::new();
+        inner.expect_get_snake().returning(|_| Ok(snake()));
+        inner
+            .expect_delete_snake()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        let registry = Arc::new(Registry::new());
+        let repository = Metrics::new(inner, Arc::clone(&registry), "repository");
+        assert_eq!(repository.get_snake(snake().id).await.unwrap(), snake());
+        assert_eq!(repository.get_snake(snake().id).await.unwrap(), snake());
+        assert!(matches!(
+            repository.delete_snake(snake().id).await,
+            Err(Error::NotFound(EntityID::Snake(_)))
+        ));
+        assert_eq!(registry.calls("repository", "get_snake"), 2);
+        assert_eq!(registry.errors("repository", "get_snake"), 0);
+        assert_eq!(registry.calls("repository", "delete_snake"), 1);
+        assert_eq!(registry.errors("repository", "delete_snake"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_layers_are_apart() {
+        let mut inner = MockSnakeService::new();
+        inner
+            .expect_get_snake_eating_frog_eating_slug()
+            .returning(|_| Ok(snake()));
+        let registry = Arc::new(Registry::new());
+        let service = Metrics::new(inner, Arc::clone(&registry), "service");
+        service
+            .get_snake_eating_frog_eating_slug(SlugID::new(1).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            registry.calls("service", "get_snake_eating_frog_eating_slug"),
+            1
+        );
+        assert_eq!(
+            registry.calls("repository", "get_snake_eating_frog_eating_slug"),
+            0
+        );
+    }
+}
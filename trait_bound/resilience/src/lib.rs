@@ -0,0 +1,7 @@
+mod breaker;
+mod retry;
+
+pub use breaker::{
+    Breaker, BreakerConfig, BreakerStats, CircuitBreaker, CircuitOpen, CircuitState,
+};
+pub use retry::{is_retryable, Retry, RetryPolicy};
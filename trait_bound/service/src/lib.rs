@@ -1,9 +1,9 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use domain::{
-    Frog, FrogID, FrogService, FrogUseCase, Slug, SlugID, SlugService, SlugUseCase, Snake, SnakeID,
-    SnakeService, SnakeUseCase,
+    Frog, FrogID, FrogService, FrogUseCase, Result, ResultExt, Slug, SlugID, SlugService,
+    SlugUseCase, Snake, SnakeID, SnakeService, SnakeUseCase,
 };
+use tracing::instrument;
 
 pub struct Service<T> {
     use_case: T,
@@ -17,34 +17,83 @@ impl<T> Service<T> {
 
 #[async_trait]
 impl<T: SnakeUseCase + FrogUseCase> SnakeService for Service<T> {
+    #[instrument(skip_all, fields(slug = %slug_id))]
     async fn get_snake_eating_frog_eating_slug(&self, slug_id: SlugID) -> Result<Snake> {
-        let frog = self.use_case.get_frog_eating_slug(slug_id).await.unwrap();
-        self.use_case.get_snake_eating_frog(frog.id).await
+        let frog = self
+            .use_case
+            .get_frog_eating_slug(slug_id)
+            .await
+            .with_context(|| format!("getting frog eating slug {slug_id}"))?;
+        self.use_case
+            .get_snake_eating_frog(frog.id)
+            .await
+            .with_context(|| format!("getting snake eating frog {}", frog.id))
     }
 }
 
 #[async_trait]
 impl<T: SlugUseCase + SnakeUseCase> SlugService for Service<T> {
+    #[instrument(skip_all, fields(frog = %frog_id))]
     async fn get_slug_eating_snake_eating_frog(&self, frog_id: FrogID) -> Result<Slug> {
-        let snake = self.use_case.get_snake_eating_frog(frog_id).await.unwrap();
-        self.use_case.get_slug_eating_snake(snake.id).await
+        let snake = self
+            .use_case
+            .get_snake_eating_frog(frog_id)
+            .await
+            .with_context(|| format!("getting snake eating frog {frog_id}"))?;
+        self.use_case
+            .get_slug_eating_snake(snake.id)
+            .await
+            .with_context(|| format!("getting slug eating snake {}", snake.id))
     }
 }
 
 #[async_trait]
 impl<T: FrogUseCase + SlugUseCase> FrogService for Service<T> {
+    #[instrument(skip_all, fields(snake = %snake_id))]
     async fn get_frog_eating_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Frog> {
-        let slug = self.use_case.get_slug_eating_snake(snake_id).await.unwrap();
-        self.use_case.get_frog_eating_slug(slug.id).await
+        let slug = self
+            .use_case
+            .get_slug_eating_snake(snake_id)
+            .await
+            .with_context(|| format!("getting slug eating snake {snake_id}"))?;
+        self.use_case
+            .get_frog_eating_slug(slug.id)
+            .await
+            .with_context(|| format!("getting frog eating slug {}", slug.id))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::io;
+
     use super::*;
-    use anyhow::Result;
     use async_trait::async_trait;
+    use domain::{EntityID, Error};
+    use domain_fakes::{fixtures, FakeRepository};
     use mockall::mock;
+    use use_case::UseCase;
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
 
     mock! {
         SnakeFrogUseCase {}
@@ -52,11 +101,15 @@ mod test {
         impl SnakeUseCase for SnakeFrogUseCase {
             async fn get_snake(&self, id: SnakeID) -> Result<Snake>;
             async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake>;
+            async fn register_snake(&self, snake: Snake) -> Result<Snake>;
+            async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog>;
         }
         #[async_trait]
         impl FrogUseCase for SnakeFrogUseCase {
             async fn get_frog(&self, id: FrogID) -> Result<Frog>;
             async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog>;
+            async fn register_frog(&self, frog: Frog) -> Result<Frog>;
+            async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug>;
         }
     }
 
@@ -65,14 +118,56 @@ mod test {
         let mut use_case = MockSnakeFrogUseCase::new();
         use_case
             .expect_get_frog_eating_slug()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|_| Ok(frog()));
         use_case
             .expect_get_snake_eating_frog()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|_| Ok(snake()));
         let service = Service::new(use_case);
-        _ = service
-            .get_snake_eating_frog_eating_slug(SlugID::default())
-            .await;
+        assert_eq!(
+            service
+                .get_snake_eating_frog_eating_slug(slug().id)
+                .await
+                .unwrap(),
+            snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_snake_eating_frog_eating_slug_first_hop_error() {
+        let mut use_case = MockSnakeFrogUseCase::new();
+        use_case
+            .expect_get_frog_eating_slug()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        use_case.expect_get_snake_eating_frog().never();
+        let service = Service::new(use_case);
+        let err = service
+            .get_snake_eating_frog_eating_slug(slug().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting frog eating slug 2");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Slug(id)) if *id == slug().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_snake_eating_frog_eating_slug_second_hop_error() {
+        let mut use_case = MockSnakeFrogUseCase::new();
+        use_case
+            .expect_get_frog_eating_slug()
+            .returning(|_| Ok(frog()));
+        use_case.expect_get_snake_eating_frog().returning(|_| {
+            Err(Error::unavailable(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            )))
+        });
+        let service = Service::new(use_case);
+        let err = service
+            .get_snake_eating_frog_eating_slug(slug().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting snake eating frog 3");
+        assert!(
+            matches!(err.root(), Error::Unavailable(source) if source.to_string() == "connection refused")
+        );
     }
 
     mock! {
@@ -81,11 +176,15 @@ mod test {
         impl SlugUseCase for SlugSnakeUseCase {
             async fn get_slug(&self, id: SlugID) -> Result<Slug>;
             async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug>;
+            async fn register_slug(&self, slug: Slug) -> Result<Slug>;
+            async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake>;
         }
         #[async_trait]
         impl SnakeUseCase for SlugSnakeUseCase {
             async fn get_snake(&self, id: SnakeID) -> Result<Snake>;
             async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake>;
+            async fn register_snake(&self, snake: Snake) -> Result<Snake>;
+            async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog>;
         }
     }
 
@@ -94,14 +193,56 @@ mod test {
         let mut use_case = MockSlugSnakeUseCase::new();
         use_case
             .expect_get_slug_eating_snake()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|_| Ok(slug()));
+        use_case
+            .expect_get_snake_eating_frog()
+            .returning(|_| Ok(snake()));
+        let service = Service::new(use_case);
+        assert_eq!(
+            service
+                .get_slug_eating_snake_eating_frog(frog().id)
+                .await
+                .unwrap(),
+            slug()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_slug_eating_snake_eating_frog_first_hop_error() {
+        let mut use_case = MockSlugSnakeUseCase::new();
         use_case
             .expect_get_snake_eating_frog()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        use_case.expect_get_slug_eating_snake().never();
         let service = Service::new(use_case);
-        _ = service
-            .get_slug_eating_snake_eating_frog(FrogID::default())
-            .await;
+        let err = service
+            .get_slug_eating_snake_eating_frog(frog().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting snake eating frog 3");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Frog(id)) if *id == frog().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_slug_eating_snake_eating_frog_second_hop_error() {
+        let mut use_case = MockSlugSnakeUseCase::new();
+        use_case
+            .expect_get_snake_eating_frog()
+            .returning(|_| Ok(snake()));
+        use_case.expect_get_slug_eating_snake().returning(|_| {
+            Err(Error::unavailable(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            )))
+        });
+        let service = Service::new(use_case);
+        let err = service
+            .get_slug_eating_snake_eating_frog(frog().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting slug eating snake 1");
+        assert!(
+            matches!(err.root(), Error::Unavailable(source) if source.to_string() == "connection refused")
+        );
     }
 
     mock! {
@@ -110,11 +251,15 @@ mod test {
         impl FrogUseCase for FrogSlugUseCase {
             async fn get_frog(&self, id: FrogID) -> Result<Frog>;
             async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog>;
+            async fn register_frog(&self, frog: Frog) -> Result<Frog>;
+            async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug>;
         }
         #[async_trait]
         impl SlugUseCase for FrogSlugUseCase {
             async fn get_slug(&self, id: SlugID) -> Result<Slug>;
             async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug>;
+            async fn register_slug(&self, slug: Slug) -> Result<Slug>;
+            async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake>;
         }
     }
 
@@ -123,13 +268,98 @@ mod test {
         let mut use_case = MockFrogSlugUseCase::new();
         use_case
             .expect_get_frog_eating_slug()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|_| Ok(frog()));
         use_case
             .expect_get_slug_eating_snake()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|_| Ok(slug()));
         let service = Service::new(use_case);
-        _ = service
-            .get_frog_eating_slug_eating_snake(SnakeID::default())
-            .await;
+        assert_eq!(
+            service
+                .get_frog_eating_slug_eating_snake(snake().id)
+                .await
+                .unwrap(),
+            frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug_eating_snake_first_hop_error() {
+        let mut use_case = MockFrogSlugUseCase::new();
+        use_case
+            .expect_get_slug_eating_snake()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        use_case.expect_get_frog_eating_slug().never();
+        let service = Service::new(use_case);
+        let err = service
+            .get_frog_eating_slug_eating_snake(snake().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting slug eating snake 1");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Snake(id)) if *id == snake().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug_eating_snake_second_hop_error() {
+        let mut use_case = MockFrogSlugUseCase::new();
+        use_case
+            .expect_get_slug_eating_snake()
+            .returning(|_| Ok(slug()));
+        use_case.expect_get_frog_eating_slug().returning(|_| {
+            Err(Error::unavailable(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            )))
+        });
+        let service = Service::new(use_case);
+        let err = service
+            .get_frog_eating_slug_eating_snake(snake().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting frog eating slug 2");
+        assert!(
+            matches!(err.root(), Error::Unavailable(source) if source.to_string() == "connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_eating_eating_stored() {
+        let service = Service::new(UseCase::new(FakeRepository::seeded()));
+        assert_eq!(
+            service
+                .get_snake_eating_frog_eating_slug(fixtures::slug().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        assert_eq!(
+            service
+                .get_slug_eating_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::slug()
+        );
+        assert_eq!(
+            service
+                .get_frog_eating_slug_eating_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug_eating_snake_broken_chain() {
+        // Snake 1 is eaten by slug 2, which is not stored.
+        let repository = FakeRepository::new()
+            .with_snake(fixtures::snake())
+            .with_frog(fixtures::frog());
+        let service = Service::new(UseCase::new(repository));
+        let err = service
+            .get_frog_eating_slug_eating_snake(fixtures::snake().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting slug eating snake 1");
+        assert!(
+            matches!(err.root(), Error::NotFound(EntityID::Slug(id)) if *id == fixtures::slug().id)
+        );
     }
 }
@@ -1,9 +1,10 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use domain::{
-    Frog, FrogID, FrogRepository, FrogUseCase, Slug, SlugID, SlugRepository, SlugUseCase, Snake,
-    SnakeID, SnakeRepository, SnakeUseCase,
+    FoodChainRepository, FoodChainUseCase, Frog, FrogID, FrogRepository, FrogUseCase, Result,
+    ResultExt, Slug, SlugID, SlugRepository, SlugUseCase, Snake, SnakeID, SnakeRepository,
+    SnakeUseCase,
 };
+use tracing::instrument;
 
 pub struct UseCase<T> {
     repository: T,
@@ -16,149 +17,751 @@ impl<T> UseCase<T> {
 }
 
 #[async_trait]
-impl<T: SnakeRepository + FrogRepository> SnakeUseCase for UseCase<T> {
+impl<T: SnakeRepository + SlugRepository + FrogRepository> SnakeUseCase for UseCase<T> {
+    #[instrument(skip_all, fields(snake = %id))]
     async fn get_snake(&self, id: SnakeID) -> Result<Snake> {
         self.repository.get_snake(id).await
     }
 
+    #[instrument(skip_all, fields(frog = %frog_id))]
     async fn get_snake_eating_frog(&self, frog_id: FrogID) -> Result<Snake> {
-        let frog = self.repository.get_frog(frog_id).await.unwrap();
-        self.repository.get_snake(frog.eaten_by).await
+        let frog = self
+            .repository
+            .get_frog(frog_id)
+            .await
+            .with_context(|| format!("getting frog {frog_id}"))?;
+        self.repository
+            .get_snake(frog.eaten_by)
+            .await
+            .with_context(|| format!("getting snake {} eating frog {frog_id}", frog.eaten_by))
+    }
+
+    #[instrument(skip_all, fields(snake = %snake.id))]
+    async fn register_snake(&self, snake: Snake) -> Result<Snake> {
+        self.repository
+            .get_slug(snake.eaten_by)
+            .await
+            .with_context(|| {
+                format!("getting slug {} eating snake {}", snake.eaten_by, snake.id)
+            })?;
+        self.repository
+            .insert_snake(snake.clone())
+            .await
+            .with_context(|| format!("registering snake {}", snake.id))?;
+        Ok(snake)
+    }
+
+    #[instrument(skip_all, fields(frog = %frog_id, snake = %snake_id))]
+    async fn set_snake_eating_frog(&self, frog_id: FrogID, snake_id: SnakeID) -> Result<Frog> {
+        let snake = self
+            .repository
+            .get_snake(snake_id)
+            .await
+            .with_context(|| format!("getting snake {snake_id}"))?;
+        let mut frog = self
+            .repository
+            .get_frog(frog_id)
+            .await
+            .with_context(|| format!("getting frog {frog_id}"))?;
+        frog.eaten_by = snake.id;
+        self.repository
+            .update_frog(frog.clone())
+            .await
+            .with_context(|| format!("updating frog {frog_id}"))?;
+        Ok(frog)
     }
 }
 
 #[async_trait]
-impl<T: SlugRepository + SnakeRepository> SlugUseCase for UseCase<T> {
+impl<T: SnakeRepository + SlugRepository + FrogRepository> SlugUseCase for UseCase<T> {
+    #[instrument(skip_all, fields(slug = %id))]
     async fn get_slug(&self, id: SlugID) -> Result<Slug> {
         self.repository.get_slug(id).await
     }
 
+    #[instrument(skip_all, fields(snake = %snake_id))]
     async fn get_slug_eating_snake(&self, snake_id: SnakeID) -> Result<Slug> {
-        let snake = self.repository.get_snake(snake_id).await.unwrap();
-        self.repository.get_slug(snake.eaten_by).await
+        let snake = self
+            .repository
+            .get_snake(snake_id)
+            .await
+            .with_context(|| format!("getting snake {snake_id}"))?;
+        self.repository
+            .get_slug(snake.eaten_by)
+            .await
+            .with_context(|| format!("getting slug {} eating snake {snake_id}", snake.eaten_by))
+    }
+
+    #[instrument(skip_all, fields(slug = %slug.id))]
+    async fn register_slug(&self, slug: Slug) -> Result<Slug> {
+        self.repository
+            .get_frog(slug.eaten_by)
+            .await
+            .with_context(|| format!("getting frog {} eating slug {}", slug.eaten_by, slug.id))?;
+        self.repository
+            .insert_slug(slug.clone())
+            .await
+            .with_context(|| format!("registering slug {}", slug.id))?;
+        Ok(slug)
+    }
+
+    #[instrument(skip_all, fields(snake = %snake_id, slug = %slug_id))]
+    async fn set_slug_eating_snake(&self, snake_id: SnakeID, slug_id: SlugID) -> Result<Snake> {
+        let slug = self
+            .repository
+            .get_slug(slug_id)
+            .await
+            .with_context(|| format!("getting slug {slug_id}"))?;
+        let mut snake = self
+            .repository
+            .get_snake(snake_id)
+            .await
+            .with_context(|| format!("getting snake {snake_id}"))?;
+        snake.eaten_by = slug.id;
+        self.repository
+            .update_snake(snake.clone())
+            .await
+            .with_context(|| format!("updating snake {snake_id}"))?;
+        Ok(snake)
     }
 }
 
 #[async_trait]
-impl<T: FrogRepository + SlugRepository> FrogUseCase for UseCase<T> {
-    async fn get_frog(&self, id: FrogID) -> anyhow::Result<domain::Frog> {
+impl<T: SnakeRepository + SlugRepository + FrogRepository> FrogUseCase for UseCase<T> {
+    #[instrument(skip_all, fields(frog = %id))]
+    async fn get_frog(&self, id: FrogID) -> Result<Frog> {
         self.repository.get_frog(id).await
     }
+    #[instrument(skip_all, fields(slug = %slug_id))]
     async fn get_frog_eating_slug(&self, slug_id: SlugID) -> Result<Frog> {
-        let slug = self.repository.get_slug(slug_id).await.unwrap();
-        self.repository.get_frog(slug.eaten_by).await
+        let slug = self
+            .repository
+            .get_slug(slug_id)
+            .await
+            .with_context(|| format!("getting slug {slug_id}"))?;
+        self.repository
+            .get_frog(slug.eaten_by)
+            .await
+            .with_context(|| format!("getting frog {} eating slug {slug_id}", slug.eaten_by))
+    }
+
+    #[instrument(skip_all, fields(frog = %frog.id))]
+    async fn register_frog(&self, frog: Frog) -> Result<Frog> {
+        self.repository
+            .get_snake(frog.eaten_by)
+            .await
+            .with_context(|| format!("getting snake {} eating frog {}", frog.eaten_by, frog.id))?;
+        self.repository
+            .insert_frog(frog.clone())
+            .await
+            .with_context(|| format!("registering frog {}", frog.id))?;
+        Ok(frog)
+    }
+
+    #[instrument(skip_all, fields(slug = %slug_id, frog = %frog_id))]
+    async fn set_frog_eating_slug(&self, slug_id: SlugID, frog_id: FrogID) -> Result<Slug> {
+        let frog = self
+            .repository
+            .get_frog(frog_id)
+            .await
+            .with_context(|| format!("getting frog {frog_id}"))?;
+        let mut slug = self
+            .repository
+            .get_slug(slug_id)
+            .await
+            .with_context(|| format!("getting slug {slug_id}"))?;
+        slug.eaten_by = frog.id;
+        self.repository
+            .update_slug(slug.clone())
+            .await
+            .with_context(|| format!("updating slug {slug_id}"))?;
+        Ok(slug)
+    }
+}
+
+#[async_trait]
+impl<T: SnakeRepository + SlugRepository + FrogRepository + FoodChainRepository> FoodChainUseCase
+    for UseCase<T>
+{
+    #[instrument(skip_all, fields(snakes = snakes.len(), slugs = slugs.len(), frogs = frogs.len()))]
+    async fn register_food_chain(
+        &self,
+        snakes: Vec<Snake>,
+        slugs: Vec<Slug>,
+        frogs: Vec<Frog>,
+    ) -> Result<()> {
+        // Predators among the animals are stored along with them.
+        for snake in &snakes {
+            if !slugs.iter().any(|slug| slug.id == snake.eaten_by) {
+                self.repository
+                    .get_slug(snake.eaten_by)
+                    .await
+                    .with_context(|| {
+                        format!("getting slug {} eating snake {}", snake.eaten_by, snake.id)
+                    })?;
+            }
+        }
+        for slug in &slugs {
+            if !frogs.iter().any(|frog| frog.id == slug.eaten_by) {
+                self.repository
+                    .get_frog(slug.eaten_by)
+                    .await
+                    .with_context(|| {
+                        format!("getting frog {} eating slug {}", slug.eaten_by, slug.id)
+                    })?;
+            }
+        }
+        for frog in &frogs {
+            if !snakes.iter().any(|snake| snake.id == frog.eaten_by) {
+                self.repository
+                    .get_snake(frog.eaten_by)
+                    .await
+                    .with_context(|| {
+                        format!("getting snake {} eating frog {}", frog.eaten_by, frog.id)
+                    })?;
+            }
+        }
+        self.repository
+            .insert_food_chain(snakes, slugs, frogs)
+            .await
+            .context("registering food chain")
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::io;
+
     use super::*;
-    use anyhow::Result;
     use async_trait::async_trait;
-    use mockall::mock;
+    use domain::{EntityID, Error};
+    use domain_fakes::{fixtures, FakeRepository};
+    use mockall::{mock, predicate::eq};
+
+    fn snake() -> Snake {
+        Snake {
+            id: SnakeID::new(1).unwrap(),
+            eaten_by: SlugID::new(2).unwrap(),
+        }
+    }
+
+    fn slug() -> Slug {
+        Slug {
+            id: SlugID::new(2).unwrap(),
+            eaten_by: FrogID::new(3).unwrap(),
+        }
+    }
+
+    fn frog() -> Frog {
+        Frog {
+            id: FrogID::new(3).unwrap(),
+            eaten_by: SnakeID::new(1).unwrap(),
+        }
+    }
 
     mock! {
-        SnakeFrogRepository {}
+        Repository {}
         #[async_trait]
-        impl SnakeRepository for SnakeFrogRepository {
+        impl SnakeRepository for Repository {
             async fn get_snake(&self, id: SnakeID) -> Result<Snake>;
+            async fn insert_snake(&self, snake: Snake) -> Result<()>;
+            async fn update_snake(&self, snake: Snake) -> Result<()>;
+            async fn upsert_snake(&self, snake: Snake) -> Result<()>;
+            async fn delete_snake(&self, id: SnakeID) -> Result<()>;
+        }
+        #[async_trait]
+        impl SlugRepository for Repository {
+            async fn get_slug(&self, id: SlugID) -> Result<Slug>;
+            async fn insert_slug(&self, slug: Slug) -> Result<()>;
+            async fn update_slug(&self, slug: Slug) -> Result<()>;
+            async fn upsert_slug(&self, slug: Slug) -> Result<()>;
+            async fn delete_slug(&self, id: SlugID) -> Result<()>;
         }
         #[async_trait]
-        impl FrogRepository for SnakeFrogRepository {
+        impl FrogRepository for Repository {
             async fn get_frog(&self, id: FrogID) -> Result<Frog>;
+            async fn insert_frog(&self, frog: Frog) -> Result<()>;
+            async fn update_frog(&self, frog: Frog) -> Result<()>;
+            async fn upsert_frog(&self, frog: Frog) -> Result<()>;
+            async fn delete_frog(&self, id: FrogID) -> Result<()>;
+        }
+        #[async_trait]
+        impl FoodChainRepository for Repository {
+            async fn insert_food_chain(
+                &self,
+                snakes: Vec<Snake>,
+                slugs: Vec<Slug>,
+                frogs: Vec<Frog>,
+            ) -> Result<()>;
         }
     }
 
     #[tokio::test]
     async fn test_get_snake() {
-        let mut repository = MockSnakeFrogRepository::new();
+        let mut repository = MockRepository::new();
+        repository.expect_get_snake().returning(|_| Ok(snake()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(use_case.get_snake(snake().id).await.unwrap(), snake());
+    }
+
+    #[tokio::test]
+    async fn test_get_snake_eating_frog() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_snake().returning(|_| Ok(snake()));
+        repository.expect_get_frog().returning(|_| Ok(frog()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(
+            use_case.get_snake_eating_frog(frog().id).await.unwrap(),
+            snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_snake_eating_frog_first_hop_error() {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_get_frog()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_get_snake().never();
+        let use_case = UseCase::new(repository);
+        let err = use_case.get_snake_eating_frog(frog().id).await.unwrap_err();
+        assert_eq!(err.to_string(), "getting frog 3");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Frog(id)) if *id == frog().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_snake_eating_frog_second_hop_error() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_frog().returning(|_| Ok(frog()));
+        repository.expect_get_snake().returning(|_| {
+            Err(Error::unavailable(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            )))
+        });
+        let use_case = UseCase::new(repository);
+        let err = use_case.get_snake_eating_frog(frog().id).await.unwrap_err();
+        assert_eq!(err.to_string(), "getting snake 1 eating frog 3");
+        assert!(
+            matches!(err.root(), Error::Unavailable(source) if source.to_string() == "connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        repository
+            .expect_insert_snake()
+            .with(eq(snake()))
+            .returning(|_| Ok(()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(use_case.register_snake(snake()).await.unwrap(), snake());
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_unknown_slug() {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_get_slug()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_insert_snake().never();
+        let use_case = UseCase::new(repository);
+        let err = use_case.register_snake(snake()).await.unwrap_err();
+        assert_eq!(err.to_string(), "getting slug 2 eating snake 1");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Slug(id)) if *id == slug().id));
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_snake().returning(|_| Ok(snake()));
+        repository.expect_get_frog().returning(|_| Ok(frog()));
+        repository
+            .expect_update_frog()
+            .withf(|frog| frog.eaten_by == snake().id)
+            .returning(|_| Ok(()));
+        let use_case = UseCase::new(repository);
+        let frog = use_case
+            .set_snake_eating_frog(frog().id, snake().id)
+            .await
+            .unwrap();
+        assert_eq!(frog.eaten_by, snake().id);
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_unknown_snake() {
+        let mut repository = MockRepository::new();
         repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_update_frog().never();
         let use_case = UseCase::new(repository);
-        _ = use_case.get_snake(SnakeID::default()).await;
+        let err = use_case
+            .set_snake_eating_frog(frog().id, snake().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Snake(id)) if *id == snake().id));
     }
 
     #[tokio::test]
-    async fn test_get_snake_eating_frog() {
-        let mut repository = MockSnakeFrogRepository::new();
+    async fn test_get_slug() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(use_case.get_slug(slug().id).await.unwrap(), slug());
+    }
+
+    #[tokio::test]
+    async fn test_get_slug_eating_snake() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        repository.expect_get_snake().returning(|_| Ok(snake()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(
+            use_case.get_slug_eating_snake(snake().id).await.unwrap(),
+            slug()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_slug_eating_snake_first_hop_error() {
+        let mut repository = MockRepository::new();
         repository
             .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_get_slug().never();
+        let use_case = UseCase::new(repository);
+        let err = use_case
+            .get_slug_eating_snake(snake().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting snake 1");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Snake(id)) if *id == snake().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_slug_eating_snake_second_hop_error() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_snake().returning(|_| Ok(snake()));
+        repository.expect_get_slug().returning(|_| {
+            Err(Error::unavailable(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            )))
+        });
+        let use_case = UseCase::new(repository);
+        let err = use_case
+            .get_slug_eating_snake(snake().id)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting slug 2 eating snake 1");
+        assert!(
+            matches!(err.root(), Error::Unavailable(source) if source.to_string() == "connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_slug() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_frog().returning(|_| Ok(frog()));
         repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
+            .expect_insert_slug()
+            .with(eq(slug()))
+            .returning(|_| Ok(()));
         let use_case = UseCase::new(repository);
-        _ = use_case.get_snake_eating_frog(FrogID::default()).await;
+        assert_eq!(use_case.register_slug(slug()).await.unwrap(), slug());
     }
 
-    mock! {
-        SlugSnakeRepository {}
-        #[async_trait]
-        impl SlugRepository for SlugSnakeRepository {
-            async fn get_slug(&self, id: SlugID) -> Result<Slug>;
-        }
-        #[async_trait]
-        impl SnakeRepository for SlugSnakeRepository {
-            async fn get_snake(&self, id: SnakeID) -> Result<Snake>;
-        }
+    #[tokio::test]
+    async fn test_set_slug_eating_snake() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        repository.expect_get_snake().returning(|_| Ok(snake()));
+        repository
+            .expect_update_snake()
+            .withf(|snake| snake.eaten_by == slug().id)
+            .returning(|_| Ok(()));
+        let use_case = UseCase::new(repository);
+        let snake = use_case
+            .set_slug_eating_snake(snake().id, slug().id)
+            .await
+            .unwrap();
+        assert_eq!(snake.eaten_by, slug().id);
     }
 
     #[tokio::test]
-    async fn test_get_slug() {
-        let mut repository = MockSlugSnakeRepository::new();
+    async fn test_set_slug_eating_snake_unknown_slug() {
+        let mut repository = MockRepository::new();
         repository
             .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_update_snake().never();
         let use_case = UseCase::new(repository);
-        _ = use_case.get_slug(SlugID::default()).await;
+        let err = use_case
+            .set_slug_eating_snake(snake().id, slug().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Slug(id)) if *id == slug().id));
     }
 
     #[tokio::test]
-    async fn test_get_slug_eating_snake() {
-        let mut repository = MockSlugSnakeRepository::new();
+    async fn test_get_frog() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_frog().returning(|_| Ok(frog()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(use_case.get_frog(frog().id).await.unwrap(), frog());
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_frog().returning(|_| Ok(frog()));
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        let use_case = UseCase::new(repository);
+        assert_eq!(
+            use_case.get_frog_eating_slug(slug().id).await.unwrap(),
+            frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug_first_hop_error() {
+        let mut repository = MockRepository::new();
         repository
             .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_get_frog().never();
+        let use_case = UseCase::new(repository);
+        let err = use_case.get_frog_eating_slug(slug().id).await.unwrap_err();
+        assert_eq!(err.to_string(), "getting slug 2");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Slug(id)) if *id == slug().id));
+    }
+
+    #[tokio::test]
+    async fn test_get_frog_eating_slug_second_hop_error() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        repository.expect_get_frog().returning(|_| {
+            Err(Error::unavailable(io::Error::from(
+                io::ErrorKind::ConnectionRefused,
+            )))
+        });
+        let use_case = UseCase::new(repository);
+        let err = use_case.get_frog_eating_slug(slug().id).await.unwrap_err();
+        assert_eq!(err.to_string(), "getting frog 3 eating slug 2");
+        assert!(
+            matches!(err.root(), Error::Unavailable(source) if source.to_string() == "connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_frog() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_snake().returning(|_| Ok(snake()));
         repository
-            .expect_get_snake()
-            .returning(|_| Ok(Snake::default()));
+            .expect_insert_frog()
+            .with(eq(frog()))
+            .returning(|_| Ok(()));
         let use_case = UseCase::new(repository);
-        _ = use_case.get_slug_eating_snake(SnakeID::default()).await;
+        assert_eq!(use_case.register_frog(frog()).await.unwrap(), frog());
     }
 
-    mock! {
-        FrogSlugRepository {}
-        #[async_trait]
-        impl FrogRepository for FrogSlugRepository {
-            async fn get_frog(&self, id: FrogID) -> Result<Frog>;
-        }
-        #[async_trait]
-        impl SlugRepository for FrogSlugRepository {
-            async fn get_slug(&self, id: SlugID) -> Result<Slug>;
-        }
+    #[tokio::test]
+    async fn test_set_frog_eating_slug() {
+        let mut repository = MockRepository::new();
+        repository.expect_get_frog().returning(|_| Ok(frog()));
+        repository.expect_get_slug().returning(|_| Ok(slug()));
+        repository
+            .expect_update_slug()
+            .withf(|slug| slug.eaten_by == frog().id)
+            .returning(|_| Ok(()));
+        let use_case = UseCase::new(repository);
+        let slug = use_case
+            .set_frog_eating_slug(slug().id, frog().id)
+            .await
+            .unwrap();
+        assert_eq!(slug.eaten_by, frog().id);
     }
 
     #[tokio::test]
-    async fn test_get_frog() {
-        let mut repository = MockFrogSlugRepository::new();
+    async fn test_set_frog_eating_slug_unknown_frog() {
+        let mut repository = MockRepository::new();
         repository
             .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_update_slug().never();
         let use_case = UseCase::new(repository);
-        _ = use_case.get_frog(FrogID::default()).await;
+        let err = use_case
+            .set_frog_eating_slug(slug().id, frog().id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Frog(id)) if *id == frog().id));
     }
 
     #[tokio::test]
-    async fn test_get_frog_eating_slug() {
-        let mut repository = MockFrogSlugRepository::new();
+    async fn test_get_eating_stored() {
+        let use_case = UseCase::new(FakeRepository::seeded());
+        assert_eq!(
+            use_case
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        assert_eq!(
+            use_case
+                .get_slug_eating_snake(fixtures::snake().id)
+                .await
+                .unwrap(),
+            fixtures::slug()
+        );
+        assert_eq!(
+            use_case
+                .get_frog_eating_slug(fixtures::slug().id)
+                .await
+                .unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_stored() {
+        let use_case = UseCase::new(FakeRepository::seeded());
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..snake()
+        };
+        use_case.register_snake(snake.clone()).await.unwrap();
+        use_case
+            .set_snake_eating_frog(fixtures::frog().id, snake.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            snake
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_snake_eating_frog_unknown_snake_stored() {
+        let use_case = UseCase::new(FakeRepository::seeded());
+        let err = use_case
+            .set_snake_eating_frog(fixtures::frog().id, SnakeID::new(4).unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Snake(_))));
+        assert_eq!(
+            use_case.get_frog(fixtures::frog().id).await.unwrap(),
+            fixtures::frog()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_snake_taken_stored() {
+        let use_case = UseCase::new(
+            FakeRepository::seeded()
+                .with_slug(Slug {
+                    id: SlugID::new(4).unwrap(),
+                    eaten_by: FrogID::new(4).unwrap(),
+                })
+                .with_frog(Frog {
+                    id: FrogID::new(4).unwrap(),
+                    eaten_by: SnakeID::new(4).unwrap(),
+                }),
+        );
+        let err = use_case
+            .register_snake(Snake {
+                eaten_by: SlugID::new(4).unwrap(),
+                ..snake()
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.root(), Error::Conflict(EntityID::Snake(id)) if *id == fixtures::snake().id)
+        );
+        assert_eq!(
+            use_case.get_snake(fixtures::snake().id).await.unwrap(),
+            fixtures::snake()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain() {
+        let mut repository = MockRepository::new();
+        // Every animal is eaten by another of them.
+        repository.expect_get_snake().never();
+        repository.expect_get_slug().never();
+        repository.expect_get_frog().never();
         repository
-            .expect_get_frog()
-            .returning(|_| Ok(Frog::default()));
+            .expect_insert_food_chain()
+            .with(eq(vec![snake()]), eq(vec![slug()]), eq(vec![frog()]))
+            .returning(|_, _, _| Ok(()));
+        let use_case = UseCase::new(repository);
+        use_case
+            .register_food_chain(vec![snake()], vec![slug()], vec![frog()])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_unknown_frog() {
+        let mut repository = MockRepository::new();
         repository
-            .expect_get_slug()
-            .returning(|_| Ok(Slug::default()));
+            .expect_get_frog()
+            .returning(|id| Err(Error::NotFound(id.into())));
+        repository.expect_insert_food_chain().never();
         let use_case = UseCase::new(repository);
-        _ = use_case.get_frog_eating_slug(SlugID::default()).await;
+        let err = use_case
+            .register_food_chain(vec![snake()], vec![slug()], vec![])
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "getting frog 3 eating slug 2");
+        assert!(matches!(err.root(), Error::NotFound(EntityID::Frog(id)) if *id == frog().id));
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_stored() {
+        let use_case = UseCase::new(FakeRepository::new());
+        use_case
+            .register_food_chain(
+                vec![fixtures::snake()],
+                vec![fixtures::slug()],
+                vec![fixtures::frog()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            use_case
+                .get_snake_eating_frog(fixtures::frog().id)
+                .await
+                .unwrap(),
+            fixtures::snake()
+        );
+        // Later animals may be eaten by ones already registered.
+        let snake = Snake {
+            id: SnakeID::new(4).unwrap(),
+            ..fixtures::snake()
+        };
+        use_case
+            .register_food_chain(vec![snake.clone()], vec![], vec![])
+            .await
+            .unwrap();
+        assert_eq!(use_case.get_snake(snake.id).await.unwrap(), snake);
+    }
+
+    #[tokio::test]
+    async fn test_register_food_chain_unknown_predator_stored() {
+        let use_case = UseCase::new(FakeRepository::new());
+        let err = use_case
+            .register_food_chain(vec![fixtures::snake()], vec![fixtures::slug()], vec![])
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.root(), Error::NotFound(EntityID::Frog(id)) if *id == fixtures::frog().id)
+        );
+        let err = use_case.get_snake(fixtures::snake().id).await.unwrap_err();
+        assert!(matches!(err, Error::NotFound(EntityID::Snake(_))));
     }
 }